@@ -1,4 +1,8 @@
 fn main() {
+    // c sources and vitasdk libs are only for vita, tests run on the host
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("vita") {
+        return;
+    }
     println!("cargo:rustc-link-search=all=./c");
     println!("cargo:rustc-link-search=all=/usr/local/vitasdk/arm-vita-eabi/lib");
     // vita2d
@@ -21,6 +25,8 @@ fn main() {
     println!("cargo:rustc-link-lib=static=SceVshBridge_stub");
     println!("cargo:rustc-link-lib=static=SceRegistryMgr_stub");
     println!("cargo:rustc-link-lib=static=SceAppUtil_stub");
+    println!("cargo:rustc-link-lib=static=SceNetCtl_stub");
+    println!("cargo:rustc-link-lib=static=ScePower_stub");
     // sqlite
     println!("cargo:rustc-link-lib=static=sqlite");
     println!("cargo:rustc-link-lib=static=SceSqlite_stub");
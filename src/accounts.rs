@@ -0,0 +1,363 @@
+//! # cloud accounts
+//!
+//! the api keeps the auth data of one account only, tokens of every
+//! logged in account are kept in `ACCOUNTS_PATH`, switching account
+//! replaces the auth data of api with the tokens of selected one
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    sync::{Mutex, OnceLock, RwLock, RwLockReadGuard},
+};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{Api, AuthData, TokenRes},
+    constant::{ACCOUNTS_PATH, GAME_SAVE_CLOUD_DIR},
+    utils::{current_time, write_file_atomic},
+};
+
+static ACCOUNTS: OnceLock<RwLock<Accounts>> = OnceLock::new();
+// refresh token can be used once, serialize refreshes
+static REFRESH_LOCK: Mutex<()> = Mutex::new(());
+
+fn default_root_dir() -> String {
+    GAME_SAVE_CLOUD_DIR.to_string()
+}
+
+pub fn now() -> u64 {
+    (current_time() / 1000) as u64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CloudAccount {
+    /// user name of drive
+    pub name: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    /// unix time in seconds, 0 if unknown
+    #[serde(default)]
+    pub expires_at: u64,
+    /// dir of game save backups
+    #[serde(default = "default_root_dir")]
+    pub root_dir: String,
+}
+
+impl CloudAccount {
+    pub fn new(token_res: &TokenRes, name: &str, now: u64) -> CloudAccount {
+        CloudAccount {
+            name: name.to_string(),
+            access_token: token_res.access_token.clone().unwrap_or_default(),
+            refresh_token: token_res.refresh_token.clone().unwrap_or_default(),
+            expires_at: token_res.expires_in.map_or(0, |secs| now + secs),
+            root_dir: default_root_dir(),
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at > 0 && self.expires_at <= now
+    }
+
+    /// update tokens, keep the refresh token if the new one is none
+    pub fn update_token(&mut self, token_res: &TokenRes, now: u64) {
+        let account = CloudAccount::new(token_res, &self.name, now);
+        self.access_token = account.access_token;
+        if !account.refresh_token.is_empty() {
+            self.refresh_token = account.refresh_token;
+        }
+        self.expires_at = account.expires_at;
+    }
+
+    fn token_res(&self, now: u64) -> TokenRes {
+        TokenRes {
+            access_token: Some(self.access_token.clone()),
+            refresh_token: Some(self.refresh_token.clone()),
+            expires_in: (self.expires_at > 0).then(|| self.expires_at.saturating_sub(now)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Accounts {
+    /// name of account in api
+    pub active: Option<String>,
+    pub accounts: Vec<CloudAccount>,
+}
+
+impl Accounts {
+    fn get() -> &'static RwLock<Accounts> {
+        ACCOUNTS.get_or_init(|| {
+            let accounts = if Path::new(ACCOUNTS_PATH).exists() {
+                Accounts::load().unwrap_or_else(|err| {
+                    error!("load accounts failed: {:?}", err);
+                    Accounts::default()
+                })
+            } else {
+                Accounts::default()
+            };
+            RwLock::new(accounts)
+        })
+    }
+
+    fn load() -> Result<Accounts, Box<dyn Error>> {
+        let buf = fs::read(ACCOUNTS_PATH)?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = Path::new(ACCOUNTS_PATH).parent() {
+            if !parent.exists() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        write_file_atomic(ACCOUNTS_PATH, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn read() -> RwLockReadGuard<'static, Accounts> {
+        Self::get().read().expect("read accounts")
+    }
+
+    fn update<T>(f: impl FnOnce(&mut Accounts) -> T) -> T {
+        let mut accounts = Self::get().write().expect("write accounts");
+        let res = f(&mut accounts);
+        if let Err(err) = accounts.save() {
+            error!("save accounts failed: {:?}", err);
+        }
+        res
+    }
+
+    pub fn get_account(&self, name: &str) -> Option<&CloudAccount> {
+        self.accounts.iter().find(|account| account.name == name)
+    }
+
+    pub fn active_account(&self) -> Option<&CloudAccount> {
+        self.get_account(self.active.as_deref()?)
+    }
+
+    /// add account or update tokens of existing one, and make it active
+    fn upsert(&mut self, account: CloudAccount) {
+        match self.accounts.iter_mut().find(|a| a.name == account.name) {
+            Some(existing) => {
+                existing.access_token = account.access_token;
+                existing.refresh_token = account.refresh_token;
+                existing.expires_at = account.expires_at;
+            }
+            None => self.accounts.push(account.clone()),
+        }
+        self.active = Some(account.name);
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        self.accounts.retain(|account| account.name != name);
+        let is_active = self.active.as_deref() == Some(name);
+        if is_active {
+            self.active = None;
+        }
+        is_active
+    }
+}
+
+/// name of active account
+pub fn active_account_name() -> Option<String> {
+    Accounts::read().active.clone()
+}
+
+pub fn active_account() -> Option<CloudAccount> {
+    Accounts::read().active_account().cloned()
+}
+
+/// # dir of game save backups of active account
+pub fn cloud_save_dir() -> String {
+    Accounts::read()
+        .active_account()
+        .map_or_else(default_root_dir, |account| account.root_dir.clone())
+}
+
+/// # cloud dir of title
+///
+/// `{root}/{title_id} {name}`, the dir is created by upload if not exists
+pub fn title_cloud_dir(title_id: &str, name: &str) -> String {
+    format!("{}/{} {}", cloud_save_dir(), title_id, name)
+        .trim()
+        .to_string()
+}
+
+/// # save account of login
+///
+/// call it before the token is moved to auth data of api
+pub fn save_login_account(token_res: &TokenRes, name: &str) {
+    info!("save account {}", name);
+    Accounts::update(|accounts| accounts.upsert(CloudAccount::new(token_res, name, now())));
+}
+
+/// # switch to account
+///
+/// the token is refreshed first if it has expired
+pub fn switch_account(name: &str) -> Result<(), Box<dyn Error>> {
+    let account = Accounts::read()
+        .get_account(name)
+        .cloned()
+        .ok_or_else(|| format!("账号 {} 不存在", name))?;
+    if account.is_expired(now()) {
+        info!("token of {} has expired, refresh it", name);
+        refresh_account_token(name)?;
+    }
+    let account = Accounts::read()
+        .get_account(name)
+        .cloned()
+        .unwrap_or(account);
+    update_api_auth(&account);
+    info!("switch to account {}", name);
+    Accounts::update(|accounts| accounts.active = Some(account.name));
+    Ok(())
+}
+
+fn update_api_auth(account: &CloudAccount) {
+    let api_type = Api::get_read().api_type;
+    Api::update_auth_data(
+        api_type,
+        Some(AuthData::new(
+            account.token_res(now()),
+            account.name.clone(),
+        )),
+    );
+}
+
+/// # refresh token of account
+///
+/// the api uses the new token if the account is active
+pub fn refresh_account_token(name: &str) -> Result<(), Box<dyn Error>> {
+    let _lock = REFRESH_LOCK.lock();
+    let refresh = Accounts::read()
+        .get_account(name)
+        .map(|account| account.refresh_token.clone())
+        .ok_or_else(|| format!("账号 {} 不存在", name))?;
+    let token_res = refresh_token(&refresh)?;
+    let account = Accounts::update(|accounts| {
+        let account = accounts.accounts.iter_mut().find(|a| a.name == name)?;
+        account.update_token(&token_res, now());
+        Some(account.clone())
+    });
+    if let Some(account) = account {
+        info!(
+            "refresh token of {}, expires at {}",
+            name, account.expires_at
+        );
+        if active_account_name().as_deref() == Some(name) {
+            update_api_auth(&account);
+        }
+    }
+    Ok(())
+}
+
+/// # deactivate account
+///
+/// log out of api, the account is kept to switch back,
+/// the next login adds a new one
+pub fn deactivate_account() {
+    let api_type = Api::get_read().api_type;
+    Api::update_auth_data(api_type, None);
+    Accounts::update(|accounts| accounts.active = None);
+}
+
+/// # log out and remove account
+///
+/// its tokens are removed, log out of api if it is active
+pub fn logout_and_remove_account(name: &str) {
+    if Accounts::update(|accounts| accounts.remove(name)) {
+        let api_type = Api::get_read().api_type;
+        Api::update_auth_data(api_type, None);
+    }
+}
+
+pub fn set_root_dir(name: &str, root_dir: &str) {
+    Accounts::update(|accounts| {
+        if let Some(account) = accounts.accounts.iter_mut().find(|a| a.name == name) {
+            account.root_dir = root_dir.trim_end_matches('/').to_string();
+        }
+    });
+}
+
+/// # refresh token
+///
+/// the refresh url is the token url of device code with refresh grant
+pub fn refresh_token(refresh_token: &str) -> Result<TokenRes, Box<dyn Error>> {
+    if refresh_token.is_empty() {
+        return Err("没有 refresh token，请重新登录".into());
+    }
+    let api_type = Api::get_read().api_type;
+    let url = refresh_token_url(&Api::get_read().get_token_url(""), refresh_token)?;
+    Api::start_fetch_token(&url, api_type)
+}
+
+fn refresh_token_url(token_url: &str, refresh_token: &str) -> Result<String, Box<dyn Error>> {
+    let (base, query) = token_url.split_once('?').unwrap_or((token_url, ""));
+    let mut params: Vec<(String, String)> = serde_urlencoded::from_str(query)?;
+    params.retain(|(key, _)| !matches!(key.as_str(), "grant_type" | "code" | "refresh_token"));
+    params.insert(0, ("grant_type".to_string(), "refresh_token".to_string()));
+    params.insert(1, ("refresh_token".to_string(), refresh_token.to_string()));
+    Ok(format!("{}?{}", base, serde_urlencoded::to_string(params)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{api::TokenRes, constant::GAME_SAVE_CLOUD_DIR};
+
+    use super::{refresh_token_url, Accounts, CloudAccount};
+
+    fn token(access_token: &str, refresh_token: Option<&str>) -> TokenRes {
+        TokenRes {
+            access_token: Some(access_token.to_string()),
+            refresh_token: refresh_token.map(|token| token.to_string()),
+            expires_in: Some(100),
+        }
+    }
+
+    #[test]
+    fn test_accounts() -> Result<(), Box<dyn std::error::Error>> {
+        let mut accounts = Accounts::default();
+        accounts.upsert(CloudAccount::new(&token("a1", Some("r1")), "a", 1000));
+        accounts.upsert(CloudAccount::new(&token("b1", Some("r2")), "b", 1000));
+        assert_eq!(accounts.active.as_deref(), Some("b"));
+        assert_eq!(accounts.accounts.len(), 2);
+
+        // login again keeps root dir
+        accounts.accounts[0].root_dir = "/apps/Backup/a".to_string();
+        accounts.upsert(CloudAccount::new(&token("a2", Some("r3")), "a", 2000));
+        let a = accounts.active_account().unwrap();
+        assert_eq!((a.access_token.as_str(), a.expires_at), ("a2", 2100));
+        assert_eq!(a.root_dir, "/apps/Backup/a");
+        assert!(!a.is_expired(2099) && a.is_expired(2100));
+
+        let mut b = accounts.get_account("b").unwrap().clone();
+        b.update_token(&token("b2", None), 3000);
+        assert_eq!((b.refresh_token.as_str(), b.expires_at), ("r2", 3100));
+
+        assert!(accounts.remove("a"));
+        assert!(!accounts.remove("b"));
+        assert_eq!(accounts.active, None);
+        assert!(accounts.accounts.is_empty());
+
+        let account: CloudAccount =
+            serde_json::from_str(r#"{"name":"c","access_token":"","refresh_token":""}"#)?;
+        assert_eq!(account.root_dir, GAME_SAVE_CLOUD_DIR);
+        assert!(!account.is_expired(u64::MAX));
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh_token_url() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            refresh_token_url(
+                "https://openapi.baidu.com/oauth/2.0/token?grant_type=device_token&code=&client_id=id&client_secret=s",
+                "r/1"
+            )?,
+            "https://openapi.baidu.com/oauth/2.0/token?grant_type=refresh_token&refresh_token=r%2F1&client_id=id&client_secret=s"
+        );
+        Ok(())
+    }
+}
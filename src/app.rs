@@ -2,6 +2,7 @@ use std::time::Instant;
 
 use crate::{
     constant::{BUTTON_HOLDING_DELAY, BUTTON_HOLDING_REPEAT_DELAY},
+    schedule::touch_input,
     tai::{psv_prevent_sleep, unmount_pfs, Titles},
     ui::{
         ui_base::UIBase, ui_cloud::UICloud, ui_desktop::UIDesktop, ui_loading::Loading,
@@ -23,6 +24,43 @@ impl AppData {
     }
 }
 
+/// # button state
+///
+/// filter the raw buttons, repeat if buttons are holding
+pub struct ButtonState {
+    first_active_at: u128,
+    active_at: u128,
+    buttons_pre: u32,
+}
+
+impl ButtonState {
+    pub fn new() -> ButtonState {
+        ButtonState {
+            first_active_at: 0,
+            active_at: 0,
+            buttons_pre: 0,
+        }
+    }
+
+    pub fn update(&mut self, buttons_origins: u32) -> u32 {
+        if self.buttons_pre == 0 && buttons_origins > 0 {
+            self.first_active_at = current_time();
+        }
+        let buttons = if buttons_origins > 0
+            && (self.buttons_pre == 0
+                || (current_time() - self.first_active_at >= BUTTON_HOLDING_DELAY
+                    && current_time() - self.active_at >= BUTTON_HOLDING_REPEAT_DELAY))
+        {
+            self.active_at = current_time();
+            buttons_origins
+        } else {
+            0
+        };
+        self.buttons_pre = buttons_origins;
+        buttons
+    }
+}
+
 pub struct App {
     pub data: AppData,
     pub uis: Vec<Box<dyn UIBase>>,
@@ -75,27 +113,15 @@ impl App {
     }
 
     pub fn present(&mut self) {
-        let mut button_first_active_at = 0;
-        let mut button_active_at = 0;
-        let mut buttons_pre = 0;
+        let mut button_state = ButtonState::new();
         let mut sleep_lock_at = Instant::now();
         'main: loop {
             // get the inputs here
             let buttons_origins = vita2d_ctrl_peek_positive();
-            if buttons_pre == 0 && buttons_origins > 0 {
-                button_first_active_at = current_time();
+            if buttons_origins != 0 {
+                touch_input(buttons_origins);
             }
-            let buttons = if buttons_origins > 0
-                && (buttons_pre == 0
-                    || (current_time() - button_first_active_at >= BUTTON_HOLDING_DELAY
-                        && current_time() - button_active_at >= BUTTON_HOLDING_REPEAT_DELAY))
-            {
-                button_active_at = current_time();
-                buttons_origins
-            } else {
-                0
-            };
-            buttons_pre = buttons_origins;
+            let buttons = button_state.update(buttons_origins);
 
             // if update is forces
             if self.update(buttons) {
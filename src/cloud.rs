@@ -0,0 +1,692 @@
+//! # cloud calls
+//!
+//! calls of api with the token lifecycle of active account,
+//! the token is refreshed ahead of expiry in background,
+//! and a call failed by invalid token is retried once after refreshing
+//!
+//! listings, downloads and uploads are retried by `RetryPolicy` on errors
+//! of network or server, calls inside a retried call are not retried again,
+//! moving, renaming, deleting and creating dirs are not repeatable
+//!
+//! fetched listings are cached, calls changing a dir mark its listing stale
+//!
+//! transfers go by slices and wait for the rate limit of settings for each
+//! slice, a failed sliced transfer is retried as a whole, it does not fall
+//! back to the api, which streams a file in one call and is only used
+//! without an active account
+//!
+//! transferred files are checked by md5 of server and transferred again if
+//! it does not match, the md5 is recorded in the backup manifest,
+//! and a backup is not uploaded if the same md5 is found in cloud
+use std::{error::Error, fmt::Display, fs, time::Duration};
+
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::{
+    accounts::{active_account, cloud_save_dir, now, refresh_account_token},
+    api::{Api, ApiOperates, SaveItem},
+    constant::{
+        BAIDU_QUOTA_URL, CORRUPTED_TEXT, GAME_SAVE_CLOUD_DIR, OFFLINE_TEXT,
+        TOKEN_CHECK_INTERVAL_SECS, TOKEN_REFRESH_AHEAD_SECS,
+    },
+    listing_cache::{
+        cached_listing, cached_title_dir, invalidate_listing, store_listing, store_title_dir,
+        CachedItem,
+    },
+    manifest::{forget_md5, manifest_entry, record_md5},
+    network::is_online,
+    retry::RetryPolicy,
+    settings::Settings,
+    throttle::{throttle, Direction},
+    transfer::{
+        download_parallel, fetch_file_md5, fetch_file_md5s, fetch_file_size, upload_parallel,
+    },
+    utils::{get_file_md5, join_path},
+};
+
+// codes of invalid or expired access token
+const TOKEN_ERROR_CODES: [i64; 3] = [-6, 110, 111];
+// messages of invalid or expired access token, in lowercase without spaces
+const TOKEN_ERRORS: [&str; 4] = [
+    "invalid_token",
+    "expired_token",
+    "accesstokeninvalid",
+    "accesstokenexpired",
+];
+
+// errors of network, in lowercase without spaces
+const NETWORK_ERRORS: [&str; 8] = [
+    "connectionreset",
+    "connectionrefused",
+    "connectionaborted",
+    "connectionfailed",
+    "brokenpipe",
+    "timedout",
+    "dnsfailed",
+    "networkerror",
+];
+// codes of server busy, such as frequency control
+const BUSY_ERROR_CODES: [i64; 1] = [31034];
+// status code of ureq error, such as `https://...: status code 503`
+const STATUS_CODE_PREFIX: &str = "statuscode";
+
+/// # codes of api error
+///
+/// integer values of `errno` and `error_code` in json of error
+fn error_codes(err: &str) -> Vec<i64> {
+    let mut codes = vec![];
+    for key in ["\"errno\"", "\"error_code\""] {
+        for (idx, _) in err.match_indices(key) {
+            let value = match err[idx + key.len()..].trim_start().strip_prefix(':') {
+                Some(value) => value.trim_start(),
+                None => continue,
+            };
+            let end = value
+                .char_indices()
+                .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+                .map_or(value.len(), |(i, _)| i);
+            if let Ok(code) = value[..end].parse() {
+                codes.push(code);
+            }
+        }
+    }
+    codes
+}
+
+/// # is error caused by access token
+pub fn is_token_error(err: &str) -> bool {
+    if error_codes(err)
+        .iter()
+        .any(|code| TOKEN_ERROR_CODES.contains(code))
+    {
+        return true;
+    }
+    let err = err.replace(' ', "").to_lowercase();
+    TOKEN_ERRORS
+        .iter()
+        .any(|token_error| err.contains(token_error))
+}
+
+/// # is failed call worth retrying
+///
+/// only errors of network, server errors and corrupted transfers,
+/// token errors are retried by `with_token` already,
+/// local errors such as file not found fail again
+pub fn is_retryable(err: &str) -> bool {
+    if is_token_error(err) {
+        return false;
+    }
+    let compact = err.replace(' ', "").to_lowercase();
+    NETWORK_ERRORS.iter().any(|error| compact.contains(error))
+        || compact.contains(CORRUPTED_TEXT)
+        || error_codes(err)
+            .iter()
+            .any(|code| BUSY_ERROR_CODES.contains(code))
+        || compact
+            .match_indices(STATUS_CODE_PREFIX)
+            .any(|(idx, _)| compact[idx + STATUS_CODE_PREFIX.len()..].starts_with('5'))
+}
+
+fn retry<T>(
+    name: &str,
+    mut f: impl FnMut(u32) -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    RetryPolicy::default().run(
+        name,
+        |attempt| {
+            if !is_online() {
+                return Err(OFFLINE_TEXT.into());
+            }
+            f(attempt)
+        },
+        |err| is_retryable(&err.to_string()),
+    )
+}
+
+/// # seconds before refresh
+///
+/// none if the expiry is unknown, 0 if it should be refreshed now
+pub fn secs_to_refresh(expires_at: u64, now: u64) -> Option<u64> {
+    if expires_at == 0 {
+        return None;
+    }
+    Some(
+        expires_at
+            .saturating_sub(TOKEN_REFRESH_AHEAD_SECS)
+            .saturating_sub(now),
+    )
+}
+
+/// # refresh token of active account if it expires soon
+///
+/// return true if it is refreshed
+pub fn ensure_token() -> Result<bool, Box<dyn Error>> {
+    let account = match active_account() {
+        Some(account) => account,
+        None => return Ok(false),
+    };
+    if secs_to_refresh(account.expires_at, now()) != Some(0) {
+        return Ok(false);
+    }
+    info!("token of {} expires soon, refresh it", account.name);
+    refresh_account_token(&account.name)?;
+    Ok(true)
+}
+
+/// # check token in background
+///
+/// call it once at startup
+pub fn start_token_refresh() {
+    tokio::spawn(async {
+        loop {
+            if let Err(err) = ensure_token() {
+                error!("refresh token failed: {:?}", err);
+            }
+            tokio::time::sleep(Duration::from_secs(TOKEN_CHECK_INTERVAL_SECS)).await;
+        }
+    });
+}
+
+/// # call with token
+///
+/// refresh token and retry once if the call is failed by the token
+pub fn with_token<T, E: Display>(mut f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    if let Err(err) = ensure_token() {
+        error!("refresh token failed: {:?}", err);
+    }
+    match f() {
+        Err(err) if is_token_error(&err.to_string()) => {
+            let account = match active_account() {
+                Some(account) => account,
+                None => return Err(err),
+            };
+            info!("token of {} is invalid: {}", account.name, err);
+            if let Err(refresh_err) = refresh_account_token(&account.name) {
+                error!("refresh token failed: {:?}", refresh_err);
+                return Err(err);
+            }
+            f()
+        }
+        res => res,
+    }
+}
+
+/// # upload file
+///
+/// the file is created in cloud after all slices are uploaded,
+/// so a retry without overwrite checks whether the last try is done,
+/// a file which fails the check is overwritten by the next try
+pub fn upload_to_cloud(
+    dir: &str,
+    name: &str,
+    path: &str,
+    is_overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    let parallelism = Settings::read().transfer_parallelism();
+    let cloud_path = join_path(dir, name);
+    let mut is_overwrite = is_overwrite;
+    let res = retry(&format!("upload {}", path), |attempt| {
+        let md5 = if attempt > 0 && !is_overwrite && is_cloud_file_exists(dir, name) {
+            get_file_md5(path)?
+        } else {
+            with_token(|| upload_file(dir, name, path, is_overwrite, parallelism))?
+        };
+        let res = verify_upload(dir, name, &md5);
+        match res {
+            Ok(is_verified) => {
+                record_md5(&cloud_path, &md5, file_size(path), is_verified);
+                Ok(())
+            }
+            Err(err) => {
+                is_overwrite = true;
+                Err(err)
+            }
+        }
+    });
+    // a failed upload may leave the dir created
+    invalidate_listing(dir);
+    if res.is_err() {
+        forget_md5(&cloud_path);
+    }
+    res
+}
+
+/// # check uploaded file by md5 of server
+///
+/// return false if the server has no md5 to compare
+fn verify_upload(dir: &str, name: &str, md5: &str) -> Result<bool, Box<dyn Error>> {
+    let cloud_path = join_path(dir, name);
+    let fs_id = list_dir(dir)?
+        .into_iter()
+        .find(|item| !item.is_dir && item.name == name)
+        .map(|item| item.fs_id)
+        .ok_or_else(|| format!("上传后找不到 {}", cloud_path))?;
+    Ok(check_md5(&cloud_path, md5, fetch_server_md5(fs_id)?)?)
+}
+
+/// md5 of cloud file, none if it is unknown
+fn fetch_server_md5(fs_id: u64) -> Result<Option<String>, Box<dyn Error>> {
+    with_token(|| match active_account() {
+        Some(account) => fetch_file_md5(&account.access_token, fs_id),
+        None => Ok(None),
+    })
+}
+
+/// # size of cloud file
+///
+/// none if it is unknown
+pub fn fetch_cloud_file_size(fs_id: u64) -> Result<Option<u64>, Box<dyn Error>> {
+    with_token(|| match active_account() {
+        Some(account) => fetch_file_size(&account.access_token, fs_id).map(Some),
+        None => Ok(None),
+    })
+}
+
+/// # compare md5 of file with server
+///
+/// return false if the server has no md5 to compare
+fn check_md5(path: &str, md5: &str, server_md5: Option<String>) -> Result<bool, String> {
+    match server_md5 {
+        Some(server_md5) if server_md5 != md5 => {
+            warn!("md5 of {} is {}, expect {}", path, md5, server_md5);
+            Err(format!("{} {}", path, CORRUPTED_TEXT))
+        }
+        Some(_) => Ok(true),
+        None => {
+            warn!("md5 of {} is not checked", path);
+            Ok(false)
+        }
+    }
+}
+
+/// upload with slices, one at a time if parallelism is 1,
+/// use one stream of api only without an active account,
+/// return md5 of the file
+fn upload_file(
+    dir: &str,
+    name: &str,
+    path: &str,
+    is_overwrite: bool,
+    parallelism: usize,
+) -> Result<String, Box<dyn Error>> {
+    if let Some(account) = active_account() {
+        // the whole upload is retried by caller, one stream of api would
+        // send the file again without the throttle of slices
+        return upload_parallel(
+            &account.access_token,
+            dir,
+            name,
+            path,
+            is_overwrite,
+            parallelism,
+        )
+        .inspect_err(|err| {
+            warn!("sliced upload of {} failed, no fallback: {}", path, err);
+        });
+    }
+    info!("upload {} with one stream of api", path);
+    throttle(Direction::Upload, file_size(path));
+    let md5 = get_file_md5(path)?;
+    Api::upload_to_cloud(dir, name, path, is_overwrite)?;
+    Ok(md5)
+}
+
+/// # download file
+///
+/// the local file is written again by retry,
+/// a file which fails the check is removed
+pub fn start_download(fs_id: u64, path: &str) -> Result<(), Box<dyn Error>> {
+    let parallelism = Settings::read().transfer_parallelism();
+    let res = retry(&format!("download {}", path), |_| {
+        let md5 = with_token(|| download_file(fs_id, path, parallelism))?;
+        match check_md5(path, &md5, fetch_server_md5(fs_id)?) {
+            Ok(is_verified) => {
+                record_md5(path, &md5, file_size(path), is_verified);
+                Ok(())
+            }
+            Err(err) => {
+                if let Err(err) = fs::remove_file(path) {
+                    error!("remove {} failed: {:?}", path, err);
+                }
+                Err(err.into())
+            }
+        }
+    });
+    if res.is_err() {
+        forget_md5(path);
+    }
+    res
+}
+
+/// download with ranges, one stream if parallelism is 1,
+/// use one stream of api only without an active account,
+/// return md5 of the file
+fn download_file(fs_id: u64, path: &str, parallelism: usize) -> Result<String, Box<dyn Error>> {
+    if let Some(account) = active_account() {
+        return download_parallel(&account.access_token, fs_id, path, parallelism)
+            .inspect_err(|err| warn!("sliced download of {} failed, no fallback: {}", path, err));
+    }
+    info!("download {} with one stream of api", path);
+    Api::start_download(fs_id, path)?;
+    // size is known after download, the next transfer waits for it
+    throttle(Direction::Download, file_size(path));
+    Ok(get_file_md5(path)?)
+}
+
+fn file_size(path: &str) -> u64 {
+    fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+fn is_cloud_file_exists(dir: &str, name: &str) -> bool {
+    list_dir(dir).is_ok_and(|items| items.iter().any(|item| !item.is_dir && item.name == name))
+}
+
+pub fn start_file_manager(
+    path: &str,
+    dest: Option<&str>,
+    new_name: Option<&str>,
+    operate: impl Fn() -> ApiOperates,
+) -> Result<(), Box<dyn Error>> {
+    let res = with_token(|| Api::start_file_manager(path, dest, new_name, operate()));
+    invalidate_listing(path);
+    if let Some(dest) = dest {
+        invalidate_listing(dest);
+    }
+    res
+}
+
+pub fn start_create_dir(path: &str, name: &str) -> Result<(), Box<dyn Error>> {
+    let res = with_token(|| Api::start_create_dir(path, name));
+    invalidate_listing(&join_path(path, name));
+    res
+}
+
+/// # list of cloud dir
+///
+/// the listing is cached for the active account
+pub fn fetch_dir_list(path: &str) -> Result<Vec<CachedItem>, Box<dyn Error>> {
+    retry(&format!("list {}", path), |_| list_dir(path))
+}
+
+/// list cloud dir once, for the calls which are retried already
+fn list_dir(path: &str) -> Result<Vec<CachedItem>, Box<dyn Error>> {
+    let api_type = Api::get_read().api_type;
+    let items = with_token(|| {
+        let url = Api::get_read().get_file_list_url(path, 0);
+        Api::start_fetch_dir_list(&url, api_type)
+    })?
+    .into_iter()
+    .map(CachedItem::from)
+    .collect::<Vec<CachedItem>>();
+    store_listing(path, items.clone());
+    Ok(items)
+}
+
+/// # cloud backups of title for active account
+///
+/// the default root dir is searched by api, other root dirs are listed here,
+/// return the dir of title and its backups
+pub fn fetch_save_cloud_list(
+    title_id: &str,
+    is_ignore_err: bool,
+) -> (Option<String>, Option<Vec<SaveItem>>) {
+    if let Err(err) = ensure_token() {
+        error!("refresh token failed: {:?}", err);
+    }
+    let root_dir = cloud_save_dir();
+    if root_dir == GAME_SAVE_CLOUD_DIR {
+        // none of list is a failure
+        let mut game_save_dir = None;
+        let res = retry(&format!("list saves of {}", title_id), |_| {
+            let (dir, list) = Api::fetch_save_cloud_list(title_id, is_ignore_err);
+            game_save_dir = dir;
+            list.ok_or_else(|| "获取云端存档列表失败".into())
+        });
+        if let (Some(dir), Ok(saves)) = (&game_save_dir, &res) {
+            store_title_dir(&root_dir, title_id, dir);
+            store_listing(
+                dir,
+                saves
+                    .iter()
+                    .map(|save| CachedItem {
+                        is_dir: false,
+                        name: save.name.clone(),
+                        fs_id: save.fs_id,
+                    })
+                    .collect(),
+            );
+        }
+        return (game_save_dir, res.ok());
+    }
+    let title_dir = match fetch_dir_list(&root_dir) {
+        Ok(items) => items
+            .into_iter()
+            .find(|item| item.is_dir && item.name.starts_with(title_id))
+            .map(|item| format!("{}/{}", root_dir, item.name)),
+        Err(err) => {
+            // root dir is created by the first upload
+            info!("fetch {} list failed: {}", root_dir, err);
+            None
+        }
+    };
+    let title_dir = match title_dir {
+        Some(title_dir) => title_dir,
+        None => return (None, Some(vec![])),
+    };
+    match fetch_dir_list(&title_dir) {
+        Ok(items) => {
+            store_title_dir(&root_dir, title_id, &title_dir);
+            (Some(title_dir), Some(saves_of(items)))
+        }
+        Err(err) => {
+            error!("fetch {} list failed: {}", title_dir, err);
+            (Some(title_dir), is_ignore_err.then(Vec::new))
+        }
+    }
+}
+
+fn saves_of(items: Vec<CachedItem>) -> Vec<SaveItem> {
+    items
+        .into_iter()
+        .filter(|item| !item.is_dir)
+        .map(|item| SaveItem {
+            name: item.name,
+            fs_id: item.fs_id,
+        })
+        .collect()
+}
+
+fn find_save_by_md5<'a>(
+    saves: &'a [SaveItem],
+    md5: &str,
+    md5_of: impl Fn(&SaveItem) -> Option<String>,
+) -> Option<&'a SaveItem> {
+    saves
+        .iter()
+        .find(|save| md5_of(save).is_some_and(|save_md5| save_md5 == md5))
+}
+
+/// # cloud backup with the same content
+///
+/// `md5` of local backup is looked up in the backup manifest first,
+/// then in md5s reported by server, return the name of the cloud backup
+pub fn find_cloud_duplicate(dir: &str, saves: &[SaveItem], md5: &str) -> Option<String> {
+    let in_manifest = find_save_by_md5(saves, md5, |save| {
+        manifest_entry(&join_path(dir, &save.name)).map(|entry| entry.md5)
+    });
+    if let Some(save) = in_manifest {
+        return Some(save.name.clone());
+    }
+    if saves.is_empty() {
+        return None;
+    }
+    let fs_ids = saves.iter().map(|save| save.fs_id).collect::<Vec<u64>>();
+    let res = retry(&format!("fetch md5s of {}", dir), |_| {
+        with_token(|| {
+            let account = active_account().ok_or("没有登录账号")?;
+            fetch_file_md5s(&account.access_token, &fs_ids)
+        })
+    });
+    match res {
+        Ok(md5s) => find_save_by_md5(saves, md5, |save| md5s.get(&save.fs_id).cloned())
+            .map(|save| save.name.clone()),
+        Err(err) => {
+            warn!("fetch md5s of {} failed: {}", dir, err);
+            None
+        }
+    }
+}
+
+/// # cached backups of title for active account
+///
+/// return the dir of title, its backups and whether they are stale
+pub fn cached_save_cloud_list(title_id: &str) -> Option<(String, Vec<SaveItem>, bool)> {
+    let dir = cached_title_dir(&cloud_save_dir(), title_id)?;
+    let listing = cached_listing(&dir)?;
+    let is_stale = listing.is_stale(now());
+    Some((dir, saves_of(listing.items), is_stale))
+}
+
+/// space of drive in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub used: u64,
+    pub total: u64,
+}
+
+#[derive(Deserialize)]
+struct QuotaRes {
+    errno: i32,
+    #[serde(default)]
+    used: u64,
+    #[serde(default)]
+    total: u64,
+}
+
+/// # quota of active account
+pub fn fetch_quota() -> Result<Quota, Box<dyn Error>> {
+    retry("fetch quota", |_| {
+        with_token(|| {
+            let account = active_account().ok_or("没有登录账号")?;
+            let res = ureq::get(BAIDU_QUOTA_URL)
+                .set("User-Agent", "pan.baidu.com")
+                .query("access_token", &account.access_token)
+                .query("checkfree", "1")
+                .call()?
+                .into_string()?;
+            parse_quota(&res)
+        })
+    })
+}
+
+fn parse_quota(res: &str) -> Result<Quota, Box<dyn Error>> {
+    let quota: QuotaRes = serde_json::from_str(res)?;
+    if quota.errno != 0 {
+        // keep the body for token errors
+        return Err(res.into());
+    }
+    Ok(Quota {
+        used: quota.used,
+        total: quota.total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constant::TOKEN_REFRESH_AHEAD_SECS;
+
+    use crate::api::SaveItem;
+
+    use super::{
+        check_md5, error_codes, find_save_by_md5, is_retryable, is_token_error, parse_quota,
+        secs_to_refresh, Quota,
+    };
+
+    #[test]
+    fn test_token_lifecycle() {
+        assert!(is_token_error(r#"{"errno": -6, "request_id": 1}"#));
+        assert!(is_token_error(
+            r#"{"error_code":111,"error_msg":"Access token expired"}"#
+        ));
+        assert!(is_token_error("Access Token invalid or no longer valid"));
+        assert!(!is_token_error(r#"{"errno":-9}"#));
+        assert!(!is_token_error(r#"{"errno":-62,"request_id":1}"#));
+        assert!(!is_token_error(r#"{"errno":1101}"#));
+        assert!(!is_token_error(
+            "upload with access token failed: timed out"
+        ));
+        assert!(!is_token_error("connection reset"));
+        assert_eq!(
+            error_codes(r#"{"errno": -6, "error_code":111, "errmsg":"errno"}"#),
+            vec![-6, 111]
+        );
+
+        let now = 1_000_000;
+        assert_eq!(secs_to_refresh(0, now), None);
+        assert_eq!(
+            secs_to_refresh(now + TOKEN_REFRESH_AHEAD_SECS + 60, now),
+            Some(60)
+        );
+        assert_eq!(secs_to_refresh(now + 60, now), Some(0));
+        assert_eq!(secs_to_refresh(now - 60, now), Some(0));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable("Connection reset by peer"));
+        assert!(is_retryable(r#"{"errno":31034}"#));
+        assert!(!is_retryable(r#"{"errno": -8}"#));
+        assert!(!is_retryable(r#"{"errno":-6}"#));
+        assert!(!is_retryable(crate::constant::OFFLINE_TEXT));
+        assert!(is_retryable("https://pan.baidu.com/rest: status code 503"));
+        assert!(!is_retryable("https://pan.baidu.com/rest: status code 404"));
+        assert!(!is_retryable("No such file or directory (os error 2)"));
+        assert!(!is_retryable("invalid Zip archive: Invalid zip header"));
+        // a corrupted slice fails the whole transfer, which is tried again
+        assert!(is_retryable(&format!(
+            "分片 0 {}：{{}}",
+            crate::constant::CORRUPTED_TEXT
+        )));
+    }
+
+    #[test]
+    fn test_check_md5() {
+        let md5 = "e10adc3949ba59abbe56e057f20f883e";
+        assert_eq!(check_md5("/a.zip", md5, Some(md5.to_string())), Ok(true));
+        assert_eq!(check_md5("/a.zip", md5, None), Ok(false));
+        let err = check_md5("/a.zip", md5, Some("0".repeat(32))).unwrap_err();
+        // a corrupted transfer is tried again
+        assert!(is_retryable(&err));
+    }
+
+    #[test]
+    fn test_find_save_by_md5() {
+        let saves = (1..=3)
+            .map(|fs_id| SaveItem {
+                name: format!("{}.zip", fs_id),
+                fs_id,
+            })
+            .collect::<Vec<SaveItem>>();
+        let md5_of = |save: &SaveItem| (save.fs_id != 1).then(|| format!("md5-{}", save.fs_id));
+        assert_eq!(
+            find_save_by_md5(&saves, "md5-2", md5_of).map(|save| save.fs_id),
+            Some(2)
+        );
+        // unknown md5 never matches
+        assert!(find_save_by_md5(&saves, "md5-1", md5_of).is_none());
+    }
+
+    #[test]
+    fn test_parse_quota() {
+        assert_eq!(
+            parse_quota(r#"{"errno":0,"total":2205465706496,"used":686653888910}"#).unwrap(),
+            Quota {
+                used: 686653888910,
+                total: 2205465706496
+            }
+        );
+        let err = parse_quota(r#"{"errno":-6}"#).unwrap_err();
+        assert!(is_token_error(&err.to_string()));
+    }
+}
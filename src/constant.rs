@@ -5,12 +5,17 @@
 
 use std::time::Duration;
 
+pub const APP_VERSION: &str = "V2024.02.28";
 pub const SCREEN_WIDTH: i32 = 960;
 pub const SCREEN_HEIGHT: i32 = 544;
 
 // psv game save path
 pub const GAME_CARD_SAVE_DIR: &str = "grw0:savedata";
 pub const GAME_SAVE_DIR: &str = "ux0:user/00/savedata";
+// psp and psone game save path of adrenaline
+pub const PSP_SAVE_DIR: &str = "ux0:pspemu/PSP/SAVEDATA";
+// pseudo game save dir prefix of backup profile
+pub const PROFILE_SAVE_DIR_PREFIX: &str = "profile:";
 pub const PSV_DEVICES: [&str; 11] = [
     "ux0:", "uma0:", "grw0:", "os0:", "pd0:", "sa0:", "tm0:", "ud0:", "ur0:", "vd0:", "vs0:",
 ];
@@ -28,8 +33,54 @@ pub const GAME_SAVE_CLOUD_DIR: &str = "/apps/Backup/psvita/save-cloud/saves";
 pub const UPLOAD_CACHE_DIR: &str = "/apps/Backup/upload_cache_can_delete";
 // log path
 pub const SAVE_LOG_PATH: &str = "ux0:data/save-cloud/log/log.txt";
+// diagnostics dir
+pub const DIAGNOSTICS_DIR: &str = "ux0:data/save-cloud/diagnostics";
+// diagnostics cloud dir
+pub const DIAGNOSTICS_CLOUD_DIR: &str = "/apps/Backup/psvita/save-cloud/diagnostics";
+// trash dir of deleted local files
+pub const TRASH_DIR: &str = "ux0:data/save-cloud/trash";
+// trash dir of deleted cloud files
+pub const CLOUD_TRASH_DIR: &str = "/apps/Backup/psvita/save-cloud/trash";
+// days to keep items in trash
+pub const TRASH_KEEP_DAYS: u64 = 30;
+// max size of trash in MB
+pub const TRASH_MAX_SIZE_MB: u64 = 1024;
+// staging dir of interrupted backups and restores
+pub const STAGING_DIR: &str = "ux0:data/save-cloud/staging";
+// operation journal path
+pub const JOURNAL_PATH: &str = "ux0:data/save-cloud/journal.jsonl";
+// max entries of operation journal
+pub const JOURNAL_MAX_ENTRIES: usize = 1000;
 // baidu auth config path
 pub const AUTH_BAIDU_CONFIG_PATH: &str = "ux0:data/save-cloud/auth";
+// tokens of cloud accounts
+pub const ACCOUNTS_PATH: &str = "ux0:data/save-cloud/accounts.json";
+// refresh access token before it expires
+pub const TOKEN_REFRESH_AHEAD_SECS: u64 = 60 * 60 * 24 * 3;
+// interval to check the expiry of access token
+pub const TOKEN_CHECK_INTERVAL_SECS: u64 = 60 * 30;
+// listings of cloud dirs
+pub const LISTING_CACHE_PATH: &str = "ux0:data/save-cloud/listing_cache.json";
+// cached listing older than it is refreshed in background
+pub const LISTING_CACHE_TTL_SECS: u64 = 60 * 10;
+pub const LISTING_CACHE_MAX_ENTRIES: usize = 512;
+// interval to check the network state
+pub const NETWORK_CHECK_INTERVAL_SECS: u64 = 5;
+pub const OFFLINE_TEXT: &str = "网络未连接，云端功能暂不可用";
+// md5 of transferred file or slice does not match
+pub const CORRUPTED_TEXT: &str = "校验失败，文件已损坏";
+// quota of baidu drive
+pub const BAIDU_QUOTA_URL: &str = "https://pan.baidu.com/api/quota";
+// parallel transfers of baidu drive
+pub const BAIDU_FILE_URL: &str = "https://pan.baidu.com/rest/2.0/xpan/file";
+pub const BAIDU_SUPERFILE_URL: &str = "https://d.pcs.baidu.com/rest/2.0/pcs/superfile2";
+pub const BAIDU_MULTIMEDIA_URL: &str = "https://pan.baidu.com/rest/2.0/xpan/multimedia";
+pub const TRANSFER_MAX_PARALLELISM: usize = 4;
+// verified hashes of transferred files
+pub const MANIFEST_PATH: &str = "ux0:data/save-cloud/manifest.json";
+pub const MANIFEST_MAX_ENTRIES: usize = 2048;
+// settings path
+pub const SETTINGS_PATH: &str = "ux0:data/save-cloud/settings.json";
 
 // app
 pub const BUTTON_HOLDING_DELAY: u128 = 360;
@@ -40,26 +91,37 @@ pub const TEXT_R: &str = "→ R";
 // desktop
 pub const DESKTOP_BOTTOM_BAR_TEXT: &str = "(START) 退出    (□) 关于    (△) 存档    (〇) 备份/还原";
 pub const DESKTOP_BOTTOM_BAR_CLOUD_TEXT: &str =
-    "(START) 退出    (□) 切换    (△) 操作    (X) 返回    (〇) 选择";
+    "(START) 退出    (SELECT) 账号    (□) 切换    (△) 操作    (X) 返回    (〇) 选择";
 
 // titles
 pub const SAVE_DRAWER_BOTTOM_BAR_TEXT: &str =
     "(SELECT) 上传    (□) 还原    (△) 删除    (X) 关闭    (〇) 选择";
 pub const SAVE_DRAWER_CLOUD_BOTTOM_BAR_TEXT: &str =
-    "(SELECT) 下载    (□) 还原    (△) 删除    (X) 关闭    (〇) 选择";
+    "(START) 账号    (SELECT) 下载    (□) 还原    (△) 删除/刷新    (X) 关闭    (〇) 选择";
 pub const ACTION_DRAWER_BOTTOM_BAR_TEXT: &str = "(X) 关闭    (〇) 选择";
 pub const TITLE_DRAWER_BOTTOM_BAR_TEXT: &str = "(X) 关闭    (〇) 选择";
+pub const TRASH_BOTTOM_BAR_TEXT: &str = "(〇) 恢复    (△) 永久删除    (X) 关闭    (↑↓) 滚动";
+pub const ACCOUNTS_BOTTOM_BAR_TEXT: &str =
+    "(〇) 切换/详情    (□) 备份目录    (△) 移除    (X) 关闭    (↑↓) 滚动";
+pub const ACCOUNT_BOTTOM_BAR_TEXT: &str = "(〇) 重新授权    (△) 退出登录    (X) 关闭";
+pub const JOURNAL_BOTTOM_BAR_TEXT: &str = "(□) 筛选    (△) 撤销上次恢复    (X) 关闭    (↑↓) 滚动";
+pub const LOG_VIEWER_BOTTOM_BAR_TEXT: &str = "(□) 级别    (△) 跟随    (X) 关闭    (↑↓) 滚动";
+pub const CHECK_LIST_BOTTOM_BAR_TEXT: &str = "(START) 确定    (□) 全选    (X) 取消    (〇) 选择";
 pub const TAB_LOCAL: &str = "本地备份";
 pub const TAB_CLOUD: &str = "云端备份";
 pub const NEW_BACKUP: &str = "新建备份";
 pub const NEW_CLOUD_BACKUP: &str = "新建云备份";
-// ignore list
+// ignore list, always excluded by backup filter
 pub const BACKUP_BLACK_LIST: [&str; 4] = [
     "sce_pfs",
     "sce_sys/safemem.dat",
     "sce_sys/keystone",
     "sce_sys/sealedkey",
 ];
+// temporary file of decrypted backup to restore
+pub const DECRYPTED_BACKUP_SUFFIX: &str = ".decrypted";
+// param.sfo of vita/psm save and psp save
+pub const SAVE_SFO_PATHS: [&str; 2] = ["sce_sys/param.sfo", "PARAM.SFO"];
 pub const ANIME_TIME_300: Duration = Duration::from_millis(300);
 pub const ANIME_TIME_160: Duration = Duration::from_millis(160);
 
@@ -69,6 +131,13 @@ pub const SCAN_QR_CODE_TIPS: &str = "百度云 App 扫码登录";
 pub const UPLOAD_SLICE_PER_SIZE: usize = 1024 * 1024 * 4; // 4 MiB
 pub const DOWNLOAD_BUF_SIZE: usize = 1024 * 512; // 512 Kib
 pub const LIST_NAME_WIDTH: i32 = SCREEN_WIDTH / 2 - 40;
+// rate limits in KiB/s to switch, 0 is unlimited
+pub const RATE_LIMIT_PRESETS_KB: [u64; 5] = [0, 256, 512, 1024, 2048];
+// deferred uploads start if no buttons are pressed for it
+pub const UPLOAD_IDLE_SECS: u64 = 60 * 5;
+pub const UPLOAD_WINDOW_CHECK_SECS: u64 = 1;
+// give up waiting, the backups are kept in local
+pub const UPLOAD_WINDOW_TIMEOUT_SECS: u64 = 60 * 60 * 2;
 
 // dialog
 pub const DIALOG_WIDTH: i32 = 600;
@@ -82,6 +151,9 @@ pub const HOME_PAGE_URL: &str = "https://save-cloud.sketchraw.com?psvita=go";
 pub const INVALID_EAT_PANCAKE: &str = "缺少 eat.pancake";
 pub const ABOUT_TEXT: &str = "Save Cloud 云存档，扫码访问主页！";
 
+// json file of fake titles, to run the ui on linux
+pub const TITLES_FIXTURE_ENV_KEY: &str = "SAVE_CLOUD_TITLES";
+
 // certificate
 pub const SSL_CERT_ENV_KEY: &str = "SSL_CERT_FILE";
 pub const CURL_CERT_CURL: &str = "https://curl.se/ca/cacert.pem";
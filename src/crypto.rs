@@ -0,0 +1,344 @@
+//! # backup encryption
+//!
+//! encrypted backup is the zip file sealed with AES-256-GCM by chunks,
+//! the key is derived from passphrase with PBKDF2-HMAC-SHA256
+//!
+//! ```text
+//! magic(8) | iterations(4) | salt(16) | nonce prefix(7) | chunk | chunk | ...
+//! chunk = ciphertext(CHUNK_LEN, shorter for the last one) | tag(16)
+//! ```
+//!
+//! nonce of chunk is `nonce prefix | index(4) | is last(1)`, so chunks can not
+//! be reordered or dropped, the file is read and written by chunks
+//!
+//! backups of version 1 are sealed as one chunk with a nonce of 12 bytes,
+//! they are still decrypted in memory
+use std::{
+    error::Error,
+    fs,
+    io::{self, Read, Write},
+    num::NonZeroU32,
+    path::Path,
+};
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::utils::{replace_file, temp_path};
+
+const MAGIC_V1: &[u8; 8] = b"SCENC\0\0\x01";
+const MAGIC: &[u8; 8] = b"SCENC\0\0\x02";
+const ITERATIONS: u32 = 100_000;
+// header of a crafted file must not hang the key derivation
+const MAX_ITERATIONS: u32 = 1_000_000;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const NONCE_PREFIX_LEN: usize = NONCE_LEN - 5;
+const CHUNK_LEN: usize = 64 * 1024;
+const HEADER_LEN_V1: usize = MAGIC.len() + 4 + SALT_LEN + NONCE_LEN;
+const HEADER_LEN: usize = MAGIC.len() + 4 + SALT_LEN + NONCE_PREFIX_LEN;
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    iterations: u32,
+) -> Result<LessSafeKey, Box<dyn Error>> {
+    if iterations > MAX_ITERATIONS {
+        return Err(format!("invalid iterations: {}", iterations).into());
+    }
+    let iterations = NonZeroU32::new(iterations).ok_or("invalid iterations")?;
+    let mut key = [0; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| "create key failed")?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn read_iterations(header: &[u8]) -> u32 {
+    let mut iterations = [0; 4];
+    iterations.copy_from_slice(&header[MAGIC.len()..MAGIC.len() + 4]);
+    u32::from_le_bytes(iterations)
+}
+
+fn chunk_nonce(prefix: &[u8], idx: u32, is_last: bool) -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&idx.to_be_bytes());
+    nonce[NONCE_LEN - 1] = is_last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// read until `buf` has `len` bytes or the end
+fn read_chunk(reader: &mut impl Read, buf: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    buf.clear();
+    reader.take(len as u64).read_to_end(buf)?;
+    Ok(())
+}
+
+pub fn is_encrypted(buf: &[u8]) -> bool {
+    buf.starts_with(MAGIC) || buf.starts_with(MAGIC_V1)
+}
+
+pub fn is_encrypted_file(path: impl AsRef<Path>) -> Result<bool, Box<dyn Error>> {
+    let mut magic = vec![];
+    fs::File::open(path)?
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    Ok(is_encrypted(&magic))
+}
+
+/// # encrypt by chunks
+pub fn encrypt(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    passphrase: &str,
+) -> Result<(), Box<dyn Error>> {
+    let rng = SystemRandom::new();
+    let mut salt = [0; SALT_LEN];
+    let mut prefix = [0; NONCE_PREFIX_LEN];
+    rng.fill(&mut salt).map_err(|_| "generate salt failed")?;
+    rng.fill(&mut prefix).map_err(|_| "generate nonce failed")?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&ITERATIONS.to_le_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&prefix);
+    writer.write_all(&header)?;
+
+    let key = derive_key(passphrase, &salt, ITERATIONS)?;
+    let mut chunk = Vec::with_capacity(CHUNK_LEN + TAG_LEN);
+    for idx in 0.. {
+        read_chunk(reader, &mut chunk, CHUNK_LEN)?;
+        // a full chunk is followed by another one, empty at least
+        let is_last = chunk.len() < CHUNK_LEN;
+        key.seal_in_place_append_tag(
+            chunk_nonce(&prefix, idx, is_last),
+            Aad::from(&header),
+            &mut chunk,
+        )
+        .map_err(|_| "encrypt failed")?;
+        writer.write_all(&chunk)?;
+        if is_last {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// # decrypt by chunks
+///
+/// a broken chunk fails at once, the chunks written before it are not
+/// trusted, so write to a temp file
+pub fn decrypt(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    passphrase: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut header = vec![];
+    read_chunk(reader, &mut header, MAGIC.len())?;
+    if header.as_slice() == MAGIC_V1 {
+        return decrypt_v1(header, reader, writer, passphrase);
+    }
+    if header.as_slice() != MAGIC {
+        return Err("not an encrypted backup".into());
+    }
+    let mut rest = vec![];
+    read_chunk(reader, &mut rest, HEADER_LEN - MAGIC.len())?;
+    header.append(&mut rest);
+    if header.len() < HEADER_LEN {
+        return Err("not an encrypted backup".into());
+    }
+    let salt = &header[MAGIC.len() + 4..MAGIC.len() + 4 + SALT_LEN];
+    let prefix = &header[HEADER_LEN - NONCE_PREFIX_LEN..];
+    let key = derive_key(passphrase, salt, read_iterations(&header))?;
+
+    let mut chunk = Vec::with_capacity(CHUNK_LEN + TAG_LEN);
+    for idx in 0.. {
+        read_chunk(reader, &mut chunk, CHUNK_LEN + TAG_LEN)?;
+        let is_last = chunk.len() < CHUNK_LEN + TAG_LEN;
+        let data = key
+            .open_in_place(
+                chunk_nonce(prefix, idx, is_last),
+                Aad::from(&header),
+                &mut chunk,
+            )
+            .map_err(|_| "wrong passphrase or broken backup")?;
+        writer.write_all(data)?;
+        if is_last {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// version 1 is sealed as a whole
+fn decrypt_v1(
+    mut header: Vec<u8>,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    passphrase: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut rest = vec![];
+    reader.read_to_end(&mut rest)?;
+    let rest_len = HEADER_LEN_V1 - header.len();
+    if rest.len() < rest_len {
+        return Err("not an encrypted backup".into());
+    }
+    let mut data = rest.split_off(rest_len);
+    header.append(&mut rest);
+    let salt = &header[MAGIC.len() + 4..MAGIC.len() + 4 + SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    nonce.copy_from_slice(&header[HEADER_LEN_V1 - NONCE_LEN..]);
+
+    let key = derive_key(passphrase, salt, read_iterations(&header))?;
+    let data = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(&header),
+            &mut data,
+        )
+        .map_err(|_| "wrong passphrase or broken backup")?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+/// encrypt file in place
+pub fn encrypt_file(path: impl AsRef<Path>, passphrase: &str) -> Result<(), Box<dyn Error>> {
+    with_temp(path.as_ref(), path.as_ref(), |reader, writer| {
+        encrypt(reader, writer, passphrase)
+    })
+}
+
+pub fn decrypt_file(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    passphrase: &str,
+) -> Result<(), Box<dyn Error>> {
+    with_temp(from.as_ref(), to.as_ref(), |reader, writer| {
+        decrypt(reader, writer, passphrase)
+    })
+}
+
+/// write `to` by a temp file, which is removed if failed
+fn with_temp(
+    from: &Path,
+    to: &Path,
+    f: impl FnOnce(
+        &mut io::BufReader<fs::File>,
+        &mut io::BufWriter<fs::File>,
+    ) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let temp = temp_path(to);
+    let res = (|| {
+        let mut reader = io::BufReader::new(fs::File::open(from)?);
+        let mut writer = io::BufWriter::new(fs::File::create(&temp)?);
+        f(&mut reader, &mut writer)?;
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        replace_file(&temp, to)?;
+        Ok(())
+    })();
+    if res.is_err() && temp.exists() {
+        let _ = fs::remove_file(&temp);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ring::aead::{Aad, Nonce, NONCE_LEN};
+
+    use super::{decrypt, derive_key, encrypt, is_encrypted, CHUNK_LEN, MAGIC, MAGIC_V1};
+
+    fn encrypt_vec(data: &[u8], passphrase: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut encrypted = vec![];
+        encrypt(&mut Cursor::new(data), &mut encrypted, passphrase)?;
+        Ok(encrypted)
+    }
+
+    fn decrypt_vec(data: &[u8], passphrase: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut decrypted = vec![];
+        decrypt(&mut Cursor::new(data), &mut decrypted, passphrase)?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn test_encrypt() -> Result<(), Box<dyn std::error::Error>> {
+        let data = b"PK\x03\x04 save data".to_vec();
+        let encrypted = encrypt_vec(&data, "密码 passphrase")?;
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(&data));
+        assert_eq!(decrypt_vec(&encrypted, "密码 passphrase")?, data);
+        // salt and nonce are random
+        assert_ne!(encrypt_vec(&data, "密码 passphrase")?, encrypted);
+
+        // empty, full and partial chunks
+        for len in [0, CHUNK_LEN, CHUNK_LEN * 2 + 7] {
+            let data = (0..len).map(|i| i as u8).collect::<Vec<u8>>();
+            let encrypted = encrypt_vec(&data, "passphrase")?;
+            assert_eq!(decrypt_vec(&encrypted, "passphrase")?, data);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_v1() -> Result<(), Box<dyn std::error::Error>> {
+        let salt = [1; 16];
+        let nonce = [2; NONCE_LEN];
+        let mut header = MAGIC_V1.to_vec();
+        header.extend_from_slice(&1000u32.to_le_bytes());
+        header.extend_from_slice(&salt);
+        header.extend_from_slice(&nonce);
+        let mut data = b"save data".to_vec();
+        derive_key("passphrase", &salt, 1000)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&header),
+                &mut data,
+            )
+            .unwrap();
+        header.append(&mut data);
+        assert!(is_encrypted(&header));
+        assert_eq!(decrypt_vec(&header, "passphrase")?, b"save data");
+        assert!(decrypt_vec(&header, "wrong passphrase").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_failed() -> Result<(), Box<dyn std::error::Error>> {
+        let data = vec![1; CHUNK_LEN * 2];
+        let encrypted = encrypt_vec(&data, "passphrase")?;
+        assert!(decrypt_vec(&encrypted, "wrong passphrase").is_err());
+        assert!(decrypt_vec(&encrypted[..20], "passphrase").is_err());
+        let mut broken = encrypted.clone();
+        let last = broken.len() - 1;
+        broken[last] ^= 1;
+        assert!(decrypt_vec(&broken, "passphrase").is_err());
+        // header is authenticated
+        let mut broken = encrypted.clone();
+        broken[12] ^= 1;
+        assert!(decrypt_vec(&broken, "passphrase").is_err());
+        // chunks can not be dropped
+        let truncated = &encrypted[..encrypted.len() - 16];
+        assert!(decrypt_vec(truncated, "passphrase").is_err());
+        // iterations are limited
+        let mut broken = encrypted.clone();
+        broken[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decrypt_vec(&broken, "passphrase").is_err());
+        Ok(())
+    }
+}
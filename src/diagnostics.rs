@@ -0,0 +1,96 @@
+//! # diagnostics
+//!
+//! logs, settings, app version and device summary in one zip for bug reports
+use std::{
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    api::Api,
+    constant::{APP_VERSION, PSV_DEVICES, SAVE_LOG_PATH},
+    ime::get_current_format_time,
+    settings::{Compression, Settings},
+    tai::{get_psv_account_id, TitleKind, Titles},
+    utils::create_parent_if_not_exists,
+};
+
+/// # device summary
+///
+/// account id is masked
+pub fn device_summary(titles: &Titles) -> String {
+    let count_of = |kind: TitleKind| titles.iter().filter(|title| title.kind() == kind).count();
+    let account_id = format!("{:016x}", get_psv_account_id());
+    let devices = PSV_DEVICES
+        .iter()
+        .map(|device| {
+            format!(
+                "{}{}",
+                device,
+                if Path::new(device).exists() {
+                    "ok"
+                } else {
+                    "-"
+                }
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    [
+        format!("version: {}", APP_VERSION),
+        format!("time: {}", get_current_format_time()),
+        format!("account id: {}************", &account_id[..4]),
+        format!("login: {}", Api::get_read().is_login()),
+        format!(
+            "titles: vita {}, psp {}, profile {}",
+            count_of(TitleKind::Vita),
+            count_of(TitleKind::Psp),
+            count_of(TitleKind::Profile)
+        ),
+        format!("devices: {}", devices),
+    ]
+    .join("\n")
+}
+
+/// # export diagnostics
+///
+/// zip log files, redacted settings and `summary` to `to`
+pub fn export_diagnostics(to: &str, summary: &str) -> Result<(), Box<dyn Error>> {
+    // write the buffered logs
+    log::logger().flush();
+
+    create_parent_if_not_exists(to)?;
+    let options = Compression::Deflate.zip_options();
+    let mut zip = zip::ZipWriter::new(fs::File::create(to)?);
+
+    zip.start_file("summary.txt", options)?;
+    zip.write_all(summary.as_bytes())?;
+
+    let settings = Settings::read();
+    let redactor = settings.redactor().unwrap_or_default();
+    let settings = serde_json::to_string_pretty(&*settings)?;
+    zip.start_file("settings.json", options)?;
+    zip.write_all(redactor.redact(&settings).as_bytes())?;
+
+    if let Some(log_dir) = Path::new(SAVE_LOG_PATH).parent() {
+        let mut files = fs::read_dir(log_dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        files.sort();
+        for path in files {
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            zip.start_file(format!("log/{}", name), options)?;
+            io::copy(&mut fs::File::open(&path)?, &mut zip)?;
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
@@ -0,0 +1,164 @@
+//! # glob pattern
+//!
+//! `*` matches any chars except `/`, `?` matches one char except `/`,
+//! `**` matches any dirs, such as `ux0:data/retroarch/**/*.srm`
+
+/// is `s` contains glob chars
+pub fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+/// is `path` matched by `pattern`
+pub fn is_match(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.split('/').collect::<Vec<&str>>();
+    let path = path.split('/').collect::<Vec<&str>>();
+    match_segments(&pattern, &path)
+}
+
+/// # path filter
+///
+/// files and dirs matched by `excludes` are excluded,
+/// only files matched by `includes` are kept if it is not empty,
+/// a pattern matched a dir matches all files in it
+#[derive(Debug, Default, Clone)]
+pub struct PathFilter {
+    excludes: Vec<String>,
+    includes: Vec<String>,
+}
+
+impl PathFilter {
+    pub fn new(excludes: Vec<String>, includes: Vec<String>) -> PathFilter {
+        PathFilter { excludes, includes }
+    }
+
+    /// is file excluded, `path` is relative such as `sce_sys/param.sfo`
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.is_dir_excluded(path)
+            || (!self.includes.is_empty() && !is_match_any(&self.includes, path))
+    }
+
+    /// only included files are kept
+    pub fn is_include_only(&self) -> bool {
+        !self.includes.is_empty()
+    }
+
+    /// is dir excluded, includes are not checked since files in it may be included
+    pub fn is_dir_excluded(&self, path: &str) -> bool {
+        is_match_any(&self.excludes, path)
+    }
+}
+
+/// is `path` or any parent dir of it matched by one of `patterns`
+fn is_match_any(patterns: &[String], path: &str) -> bool {
+    let path = path.trim_matches('/');
+    path.match_indices('/')
+        .map(|(i, _)| &path[..i])
+        .chain([path])
+        .any(|path| patterns.iter().any(|pattern| is_match(pattern, path)))
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| match_segments(rest, &path[i..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path)) => match_segment(first, name) && match_segments(rest, path),
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    let (mut p, mut n) = (0, 0);
+    // position of last `*` in pattern and the name position it matched to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_glob, is_match, PathFilter};
+
+    #[test]
+    fn test_is_match() {
+        assert!(is_match("ux0:data/a.txt", "ux0:data/a.txt"));
+        assert!(!is_match("ux0:data/a.txt", "ux0:data/b.txt"));
+        assert!(is_match("ux0:data/*.srm", "ux0:data/mario.srm"));
+        assert!(!is_match("ux0:data/*.srm", "ux0:data/saves/mario.srm"));
+        assert!(is_match("ux0:data/save?.bin", "ux0:data/save1.bin"));
+        assert!(!is_match("ux0:data/save?.bin", "ux0:data/save10.bin"));
+        assert!(is_match("ux0:data/**", "ux0:data/a/b/c"));
+        assert!(is_match("ux0:data/**/*.srm", "ux0:data/mario.srm"));
+        assert!(is_match(
+            "ux0:data/**/*.srm",
+            "ux0:data/saves/snes/mario.srm"
+        ));
+        assert!(is_match("**/*.tmp", "ux0:data/cache/a.tmp"));
+        assert!(!is_match("**/*.tmp", "ux0:data/cache/a.tmp.bak"));
+        assert!(is_match("ux0:data/*a*b*", "ux0:data/xaybz"));
+        assert!(!is_match("ux0:data/*a*b", "ux0:data/xaybz"));
+    }
+
+    #[test]
+    fn test_is_glob() {
+        assert!(is_glob("ux0:data/**"));
+        assert!(is_glob("ux0:data/save?.bin"));
+        assert!(!is_glob("ux0:data/retroarch"));
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let filter = PathFilter::new(
+            vec![
+                "sce_pfs".to_string(),
+                "sce_sys/keystone".to_string(),
+                "**/*.tmp".to_string(),
+                "cache/**".to_string(),
+            ],
+            vec![],
+        );
+        assert!(filter.is_dir_excluded("sce_pfs"));
+        assert!(filter.is_excluded("sce_pfs/files.db"));
+        assert!(filter.is_excluded("sce_sys/keystone"));
+        assert!(!filter.is_excluded("sce_sys/param.sfo"));
+        assert!(filter.is_excluded("data/a.tmp"));
+        assert!(filter.is_dir_excluded("cache"));
+        assert!(filter.is_excluded("cache/a/b.bin"));
+        assert!(!filter.is_excluded("data/cache.bin"));
+
+        let filter = PathFilter::new(
+            vec!["sce_pfs".to_string()],
+            vec![
+                "sce_sys/param.sfo".to_string(),
+                "slot?.bin".to_string(),
+                "data".to_string(),
+            ],
+        );
+        assert!(!filter.is_dir_excluded("cache"));
+        assert!(filter.is_excluded("cache/a.bin"));
+        assert!(!filter.is_excluded("sce_sys/param.sfo"));
+        assert!(filter.is_excluded("sce_sys/icon0.png"));
+        assert!(!filter.is_excluded("slot1.bin"));
+        assert!(!filter.is_excluded("data/a/b.bin"));
+        assert!(filter.is_excluded("sce_pfs/files.db"));
+
+        assert!(!PathFilter::default().is_excluded("a/b"));
+    }
+}
@@ -0,0 +1,324 @@
+//! # operation journal
+//!
+//! backup, restore, delete, upload, download, rename and account id change
+//! are appended to the journal as json lines, the oldest entries are dropped
+//! when the journal is full
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accounts::active_account_name,
+    constant::{JOURNAL_MAX_ENTRIES, JOURNAL_PATH},
+    ime::get_current_format_time,
+    manifest::manifest_entry,
+    utils::{create_parent_if_not_exists, write_file_atomic},
+};
+
+// serialize writes from background tasks, with the path of journal and
+// its lines, which are counted once instead of on every append
+static JOURNAL_LOCK: Mutex<Option<(String, usize)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalOp {
+    Backup,
+    Restore,
+    Delete,
+    Upload,
+    Download,
+    Rename,
+    ChangeAccountId,
+}
+
+impl JournalOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JournalOp::Backup => "备份",
+            JournalOp::Restore => "恢复",
+            JournalOp::Delete => "删除",
+            JournalOp::Upload => "上传",
+            JournalOp::Download => "下载",
+            JournalOp::Rename => "重命名",
+            JournalOp::ChangeAccountId => "修改账号",
+        }
+    }
+}
+
+/// # journal entry
+///
+/// `title_id` is empty for the operations of file manager,
+/// `error` is empty if the operation is succeeded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub time: String,
+    pub op: JournalOp,
+    pub title_id: String,
+    pub source: String,
+    pub destination: String,
+    pub size: u64,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    /// backup of game save made before restore
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_backup: Option<String>,
+    /// cloud account of cloud source or destination
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// md5 of transferred file from the backup manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+}
+
+impl JournalEntry {
+    pub fn new(op: JournalOp, title_id: &str, source: &str, destination: &str) -> JournalEntry {
+        JournalEntry {
+            time: String::new(),
+            op,
+            title_id: title_id.to_string(),
+            source: source.to_string(),
+            destination: destination.to_string(),
+            size: 0,
+            ok: true,
+            error: String::new(),
+            auto_backup: None,
+            account: None,
+            md5: None,
+        }
+    }
+
+    pub fn size(mut self, size: u64) -> JournalEntry {
+        self.size = size;
+        self
+    }
+
+    /// size of local file, 0 if it does not exist
+    pub fn size_of(self, path: &str) -> JournalEntry {
+        let size = fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+        self.size(size)
+    }
+
+    pub fn auto_backup(mut self, auto_backup: Option<String>) -> JournalEntry {
+        self.auto_backup = auto_backup;
+        self
+    }
+
+    /// is file transferred to destination
+    fn is_transfer(&self) -> bool {
+        matches!(
+            self.op,
+            JournalOp::Backup | JournalOp::Upload | JournalOp::Download
+        )
+    }
+
+    /// is source or destination in cloud
+    pub fn is_cloud(&self) -> bool {
+        self.source.starts_with('/') || self.destination.starts_with('/')
+    }
+
+    pub fn result<T, E: Display>(mut self, res: &Result<T, E>) -> JournalEntry {
+        match res {
+            Ok(_) => {
+                self.ok = true;
+                self.error.clear();
+            }
+            Err(err) => {
+                self.ok = false;
+                self.error = err.to_string();
+            }
+        }
+        self
+    }
+
+    /// # record entry with result of operation
+    pub fn record<T, E: Display>(self, res: &Result<T, E>) {
+        let mut entry = self.result(res);
+        entry.time = get_current_format_time().to_string();
+        if entry.is_cloud() {
+            entry.account = active_account_name();
+            if entry.ok && entry.is_transfer() {
+                entry.md5 = manifest_entry(&entry.destination).map(|manifest| manifest.md5);
+            }
+        }
+        if let Err(err) = append(JOURNAL_PATH, &entry, JOURNAL_MAX_ENTRIES) {
+            error!("record journal {:?} failed: {:?}", entry, err);
+        }
+    }
+
+    /// is the auto backup of restore still exists
+    pub fn can_undo(&self) -> bool {
+        self.op == JournalOp::Restore
+            && self
+                .auto_backup
+                .as_ref()
+                .is_some_and(|path| Path::new(path).exists())
+    }
+}
+
+/// # read journal
+///
+/// entries of `title_id` only if it is some, the oldest first
+pub fn read_journal(title_id: Option<&str>) -> Vec<JournalEntry> {
+    let _lock = JOURNAL_LOCK.lock();
+    let entries = read_entries(JOURNAL_PATH).unwrap_or_else(|err| {
+        if err.kind() != io::ErrorKind::NotFound {
+            error!("read journal failed: {:?}", err);
+        }
+        vec![]
+    });
+    filter_entries(entries, title_id)
+}
+
+/// # last restore which can be undone
+pub fn last_undoable_restore(entries: &[JournalEntry]) -> Option<&JournalEntry> {
+    entries
+        .iter()
+        .rev()
+        .find(|entry| entry.op == JournalOp::Restore)
+        .filter(|entry| entry.can_undo())
+}
+
+fn filter_entries(entries: Vec<JournalEntry>, title_id: Option<&str>) -> Vec<JournalEntry> {
+    match title_id {
+        Some(title_id) => entries
+            .into_iter()
+            .filter(|entry| entry.title_id == title_id)
+            .collect(),
+        None => entries,
+    }
+}
+
+/// lines which can not be parsed are skipped
+fn read_entries(path: &str) -> io::Result<Vec<JournalEntry>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// append `entry`, keep the last `max_entries` entries
+fn append(
+    path: &str,
+    entry: &JournalEntry,
+    max_entries: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut lines = JOURNAL_LOCK.lock().expect("lock journal");
+    let mut count = match lines.take() {
+        Some((counted, count)) if counted == path => count,
+        _ => count_lines(path)?,
+    };
+    create_parent_if_not_exists(path)?;
+    let line = serde_json::to_string(entry)?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", line)?;
+    drop(file);
+    count += 1;
+
+    // drop the oldest half of entries when it is full
+    if count > max_entries {
+        let entries = read_entries(path)?;
+        let keep = &entries[entries.len().saturating_sub(max_entries / 2)..];
+        let mut text = String::new();
+        for entry in keep {
+            text.push_str(&serde_json::to_string(entry)?);
+            text.push('\n');
+        }
+        write_file_atomic(path, text)?;
+        count = keep.len();
+    }
+    *lines = Some((path.to_string(), count));
+    Ok(())
+}
+
+fn count_lines(path: &str) -> io::Result<usize> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.lines().count()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::utils::TestDir;
+
+    use super::{
+        append, filter_entries, last_undoable_restore, read_entries, JournalEntry, JournalOp,
+    };
+
+    #[test]
+    fn test_journal() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("journal");
+        let path = dir.join("journal.jsonl");
+        let path = path.to_str().unwrap();
+        let auto_backup = dir.join("auto.zip");
+        fs::write(&auto_backup, "zip")?;
+
+        let backup = JournalEntry::new(
+            JournalOp::Backup,
+            "PCSG00001",
+            "ux0:user/00/savedata/PCSG00001",
+            "a.zip",
+        )
+        .size(3)
+        .result::<(), String>(&Ok(()));
+        let restore = JournalEntry::new(
+            JournalOp::Restore,
+            "PCSG00001",
+            "a.zip",
+            "ux0:user/00/savedata/PCSG00001",
+        )
+        .auto_backup(auto_backup.to_str().map(|s| s.to_string()));
+        let failed = JournalEntry::new(JournalOp::Delete, "PCSG00002", "b.zip", "")
+            .result::<(), &str>(&Err("not found"));
+        // broken line is skipped, it is counted before the first append
+        fs::write(path, "{\"op\":\n")?;
+        for entry in [&backup, &restore, &failed] {
+            append(path, entry, 10)?;
+        }
+
+        let entries = read_entries(path)?;
+        assert_eq!(
+            entries,
+            vec![backup.clone(), restore.clone(), failed.clone()]
+        );
+        assert!(!entries[2].ok);
+        assert_eq!(entries[2].error, "not found");
+        assert_eq!(
+            filter_entries(entries.clone(), Some("PCSG00002")),
+            vec![failed]
+        );
+        assert_eq!(last_undoable_restore(&entries), Some(&restore));
+
+        // the last restore can not be undone without auto backup
+        let restore_again = JournalEntry::new(
+            JournalOp::Restore,
+            "PCSG00001",
+            "a.zip",
+            "ux0:user/00/savedata/PCSG00001",
+        );
+        append(path, &restore_again, 10)?;
+        assert_eq!(last_undoable_restore(&read_entries(path)?), None);
+
+        // keep the last half when full
+        for _ in 0..6 {
+            append(path, &backup, 10)?;
+        }
+        assert_eq!(read_entries(path)?.len(), 5);
+
+        Ok(())
+    }
+}
@@ -1,9 +1,31 @@
+pub mod accounts;
 pub mod api;
 pub mod app;
+pub mod cloud;
 pub mod constant;
+pub mod crypto;
+pub mod diagnostics;
+pub mod glob;
 pub mod ime;
+pub mod journal;
+pub mod listing_cache;
 pub mod log;
+pub mod manifest;
+pub mod network;
+pub mod profile;
+pub mod redact;
+pub mod retry;
+pub mod save_location;
+pub mod schedule;
+pub mod settings;
+pub mod sfo;
+pub mod space;
+pub mod staging;
 pub mod tai;
+pub mod throttle;
+pub mod title_source;
+pub mod transfer;
+pub mod trash;
 pub mod ui;
 pub mod utils;
 pub mod vita2d;
@@ -0,0 +1,304 @@
+//! # cloud listing cache
+//!
+//! listings of cloud dirs are kept in `LISTING_CACHE_PATH` by account and path,
+//! a cached listing is shown at once and refreshed in background when it is
+//! stale, and the cached tree is browsed while offline
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::Path,
+    sync::{OnceLock, RwLock},
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accounts::{active_account_name, now},
+    api::DirItem,
+    constant::{LISTING_CACHE_MAX_ENTRIES, LISTING_CACHE_PATH, LISTING_CACHE_TTL_SECS},
+    utils::{get_str_md5, write_file_atomic},
+};
+
+static LISTING_CACHE: OnceLock<RwLock<ListingCache>> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedItem {
+    pub is_dir: bool,
+    pub name: String,
+    pub fs_id: u64,
+}
+
+impl From<DirItem> for CachedItem {
+    fn from(item: DirItem) -> Self {
+        CachedItem {
+            is_dir: item.isdir == 1,
+            name: item.server_filename,
+            fs_id: item.fs_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Listing {
+    pub items: Vec<CachedItem>,
+    /// changes if any item is added, removed or replaced
+    pub tag: String,
+    /// unix time in seconds
+    pub fetched_at: u64,
+    /// changed by this app since fetched
+    #[serde(default)]
+    pub is_invalidated: bool,
+}
+
+impl Listing {
+    pub fn new(items: Vec<CachedItem>, now: u64) -> Listing {
+        Listing {
+            tag: listing_tag(&items),
+            items,
+            fetched_at: now,
+            is_invalidated: false,
+        }
+    }
+
+    pub fn is_stale(&self, now: u64) -> bool {
+        self.is_invalidated || now >= self.fetched_at + LISTING_CACHE_TTL_SECS
+    }
+}
+
+/// # tag of listing
+///
+/// fs id of a file changes when it is replaced, so the tag works like etag
+fn listing_tag(items: &[CachedItem]) -> String {
+    let mut lines = items
+        .iter()
+        .map(|item| format!("{}|{}|{}", item.is_dir, item.name, item.fs_id))
+        .collect::<Vec<String>>();
+    lines.sort();
+    get_str_md5(lines.join("\n").as_bytes())
+}
+
+/// `/a/b/` and `/a/b` are the same dir
+pub fn normalize_cloud_path(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+fn parent_path(path: &str) -> &str {
+    let path = normalize_cloud_path(path);
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(idx) => &path[..idx],
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListingCache {
+    /// listings by `{account}:{path}`
+    listings: HashMap<String, Listing>,
+    /// cloud dir of title by `{account}:{root dir}:{title id}`,
+    /// the dir is searched by api
+    title_dirs: HashMap<String, String>,
+}
+
+impl ListingCache {
+    fn get() -> &'static RwLock<ListingCache> {
+        LISTING_CACHE.get_or_init(|| {
+            let cache = if Path::new(LISTING_CACHE_PATH).exists() {
+                ListingCache::load().unwrap_or_else(|err| {
+                    error!("load listing cache failed: {:?}", err);
+                    ListingCache::default()
+                })
+            } else {
+                ListingCache::default()
+            };
+            RwLock::new(cache)
+        })
+    }
+
+    fn load() -> Result<ListingCache, Box<dyn Error>> {
+        let buf = fs::read(LISTING_CACHE_PATH)?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = Path::new(LISTING_CACHE_PATH).parent() {
+            if !parent.exists() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        write_file_atomic(LISTING_CACHE_PATH, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// update cache, it is saved if `f` returns true
+    fn update<T>(f: impl FnOnce(&mut ListingCache) -> (T, bool)) -> T {
+        let mut cache = Self::get().write().expect("write listing cache");
+        let (res, is_changed) = f(&mut cache);
+        if is_changed {
+            if let Err(err) = cache.save() {
+                error!("save listing cache failed: {:?}", err);
+            }
+        }
+        res
+    }
+
+    fn key(account: &str, path: &str) -> String {
+        format!("{}:{}", account, normalize_cloud_path(path))
+    }
+
+    fn listing(&self, account: &str, path: &str) -> Option<&Listing> {
+        self.listings.get(&Self::key(account, path))
+    }
+
+    /// # put listing
+    ///
+    /// return true if the listing is new or its tag changes,
+    /// the oldest listings are dropped if the cache is full
+    fn put(&mut self, account: &str, path: &str, listing: Listing) -> bool {
+        let key = Self::key(account, path);
+        let is_changed = match self.listings.get(&key) {
+            Some(old) => old.tag != listing.tag,
+            None => true,
+        };
+        self.listings.insert(key, listing);
+        while self.listings.len() > LISTING_CACHE_MAX_ENTRIES {
+            let oldest = self
+                .listings
+                .iter()
+                .min_by_key(|(_, listing)| listing.fetched_at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.listings.remove(&oldest),
+                None => break,
+            };
+        }
+        is_changed
+    }
+
+    /// mark the dir and its parent stale, return true if any is marked
+    fn invalidate(&mut self, account: &str, path: &str) -> bool {
+        let mut is_changed = false;
+        for path in [path, parent_path(path)] {
+            if let Some(listing) = self.listings.get_mut(&Self::key(account, path)) {
+                is_changed |= !listing.is_invalidated;
+                listing.is_invalidated = true;
+            }
+        }
+        is_changed
+    }
+}
+
+/// # cached listing of dir for active account
+pub fn cached_listing(path: &str) -> Option<Listing> {
+    let account = active_account_name()?;
+    ListingCache::get()
+        .read()
+        .expect("read listing cache")
+        .listing(&account, path)
+        .cloned()
+}
+
+/// # cache listing of dir for active account
+///
+/// return true if the listing changes since last fetch
+pub fn store_listing(path: &str, items: Vec<CachedItem>) -> bool {
+    let account = match active_account_name() {
+        Some(account) => account,
+        None => return true,
+    };
+    ListingCache::update(|cache| {
+        let is_changed = cache.put(&account, path, Listing::new(items, now()));
+        (is_changed, is_changed)
+    })
+}
+
+/// # mark listing stale after changing the dir
+///
+/// the parent is marked too, it lists the dir itself
+pub fn invalidate_listing(path: &str) {
+    if let Some(account) = active_account_name() {
+        ListingCache::update(|cache| ((), cache.invalidate(&account, path)));
+    }
+}
+
+/// # cached cloud dir of title
+pub fn cached_title_dir(root_dir: &str, title_id: &str) -> Option<String> {
+    let account = active_account_name()?;
+    ListingCache::get()
+        .read()
+        .expect("read listing cache")
+        .title_dirs
+        .get(&format!("{}:{}:{}", account, root_dir, title_id))
+        .cloned()
+}
+
+pub fn store_title_dir(root_dir: &str, title_id: &str, dir: &str) {
+    if let Some(account) = active_account_name() {
+        ListingCache::update(|cache| {
+            let old = cache.title_dirs.insert(
+                format!("{}:{}:{}", account, root_dir, title_id),
+                dir.to_string(),
+            );
+            ((), old.as_deref() != Some(dir))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constant::{LISTING_CACHE_MAX_ENTRIES, LISTING_CACHE_TTL_SECS};
+
+    use super::{parent_path, CachedItem, Listing, ListingCache};
+
+    fn item(name: &str, fs_id: u64) -> CachedItem {
+        CachedItem {
+            is_dir: false,
+            name: name.to_string(),
+            fs_id,
+        }
+    }
+
+    #[test]
+    fn test_listing_cache() {
+        let mut cache = ListingCache::default();
+        let listing = Listing::new(vec![item("a.zip", 1), item("b.zip", 2)], 1000);
+        assert!(cache.put("u", "/saves/", listing.clone()));
+        assert_eq!(cache.listing("u", "/saves"), Some(&listing));
+        assert_eq!(cache.listing("v", "/saves"), None);
+
+        // same items in other order keep the tag
+        let reordered = Listing::new(vec![item("b.zip", 2), item("a.zip", 1)], 2000);
+        assert!(!cache.put("u", "/saves", reordered));
+        // replaced file changes fs id
+        assert!(cache.put("u", "/saves", Listing::new(vec![item("a.zip", 3)], 3000)));
+
+        let listing = cache.listing("u", "/saves").unwrap();
+        assert!(!listing.is_stale(3000));
+        assert!(listing.is_stale(3000 + LISTING_CACHE_TTL_SECS));
+
+        // parent lists the dir
+        cache.put("u", "/", Listing::new(vec![], 3000));
+        assert!(cache.invalidate("u", "/saves/a"));
+        assert!(cache.listing("u", "/saves").unwrap().is_stale(3000));
+        assert!(!cache.listing("u", "/").unwrap().is_stale(3000));
+        assert!(!cache.invalidate("u", "/saves/a"));
+
+        for idx in 0..LISTING_CACHE_MAX_ENTRIES as u64 {
+            cache.put("u", &format!("/{}", idx), Listing::new(vec![], 4000 + idx));
+        }
+        assert_eq!(cache.listings.len(), LISTING_CACHE_MAX_ENTRIES);
+        assert_eq!(cache.listing("u", "/saves"), None);
+    }
+
+    #[test]
+    fn test_parent_path() {
+        assert_eq!(parent_path("/"), "/");
+        assert_eq!(parent_path("/a"), "/");
+        assert_eq!(parent_path("/a/b/"), "/a");
+    }
+}
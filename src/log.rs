@@ -1,7 +1,9 @@
 //! fork from https://github.com/sweihub/log2
 //! - delete color
 //! - remove chrono
-//! - mask key info
+//! - redact secrets, see `Redactor`
+//! - json lines output with operation id
+//! - gzip rotated files, rotate by time
 //!
 //!# log2
 //!
@@ -104,18 +106,162 @@
 //!log.9.txt
 //!```
 use core::fmt;
+use flate2::{write::GzEncoder, Compression};
 use log::{Level, LevelFilter, Metadata, Record};
-use std::{io::Write, thread::JoinHandle};
+use std::{
+    fs,
+    future::Future,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 /// log macros
 pub use log::{debug, error, info, trace, warn};
 
-use crate::ime::get_current_format_time;
+use crate::{ime::get_current_format_time, redact::Redactor};
 
 /// log levels
 #[allow(non_camel_case_types)]
 pub type level = LevelFilter;
 
+static JSON: AtomicBool = AtomicBool::new(false);
+static NEXT_OPERATION_ID: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    /// operation id of the running task
+    static OPERATION_ID: u64;
+}
+
+/// switch output to json lines or plain text
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+/// # operation
+///
+/// records logged by the future of `run` are tagged with the operation id,
+/// records of other tasks at the same time are not
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operation(u64);
+
+impl Operation {
+    pub fn begin() -> Operation {
+        Operation(NEXT_OPERATION_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+
+    pub fn run<F: Future>(self, future: F) -> impl Future<Output = F::Output> {
+        OPERATION_ID.scope(self.0, future)
+    }
+
+    /// operation of the running task
+    pub fn current() -> Option<Operation> {
+        OPERATION_ID.try_with(|id| Operation(*id)).ok()
+    }
+}
+
+/// # log line
+///
+/// parsed line of log file, both plain text and json lines
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub time: String,
+    pub level: Level,
+    pub module: String,
+    pub op: Option<u64>,
+    pub msg: String,
+}
+
+impl LogLine {
+    fn to_json(&self) -> String {
+        let mut value = serde_json::json!({
+            "time": self.time,
+            "level": self.level.as_str(),
+            "module": self.module,
+            "msg": self.msg,
+        });
+        if let Some(op) = self.op {
+            value["op"] = op.into();
+        }
+        value.to_string()
+    }
+
+    fn to_text(&self) -> String {
+        let module = if self.module.is_empty() {
+            "".to_string()
+        } else {
+            format!("{}: ", self.module)
+        };
+        format!("[{}] [{}] {}{}", self.time, self.level, module, self.msg)
+    }
+
+    /// parse `[time] [level] module: msg` or json line
+    pub fn parse(line: &str) -> Option<LogLine> {
+        if line.starts_with('{') {
+            let value = serde_json::from_str::<serde_json::Value>(line).ok()?;
+            return Some(LogLine {
+                time: value["time"].as_str()?.to_string(),
+                level: Level::from_str(value["level"].as_str()?).ok()?,
+                module: value["module"].as_str().unwrap_or("").to_string(),
+                op: value["op"].as_u64(),
+                msg: value["msg"].as_str()?.to_string(),
+            });
+        }
+        let (time, rest) = line.strip_prefix('[')?.split_once("] [")?;
+        let (level, rest) = rest.split_once("] ")?;
+        let (module, msg) = match rest.split_once(": ") {
+            Some((module, msg)) if !module.contains(' ') => (module, msg),
+            _ => ("", rest),
+        };
+        Some(LogLine {
+            time: time.to_string(),
+            level: Level::from_str(level).ok()?,
+            module: module.to_string(),
+            op: None,
+            msg: msg.to_string(),
+        })
+    }
+}
+
+/// # read log lines
+///
+/// read the last `max_size` bytes of log file,
+/// the lines which can not be parsed are appended to the previous line
+pub fn read_lines(path: impl AsRef<Path>, max_size: u64) -> std::io::Result<Vec<LogLine>> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let start = size.saturating_sub(max_size);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = vec![];
+    file.read_to_end(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+    let mut lines = text.lines();
+    // the first line may be cut
+    if start > 0 {
+        lines.next();
+    }
+    let mut list: Vec<LogLine> = vec![];
+    for line in lines {
+        match LogLine::parse(line) {
+            Some(line) => list.push(line),
+            None => {
+                if let Some(pre) = list.last_mut() {
+                    pre.msg.push('\n');
+                    pre.msg.push_str(line);
+                }
+            }
+        }
+    }
+    Ok(list)
+}
+
 fn get_level(level: String) -> LevelFilter {
     let level = level.to_lowercase();
     match &*level {
@@ -155,7 +301,9 @@ pub struct Log2 {
     module: bool,
     filesize: u64,
     count: usize,
+    interval: Option<Duration>,
     level: String,
+    redactor: Redactor,
 }
 
 struct Context {
@@ -163,6 +311,7 @@ struct Context {
     path: String,
     size: u64,
     count: usize,
+    interval: Option<Duration>,
 }
 
 impl Log2 {
@@ -176,7 +325,9 @@ impl Log2 {
             module: true,
             filesize: 100 * 1024 * 1024,
             count: 10,
+            interval: None,
             level: String::new(),
+            redactor: Redactor::default(),
         }
     }
 
@@ -210,11 +361,29 @@ impl Log2 {
         self
     }
 
+    /// rotate when the period of `interval` is changed, such as one day
+    pub fn interval(mut self, interval: Duration) -> Log2 {
+        self.interval = Some(interval);
+        self
+    }
+
     pub fn level<T: fmt::Display>(mut self, name: T) -> Self {
         self.level = name.to_string();
         self
     }
 
+    /// redact secrets of records before output
+    pub fn redact(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// write json lines instead of plain text
+    pub fn json(self, json: bool) -> Self {
+        set_json(json);
+        self
+    }
+
     /// start the log2 instance
     pub fn start(self) -> Handle {
         let n = self.level.clone();
@@ -245,19 +414,25 @@ impl log::Log for Log2 {
         let mut module = "".into();
         if self.module {
             if file.starts_with("src/") && file.ends_with(".rs") {
-                module = format!("{}: ", &file[4..file.len() - 3]);
+                module = file[4..file.len() - 3].to_string();
             } else {
-                module = format!("{file}: ");
+                module = file.to_string();
             }
         }
 
         if self.tee || self.path.len() > 0 {
-            let line = format!(
-                "[{}] [{}] {module}{}\n",
-                &get_current_format_time() as &str,
-                record.level(),
-                record.args()
-            );
+            let line = LogLine {
+                time: get_current_format_time().to_string(),
+                level: record.level(),
+                module,
+                op: Operation::current().map(|op| op.id()),
+                msg: self.redactor.redact(&record.args().to_string()),
+            };
+            let line = if JSON.load(Ordering::Relaxed) {
+                format!("{}\n", line.to_json())
+            } else {
+                format!("{}\n", line.to_text())
+            };
 
             // stdout
             if self.tee {
@@ -295,8 +470,8 @@ impl Drop for Handle {
     }
 }
 
-fn rotate(ctx: &Context) -> Result<std::fs::File, std::io::Error> {
-    let size = std::fs::metadata(&ctx.path)?.len();
+/// path of rotated file, such as `log.1.txt.gz`
+fn rotated_path(ctx: &Context, i: usize) -> String {
     let dot = ctx.path.rfind(".").unwrap_or(0);
     let mut suffix = "";
     let mut prefix = &ctx.path[..];
@@ -304,16 +479,29 @@ fn rotate(ctx: &Context) -> Result<std::fs::File, std::io::Error> {
         suffix = &ctx.path[dot..];
         prefix = &ctx.path[0..dot];
     }
+    format!("{prefix}.{i}{suffix}.gz")
+}
+
+fn gzip(from: &str, to: &str) -> Result<(), std::io::Error> {
+    let mut encoder = GzEncoder::new(fs::File::create(to)?, Compression::default());
+    io::copy(&mut fs::File::open(from)?, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// rotate if the file is full or `force`, the file should be closed before
+fn rotate(ctx: &Context, force: bool) -> Result<std::fs::File, std::io::Error> {
+    let size = std::fs::metadata(&ctx.path)?.len();
 
-    if size >= ctx.size {
-        for i in (0..ctx.count - 1).rev() {
-            let mut a = format!("{prefix}.{}{suffix}", i);
-            if i == 0 {
-                a = ctx.path.clone();
+    if size > 0 && (size >= ctx.size || force) {
+        if ctx.count > 1 {
+            for i in (1..ctx.count - 1).rev() {
+                let _ = std::fs::rename(rotated_path(ctx, i), rotated_path(ctx, i + 1));
             }
-            let b = format!("{prefix}.{}{suffix}", i + 1);
-            let _ = std::fs::rename(&a, &b);
+            gzip(&ctx.path, &rotated_path(ctx, 1))?;
         }
+        // truncate
+        std::fs::File::create(&ctx.path)?;
     }
 
     let file = std::fs::OpenOptions::new()
@@ -324,6 +512,14 @@ fn rotate(ctx: &Context) -> Result<std::fs::File, std::io::Error> {
     Ok(file)
 }
 
+/// period of time by interval
+fn period(time: SystemTime, interval: Duration) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / interval.as_secs().max(1)
+}
+
 fn now() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -336,8 +532,19 @@ fn worker(ctx: Context) -> Result<(), std::io::Error> {
     let mut size: u64 = 0;
     let mut last = size;
 
+    let mut current_period = ctx
+        .interval
+        .map(|interval| period(SystemTime::now(), interval));
+
     if ctx.path.len() > 0 {
-        let file = rotate(&ctx)?;
+        // rotate the file written in the previous period
+        let is_expired = match (ctx.interval, current_period) {
+            (Some(interval), Some(current_period)) => fs::metadata(&ctx.path)
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|modified| period(modified, interval) != current_period),
+            _ => false,
+        };
+        let file = rotate(&ctx, is_expired)?;
         size = file.metadata()?.len();
         target = Some(file);
     }
@@ -350,34 +557,16 @@ fn worker(ctx: Context) -> Result<(), std::io::Error> {
             match action {
                 Action::Write(line) => {
                     let file = target.as_mut().unwrap();
-                    // mask key info
-                    let line = if let Some(index) = ["access_token", "refresh_token"]
-                        .iter()
-                        .find_map(|s| line.find(s))
-                    {
-                        let mut line = line;
-                        let max_count = 60;
-                        for idx in 0..max_count {
-                            if index + 13 + max_count - idx < line.len() {
-                                line = format!(
-                                    "{}{}{}",
-                                    &line[..index + 13],
-                                    "******",
-                                    &line[index + 13 + max_count - idx..]
-                                );
-                                break;
-                            }
-                        }
-                        line
-                    } else {
-                        line
-                    };
                     let buf = line.as_bytes();
                     file.write_all(buf)?;
                     size += buf.len() as u64;
                     if size >= ctx.size {
-                        let f = rotate(&ctx)?;
+                        file.flush()?;
+                        // close the file before rotate
+                        drop(target.take());
+                        let f = rotate(&ctx, false)?;
                         size = f.metadata()?.len();
+                        last = size;
                         target = Some(f);
                     }
                 }
@@ -399,6 +588,20 @@ fn worker(ctx: Context) -> Result<(), std::io::Error> {
                 }
             }
         }
+        // rotate by time
+        if let (Some(interval), Some(file)) = (ctx.interval, target.as_mut()) {
+            let now_period = period(SystemTime::now(), interval);
+            if current_period != Some(now_period) {
+                current_period = Some(now_period);
+                file.flush()?;
+                // close the file before rotate
+                drop(target.take());
+                let f = rotate(&ctx, true)?;
+                size = f.metadata()?.len();
+                last = size;
+                target = Some(f);
+            }
+        }
         // flush every 1s
         if size > last && target.is_some() {
             let n = now();
@@ -456,6 +659,7 @@ fn start_log2(mut logger: Log2) -> Handle {
         path: logger.path.clone(),
         size: logger.filesize,
         count: logger.count,
+        interval: logger.interval,
     };
 
     let mut handle = Handle {
@@ -476,3 +680,89 @@ fn start_log2(mut logger: Log2) -> Handle {
 
     return handle;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read};
+
+    use flate2::read::GzDecoder;
+    use log::Level;
+
+    use crate::utils::TestDir;
+
+    use super::{rotate, rotated_path, Context, LogLine, Operation};
+
+    #[test]
+    fn test_operation() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let op = Operation::begin();
+        let other = Operation::begin();
+        assert_ne!(op, other);
+        assert_eq!(
+            runtime.block_on(op.run(async { Operation::current() })),
+            Some(op)
+        );
+        assert_eq!(runtime.block_on(async { Operation::current() }), None);
+        assert_eq!(Operation::current(), None);
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let line = LogLine {
+            time: "2024-02-28 12:00:00".to_string(),
+            level: Level::Error,
+            module: "ui/ui_cloud".to_string(),
+            op: Some(3),
+            msg: "upload failed: \"timeout\"".to_string(),
+        };
+        assert_eq!(LogLine::parse(&line.to_json()), Some(line.clone()));
+        assert_eq!(
+            LogLine::parse(&line.to_text()),
+            Some(LogLine { op: None, ..line })
+        );
+        assert_eq!(
+            LogLine::parse("[2024-02-28 12:00:00] [INFO] start app: ok"),
+            Some(LogLine {
+                time: "2024-02-28 12:00:00".to_string(),
+                level: Level::Info,
+                module: "".to_string(),
+                op: None,
+                msg: "start app: ok".to_string(),
+            })
+        );
+        assert_eq!(LogLine::parse("    at src/main.rs"), None);
+    }
+
+    #[test]
+    fn test_rotate() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("log");
+        let ctx = Context {
+            rx: std::sync::mpsc::channel().1,
+            path: dir.join("log.txt").to_str().unwrap().to_string(),
+            size: 4,
+            count: 3,
+            interval: None,
+        };
+        for i in 0..4 {
+            fs::write(&ctx.path, format!("log {}", i))?;
+            rotate(&ctx, false)?;
+            assert_eq!(fs::metadata(&ctx.path)?.len(), 0);
+        }
+        // not full
+        fs::write(&ctx.path, "log")?;
+        rotate(&ctx, false)?;
+        assert_eq!(fs::read_to_string(&ctx.path)?, "log");
+
+        let mut text = String::new();
+        GzDecoder::new(fs::File::open(rotated_path(&ctx, 1))?).read_to_string(&mut text)?;
+        assert_eq!(text, "log 3");
+        let mut text = String::new();
+        GzDecoder::new(fs::File::open(rotated_path(&ctx, 2))?).read_to_string(&mut text)?;
+        assert_eq!(text, "log 2");
+        assert!(!std::path::Path::new(&rotated_path(&ctx, 3)).exists());
+
+        Ok(())
+    }
+}
@@ -1,8 +1,15 @@
+use std::time::Duration;
+
 use log::error;
 use vita_save_cloud::app::App;
+use vita_save_cloud::cloud::start_token_refresh;
 use vita_save_cloud::constant::SAVE_LOG_PATH;
 use vita_save_cloud::log;
+use vita_save_cloud::network::start_network_check;
+use vita_save_cloud::settings::Settings;
+use vita_save_cloud::staging::recover_staging;
 use vita_save_cloud::tai::{tai_init, Titles};
+use vita_save_cloud::trash::purge_trash;
 use vita_save_cloud::vita2d::Vita2dContext;
 
 // stack
@@ -11,11 +18,21 @@ use vita_save_cloud::vita2d::Vita2dContext;
 pub static SCE_USER_MAIN_THREAD_STACK_SIZE: u32 = 1 * 1024 * 1024; // 1 MiB
 
 pub fn main() {
+    let redactor = Settings::read().redactor();
     let _log = log::open(SAVE_LOG_PATH)
         .size(100 * 1024)
         .rotate(10)
+        .interval(Duration::from_secs(60 * 60 * 24))
         .tee(if cfg!(debug_assertions) { true } else { false })
+        .json(Settings::read().json_log)
+        .redact(redactor.clone().unwrap_or_default())
         .start();
+    if let Some(err) = Settings::load_error() {
+        error!("load settings failed: {}", err);
+    }
+    if let Err(err) = redactor {
+        error!("invalid log redact pattern: {}", err);
+    }
 
     tokio::runtime::Builder::new_multi_thread()
         .enable_time()
@@ -37,6 +54,24 @@ pub fn main() {
                 }
             };
 
+            // finish or clean up interrupted backups and restores
+            if let Err(err) = recover_staging() {
+                error!("recover staging failed: {:?}", err);
+            }
+
+            // offline state of cloud tabs
+            start_network_check();
+
+            // refresh access token ahead of expiry
+            start_token_refresh();
+
+            // purge expired items of trash
+            tokio::spawn(async {
+                if let Err(err) = purge_trash() {
+                    error!("purge trash failed: {:?}", err);
+                }
+            });
+
             app.present();
         });
 }
@@ -0,0 +1,180 @@
+//! # backup manifest
+//!
+//! md5 of transferred files are kept in `MANIFEST_PATH` by destination,
+//! cloud paths are kept by account, an entry is verified if the md5 matches
+//! the checksum of server
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::Path,
+    sync::{OnceLock, RwLock},
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accounts::{active_account_name, now},
+    constant::{MANIFEST_MAX_ENTRIES, MANIFEST_PATH},
+    listing_cache::normalize_cloud_path,
+    utils::write_file_atomic,
+};
+
+static MANIFEST: OnceLock<RwLock<Manifest>> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub md5: String,
+    pub size: u64,
+    /// false if the server does not report a usable checksum
+    pub is_verified: bool,
+    /// unix time in seconds
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    /// entries by `{account}:{cloud path}` or local path
+    entries: HashMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    fn get() -> &'static RwLock<Manifest> {
+        MANIFEST.get_or_init(|| {
+            let manifest = if Path::new(MANIFEST_PATH).exists() {
+                Manifest::load().unwrap_or_else(|err| {
+                    error!("load manifest failed: {:?}", err);
+                    Manifest::default()
+                })
+            } else {
+                Manifest::default()
+            };
+            RwLock::new(manifest)
+        })
+    }
+
+    fn load() -> Result<Manifest, Box<dyn Error>> {
+        let buf = fs::read(MANIFEST_PATH)?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = Path::new(MANIFEST_PATH).parent() {
+            if !parent.exists() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        write_file_atomic(MANIFEST_PATH, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    fn update(f: impl FnOnce(&mut Manifest)) {
+        let mut manifest = Self::get().write().expect("write manifest");
+        f(&mut manifest);
+        if let Err(err) = manifest.save() {
+            error!("save manifest failed: {:?}", err);
+        }
+    }
+
+    /// cloud paths start with `/`
+    fn key(account: Option<&str>, path: &str) -> Option<String> {
+        if path.starts_with('/') {
+            Some(format!("{}:{}", account?, normalize_cloud_path(path)))
+        } else {
+            Some(path.to_string())
+        }
+    }
+
+    /// the oldest entries are dropped if the manifest is full
+    fn put(&mut self, key: String, entry: ManifestEntry) {
+        self.entries.insert(key, entry);
+        while self.entries.len() > MANIFEST_MAX_ENTRIES {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.updated_at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.entries.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+}
+
+/// # record md5 of transferred file
+///
+/// `path` is the destination, a cloud path is kept for active account
+pub fn record_md5(path: &str, md5: &str, size: u64, is_verified: bool) {
+    let account = active_account_name();
+    if let Some(key) = Manifest::key(account.as_deref(), path) {
+        let entry = ManifestEntry {
+            md5: md5.to_string(),
+            size,
+            is_verified,
+            updated_at: now(),
+        };
+        Manifest::update(|manifest| manifest.put(key, entry));
+    }
+}
+
+/// # forget file which fails the check
+pub fn forget_md5(path: &str) {
+    let account = active_account_name();
+    if let Some(key) = Manifest::key(account.as_deref(), path) {
+        Manifest::update(|manifest| {
+            manifest.entries.remove(&key);
+        });
+    }
+}
+
+/// # manifest entry of file
+pub fn manifest_entry(path: &str) -> Option<ManifestEntry> {
+    let account = active_account_name();
+    let key = Manifest::key(account.as_deref(), path)?;
+    Manifest::get()
+        .read()
+        .expect("read manifest")
+        .entries
+        .get(&key)
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constant::MANIFEST_MAX_ENTRIES;
+
+    use super::{Manifest, ManifestEntry};
+
+    fn entry(updated_at: u64) -> ManifestEntry {
+        ManifestEntry {
+            md5: "e10adc3949ba59abbe56e057f20f883e".to_string(),
+            size: 6,
+            is_verified: true,
+            updated_at,
+        }
+    }
+
+    #[test]
+    fn test_manifest() {
+        assert_eq!(
+            Manifest::key(Some("u"), "/saves/a.zip/"),
+            Some("u:/saves/a.zip".to_string())
+        );
+        assert_eq!(Manifest::key(None, "/saves/a.zip"), None);
+        assert_eq!(
+            Manifest::key(None, "ux0:data/a.zip"),
+            Some("ux0:data/a.zip".to_string())
+        );
+
+        let mut manifest = Manifest::default();
+        manifest.put("u:/a.zip".to_string(), entry(1));
+        for idx in 0..MANIFEST_MAX_ENTRIES as u64 {
+            manifest.put(format!("u:/{}", idx), entry(2 + idx));
+        }
+        assert_eq!(manifest.entries.len(), MANIFEST_MAX_ENTRIES);
+        assert!(!manifest.entries.contains_key("u:/a.zip"));
+    }
+}
@@ -0,0 +1,64 @@
+//! # network availability
+//!
+//! the connection state is checked in background,
+//! cloud tabs show offline state instead of failing every call
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use log::info;
+
+use crate::{constant::NETWORK_CHECK_INTERVAL_SECS, tai::get_psv_net_state};
+
+// SCE_NETCTL_STATE_CONNECTED
+const NET_STATE_CONNECTED: i32 = 3;
+
+static ONLINE: AtomicBool = AtomicBool::new(true);
+
+/// is network connected, true if the state is unknown
+pub fn is_online() -> bool {
+    ONLINE.load(Ordering::Relaxed)
+}
+
+fn is_connected(state: Option<i32>) -> bool {
+    match state {
+        Some(state) => state == NET_STATE_CONNECTED,
+        None => true,
+    }
+}
+
+/// # update network state now
+pub fn check_network() -> bool {
+    let online = is_connected(get_psv_net_state());
+    if ONLINE.swap(online, Ordering::Relaxed) != online {
+        info!("network is {}", if online { "online" } else { "offline" });
+    }
+    online
+}
+
+/// # check network in background
+///
+/// call it once at startup
+pub fn start_network_check() {
+    check_network();
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(Duration::from_secs(NETWORK_CHECK_INTERVAL_SECS)).await;
+            check_network();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_connected;
+
+    #[test]
+    fn test_is_connected() {
+        assert!(is_connected(None));
+        assert!(is_connected(Some(3)));
+        assert!(!is_connected(Some(0)));
+        assert!(!is_connected(Some(2)));
+    }
+}
@@ -0,0 +1,224 @@
+//! # backup profile
+//!
+//! homebrew and other data outside of savedata, defined in `profiles` of settings
+//! or added in game menu, they are listed as titles and backed up like game saves,
+//! files of backup are only restored under `paths` of the profile
+//!
+//! ```json
+//! {
+//!     "id": "RETROARCH",
+//!     "name": "RetroArch",
+//!     "paths": ["ux0:data/retroarch/saves", "ux0:data/retroarch/states/**/*.state*"],
+//!     "excludes": ["**/*.tmp"],
+//!     "icon": ""
+//! }
+//! ```
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constant::PSV_DEVICES,
+    glob::{is_glob, is_match},
+    settings::Settings,
+};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// used as title id, fallback to name
+    pub id: String,
+    pub name: String,
+    /// files, dirs or globs
+    pub paths: Vec<String>,
+    /// globs of excluded files
+    pub excludes: Vec<String>,
+    /// png icon path
+    pub icon: String,
+}
+
+impl Profile {
+    pub fn title_id(&self) -> &str {
+        if self.id.is_empty() {
+            &self.name
+        } else {
+            &self.id
+        }
+    }
+
+    /// all paths are on devices
+    pub fn is_valid(&self) -> bool {
+        !self.title_id().is_empty()
+            && !self.paths.is_empty()
+            && self.paths.iter().all(|path| split_device(path).is_some())
+    }
+
+    /// is any base dir of paths exists
+    pub fn exists(&self) -> bool {
+        self.paths
+            .iter()
+            .any(|path| Path::new(&get_base_dir(path)).exists())
+    }
+
+    /// # is file under paths
+    ///
+    /// in dir of paths or matched by glob of paths, `.` and `..` are not allowed
+    pub fn contains(&self, file: &str) -> bool {
+        if file.split('/').any(|name| name == "." || name == "..") {
+            return false;
+        }
+        self.paths.iter().any(|path| {
+            if is_glob(path) {
+                is_match(path, file)
+            } else {
+                let path = path.trim_end_matches('/');
+                file == path
+                    || file
+                        .strip_prefix(path)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            }
+        })
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.excludes.iter().any(|pattern| is_match(pattern, path))
+    }
+
+    /// files of profile, without excluded files
+    pub fn files(&self) -> io::Result<Vec<String>> {
+        let mut files = vec![];
+        for path in &self.paths {
+            let base_dir = get_base_dir(path);
+            let base = Path::new(&base_dir);
+            if !base.exists() {
+                continue;
+            }
+            let mut list = vec![];
+            if base.is_dir() {
+                walk_files(base, &mut list)?;
+            } else {
+                list.push(base.to_path_buf());
+            }
+            for file in list {
+                let file = match file.to_str() {
+                    Some(file) => file.to_string(),
+                    None => continue,
+                };
+                if (!is_glob(path) || is_match(path, &file))
+                    && !self.is_excluded(&file)
+                    && !files.contains(&file)
+                {
+                    files.push(file);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// # get profile by title id
+pub fn get_profile(title_id: &str) -> Option<Profile> {
+    Settings::read()
+        .profiles
+        .iter()
+        .find(|profile| profile.title_id() == title_id)
+        .cloned()
+}
+
+/// split path into device and rest, such as `ux0:` and `data/a`
+fn split_device(path: &str) -> Option<(&str, &str)> {
+    PSV_DEVICES
+        .iter()
+        .find(|device| path.starts_with(*device))
+        .map(|device| path.split_at(device.len()))
+}
+
+/// leading dirs of path without glob chars
+fn get_base_dir(path: &str) -> String {
+    path.split('/')
+        .take_while(|name| !is_glob(name))
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+fn walk_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// # zip entry name of file
+///
+/// `ux0:data/a` to `ux0/data/a`
+pub fn to_entry_name(path: &str) -> Option<String> {
+    let (device, rest) = split_device(path)?;
+    Some(format!(
+        "{}/{}",
+        device.trim_end_matches(':'),
+        rest.trim_start_matches('/')
+    ))
+}
+
+/// # file of zip entry name
+///
+/// `ux0/data/a` to `ux0:data/a`
+pub fn from_entry_name(name: &str) -> Option<String> {
+    let (device, rest) = name.split_once('/')?;
+    let device = format!("{}:", device);
+    if PSV_DEVICES.contains(&device.as_str()) {
+        Some(format!("{}{}", device, rest))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_entry_name, get_base_dir, to_entry_name, Profile};
+
+    #[test]
+    fn test_entry_name() {
+        assert_eq!(
+            to_entry_name("ux0:data/retroarch/a.srm").as_deref(),
+            Some("ux0/data/retroarch/a.srm")
+        );
+        assert_eq!(to_entry_name("/tmp/a"), None);
+        assert_eq!(
+            from_entry_name("ux0/data/retroarch/a.srm").as_deref(),
+            Some("ux0:data/retroarch/a.srm")
+        );
+        assert_eq!(from_entry_name("xx0/data/a"), None);
+        assert_eq!(
+            get_base_dir("ux0:data/retroarch/**/*.srm"),
+            "ux0:data/retroarch"
+        );
+        assert_eq!(get_base_dir("ux0:data/retroarch"), "ux0:data/retroarch");
+    }
+
+    #[test]
+    fn test_contains() {
+        let profile = Profile {
+            paths: vec![
+                "ux0:data/retroarch/saves".to_string(),
+                "ux0:data/retroarch/states/**/*.state*".to_string(),
+            ],
+            ..Profile::default()
+        };
+        assert!(profile.contains("ux0:data/retroarch/saves/a.srm"));
+        assert!(profile.contains("ux0:data/retroarch/states/snes/a.state1"));
+        assert!(!profile.contains("ux0:data/retroarch/saves2/a.srm"));
+        assert!(!profile.contains("ux0:data/retroarch/states/snes/a.png"));
+        assert!(!profile.contains("ux0:data/retroarch/saves/../../a"));
+        assert!(!profile.contains("ur0:tai/config.txt"));
+    }
+}
@@ -0,0 +1,184 @@
+//! # secret redaction
+//!
+//! replace secrets in text with `******`, rules are key names or regex,
+//! key name rules cover query string, json fields, debug output and headers
+//!
+//! ```text
+//! ?access_token=abc&a=1        ->  ?access_token=******&a=1
+//! {"refresh_token": "abc"}     ->  {"refresh_token": "******"}
+//! access_token: Some("abc")    ->  access_token: Some("******")
+//! Authorization: Bearer abc    ->  Authorization: Bearer ******
+//! ```
+use regex_lite::{Captures, Regex};
+
+const MASK: &str = "******";
+
+/// secret keys redacted by default
+pub const DEFAULT_REDACT_KEYS: [&str; 7] = [
+    "access_token",
+    "refresh_token",
+    "client_secret",
+    "client_id",
+    "device_code",
+    "password",
+    "passphrase",
+];
+
+/// authorization header, the scheme such as `Bearer` is kept
+const AUTHORIZATION_PATTERN: &str =
+    r#"(?i)authorization["']?\s*[:=]\s*["']?(?:(?:bearer|basic|token)\s+)?(?P<secret>[^"'\s,;&]+)"#;
+
+/// # redactor
+///
+/// the secret of a regex rule is the named group `secret` if it exists,
+/// otherwise the whole match
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    rules: Vec<Regex>,
+}
+
+impl Default for Redactor {
+    /// default keys and authorization header
+    fn default() -> Self {
+        let redactor = Redactor::new().pattern(AUTHORIZATION_PATTERN).unwrap();
+        DEFAULT_REDACT_KEYS
+            .iter()
+            .fold(redactor, |redactor, key| redactor.key(key))
+    }
+}
+
+impl Redactor {
+    /// redactor without rules
+    pub fn new() -> Redactor {
+        Redactor { rules: vec![] }
+    }
+
+    /// # add key name rule
+    ///
+    /// values of `key=value`, `"key": "value"` and `key: Some("value")`
+    pub fn key(mut self, key: &str) -> Redactor {
+        let pattern = format!(
+            r#"(?i)(?:^|[^\w])["']?{}["']?\s*[:=]\s*(?:Some\()?["']?(?P<secret>[^"'\s&,;)}}\]]+)"#,
+            regex_lite::escape(key)
+        );
+        self.rules
+            .push(Regex::new(&pattern).expect("regex of redact key"));
+        self
+    }
+
+    /// add regex rule
+    pub fn pattern(mut self, pattern: &str) -> Result<Redactor, regex_lite::Error> {
+        self.rules.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            if !rule.is_match(&text) {
+                continue;
+            }
+            text = rule
+                .replace_all(&text, |caps: &Captures| {
+                    let all = caps.get(0).expect("match of redact rule");
+                    match caps.name("secret") {
+                        Some(secret) => format!(
+                            "{}{}{}",
+                            &text[all.start()..secret.start()],
+                            MASK,
+                            &text[secret.end()..all.end()]
+                        ),
+                        None => MASK.to_string(),
+                    }
+                })
+                .to_string();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Redactor;
+
+    #[test]
+    fn test_query_string() {
+        let redactor = Redactor::default();
+        assert_eq!(
+            redactor.redact("GET https://a.com/oauth?access_token=abc.123-x&method=list"),
+            "GET https://a.com/oauth?access_token=******&method=list"
+        );
+        assert_eq!(
+            redactor.redact("refresh?grant_type=refresh_token&refresh_token=122.abc&client_id=xyz"),
+            "refresh?grant_type=refresh_token&refresh_token=******&client_id=******"
+        );
+        assert_eq!(
+            redactor.redact("a?device_code=1234 b?DEVICE_CODE=5678"),
+            "a?device_code=****** b?DEVICE_CODE=******"
+        );
+    }
+
+    #[test]
+    fn test_json_and_debug() {
+        let redactor = Redactor::default();
+        assert_eq!(
+            redactor.redact(
+                r#"{"expires_in":2592000,"refresh_token":"122.abc","access_token": "121.def"}"#
+            ),
+            r#"{"expires_in":2592000,"refresh_token":"******","access_token": "******"}"#
+        );
+        assert_eq!(
+            redactor.redact(r#"TokenRes { access_token: Some("121.def"), expires_in: Some(30) }"#),
+            r#"TokenRes { access_token: Some("******"), expires_in: Some(30) }"#
+        );
+        assert_eq!(
+            redactor.redact(r#"{'password': 'p@ss', "passphrase":"密码"}"#),
+            r#"{'password': '******', "passphrase":"******"}"#
+        );
+    }
+
+    #[test]
+    fn test_authorization() {
+        let redactor = Redactor::default();
+        assert_eq!(
+            redactor.redact("Authorization: Bearer eyJhbGciOi.abc"),
+            "Authorization: Bearer ******"
+        );
+        assert_eq!(
+            redactor.redact(r#"headers: {"authorization": "Basic dXNlcjpwYXNz"}"#),
+            r#"headers: {"authorization": "Basic ******"}"#
+        );
+        assert_eq!(redactor.redact("authorization=abc"), "authorization=******");
+    }
+
+    #[test]
+    fn test_not_redacted() {
+        let redactor = Redactor::default();
+        for text in [
+            "upload 3 files, token_count=3",
+            "my_access_token_len=10",
+            "zip ux0:data/save-cloud/saves/PCSG00001 device_code.bin",
+            "access_token",
+        ] {
+            assert_eq!(redactor.redact(text), text);
+        }
+        assert_eq!(
+            Redactor::new().redact("access_token=abc"),
+            "access_token=abc"
+        );
+    }
+
+    #[test]
+    fn test_custom_rules() -> Result<(), Box<dyn std::error::Error>> {
+        let redactor = Redactor::new()
+            .key("uk")
+            .pattern(r"\d{3}-\d{4}")?
+            .pattern(r"sign=(?P<secret>[0-9a-f]+)")?;
+        assert_eq!(
+            redactor.redact("uk=123456 phone 555-1234 sign=deadbeef&x=1"),
+            "uk=****** phone ****** sign=******&x=1"
+        );
+        assert!(Redactor::new().pattern("(").is_err());
+        Ok(())
+    }
+}
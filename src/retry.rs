@@ -0,0 +1,193 @@
+//! # retry policy
+//!
+//! failed calls are retried with exponential backoff and jitter,
+//! only retry the calls which are safe to repeat
+use std::{fmt::Display, thread, time::Duration};
+
+use log::warn;
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    task, time,
+};
+
+use crate::utils::current_time;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// attempts including the first one
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// # delay before next attempt
+    ///
+    /// `base * 2^attempt` capped by `max_delay`, half of it is fixed and
+    /// the other half is scaled by `jitter` in `[0, 1)`
+    pub fn delay(&self, attempt: u32, jitter: f64) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        backoff / 2 + backoff.mul_f64(jitter.clamp(0.0, 1.0) / 2.0)
+    }
+
+    /// # run `f` until it succeeds
+    ///
+    /// `f` gets the index of attempt, stop retrying if the error is not
+    /// retryable or attempts are used up
+    pub fn run<T, E: Display>(
+        &self,
+        name: &str,
+        mut f: impl FnMut(u32) -> Result<T, E>,
+        is_retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut attempt = 0;
+        loop {
+            match f(attempt) {
+                Err(err) if attempt + 1 < self.max_attempts && is_retryable(&err) => {
+                    let delay = self.delay(attempt, jitter());
+                    warn!(
+                        "{} failed at attempt {}, retry in {:?}: {}",
+                        name,
+                        attempt + 1,
+                        delay,
+                        err
+                    );
+                    backoff(delay);
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// # wait in blocking code
+///
+/// the worker of tokio is handed to other tasks while waiting
+pub fn backoff(delay: Duration) {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            task::block_in_place(|| handle.block_on(time::sleep(delay)))
+        }
+        // out of runtime, or no other worker to hand over
+        _ => thread::sleep(delay),
+    }
+}
+
+/// pseudo random in `[0, 1)`, good enough to spread retries
+fn jitter() -> f64 {
+    let mut x = current_time() as u64 | 1;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    (x % 1000) as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.delay(0, 1.0), Duration::from_secs(1));
+        assert_eq!(policy.delay(2, 0.5), Duration::from_secs(3));
+        // capped
+        assert_eq!(policy.delay(10, 1.0), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX, 0.0), Duration::from_secs(15));
+    }
+
+    #[test]
+    fn test_run() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        };
+        let mut attempts = vec![];
+        let res = policy.run(
+            "test",
+            |attempt| {
+                attempts.push(attempt);
+                if attempt < 2 {
+                    Err("timeout")
+                } else {
+                    Ok(attempt)
+                }
+            },
+            |_| true,
+        );
+        assert_eq!((res, &attempts[..]), (Ok(2), &[0, 1, 2][..]));
+
+        // attempts are used up
+        let mut count = 0;
+        let res: Result<(), _> = policy.run(
+            "test",
+            |_| {
+                count += 1;
+                Err("timeout")
+            },
+            |_| true,
+        );
+        assert_eq!((res, count), (Err("timeout"), 3));
+
+        // not retryable
+        let mut count = 0;
+        let res: Result<(), _> = policy.run(
+            "test",
+            |_| {
+                count += 1;
+                Err("exists")
+            },
+            |err| *err != "exists",
+        );
+        assert_eq!((res, count), (Err("exists"), 1));
+    }
+
+    #[test]
+    fn test_run_in_task() {
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        };
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let res = runtime.block_on(async move {
+            tokio::spawn(async move {
+                policy.run(
+                    "test",
+                    |attempt| {
+                        if attempt == 0 {
+                            Err("timeout")
+                        } else {
+                            Ok(attempt)
+                        }
+                    },
+                    |_| true,
+                )
+            })
+            .await
+            .unwrap()
+        });
+        assert_eq!(res, Ok(1));
+    }
+}
@@ -0,0 +1,139 @@
+//! # save location
+//!
+//! where the game save of a title is kept, only vita saves are dirs encrypted
+//! by pfs, psp saves are several dirs and profiles are files on devices
+use std::{fmt, fs, io, path::Path};
+
+use crate::{
+    constant::{PROFILE_SAVE_DIR_PREFIX, PSP_SAVE_DIR},
+    profile::{get_profile, Profile},
+    tai::mount_pfs,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveLocation {
+    /// save dir of vita game
+    Vita(String),
+    /// save dirs in `parent` start with game id
+    Psp { parent: String, game_id: String },
+    /// files of backup profile by title id
+    Profile(String),
+}
+
+impl SaveLocation {
+    pub fn psp(game_id: &str) -> SaveLocation {
+        SaveLocation::Psp {
+            parent: PSP_SAVE_DIR.to_string(),
+            game_id: game_id.to_string(),
+        }
+    }
+
+    /// # parse location of `to_string`
+    ///
+    /// such as the destination of journal
+    pub fn parse(path: &str) -> SaveLocation {
+        if let Some(title_id) = path.strip_prefix(PROFILE_SAVE_DIR_PREFIX) {
+            return SaveLocation::Profile(title_id.to_string());
+        }
+        match path
+            .strip_suffix('*')
+            .and_then(|path| path.rsplit_once('/'))
+        {
+            Some((parent, game_id)) => SaveLocation::Psp {
+                parent: parent.to_string(),
+                game_id: game_id.to_string(),
+            },
+            None => SaveLocation::Vita(path.to_string()),
+        }
+    }
+
+    /// dir of vita save, `None` for others
+    pub fn vita_dir(&self) -> Option<&str> {
+        match self {
+            SaveLocation::Vita(dir) => Some(dir),
+            _ => None,
+        }
+    }
+
+    pub fn profile(&self) -> Option<Profile> {
+        match self {
+            SaveLocation::Profile(title_id) => get_profile(title_id),
+            _ => None,
+        }
+    }
+
+    /// # mount pfs of vita save
+    ///
+    /// files of vita save are written through pfs, others are plain files
+    pub fn mount(&self) -> i32 {
+        match self {
+            SaveLocation::Vita(dir) => mount_pfs(dir),
+            _ => 0,
+        }
+    }
+
+    /// # names of psp save dirs
+    ///
+    /// empty for others
+    pub fn psp_dirs(&self) -> io::Result<Vec<String>> {
+        let (parent, game_id) = match self {
+            SaveLocation::Psp { parent, game_id } => (parent, game_id),
+            _ => return Ok(vec![]),
+        };
+        if !Path::new(parent).exists() {
+            return Ok(vec![]);
+        }
+        let mut dirs = vec![];
+        for entry in fs::read_dir(parent)? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                if let Some(name) = entry.file_name().to_str() {
+                    if name.starts_with(game_id.as_str()) {
+                        dirs.push(name.to_string());
+                    }
+                }
+            }
+        }
+        dirs.sort();
+        Ok(dirs)
+    }
+}
+
+/// `{parent}/{game id}*` for psp save, `profile:{title id}` for profile
+impl fmt::Display for SaveLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveLocation::Vita(dir) => write!(f, "{}", dir),
+            SaveLocation::Psp { parent, game_id } => write!(f, "{}/{}*", parent, game_id),
+            SaveLocation::Profile(title_id) => write!(f, "{}{}", PROFILE_SAVE_DIR_PREFIX, title_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SaveLocation;
+
+    #[test]
+    fn test_parse() {
+        for (path, location) in [
+            (
+                "ux0:user/00/savedata/PCSG00001",
+                SaveLocation::Vita("ux0:user/00/savedata/PCSG00001".to_string()),
+            ),
+            (
+                "ux0:pspemu/PSP/SAVEDATA/ULJM05800*",
+                SaveLocation::psp("ULJM05800"),
+            ),
+            (
+                "profile:RETROARCH",
+                SaveLocation::Profile("RETROARCH".to_string()),
+            ),
+        ] {
+            assert_eq!(SaveLocation::parse(path), location);
+            assert_eq!(location.to_string(), path);
+        }
+        assert_eq!(SaveLocation::psp("ULJM05800").vita_dir(), None);
+        assert_eq!(SaveLocation::psp("ULJM05800").mount(), 0);
+    }
+}
@@ -0,0 +1,101 @@
+//! # upload window
+//!
+//! queued uploads can be deferred until the vita is charging or idle,
+//! idle means no buttons are pressed for `UPLOAD_IDLE_SECS`,
+//! the wait is canceled by cross or after `UPLOAD_WINDOW_TIMEOUT_SECS`
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::info;
+
+use crate::{
+    accounts::now,
+    constant::{UPLOAD_IDLE_SECS, UPLOAD_WINDOW_CHECK_SECS, UPLOAD_WINDOW_TIMEOUT_SECS},
+    retry::backoff,
+    settings::Settings,
+    tai::is_psv_charging,
+    vita2d::{is_button, SceCtrlButtons},
+};
+
+// unix time in seconds, 0 if no buttons are pressed since started
+static LAST_INPUT_AT: AtomicU64 = AtomicU64::new(0);
+static IS_WAITING: AtomicBool = AtomicBool::new(false);
+static IS_CANCELED: AtomicBool = AtomicBool::new(false);
+
+/// # call it when buttons are pressed
+///
+/// cross cancels the wait of upload window
+pub fn touch_input(buttons: u32) {
+    LAST_INPUT_AT.store(now(), Ordering::Relaxed);
+    if IS_WAITING.load(Ordering::Relaxed) && is_button(buttons, SceCtrlButtons::SceCtrlCross) {
+        IS_CANCELED.store(true, Ordering::Relaxed);
+    }
+}
+
+fn is_idle(last_input_at: u64, now: u64) -> bool {
+    now.saturating_sub(last_input_at) >= UPLOAD_IDLE_SECS
+}
+
+/// # can queued uploads start now
+pub fn is_upload_window() -> bool {
+    !Settings::read().defer_uploads
+        || is_psv_charging()
+        || is_idle(LAST_INPUT_AT.load(Ordering::Relaxed), now())
+}
+
+/// # wait until queued uploads can start
+///
+/// `on_wait` is called once if it needs to wait,
+/// return false if the wait is canceled or timed out
+pub fn wait_upload_window(on_wait: impl FnOnce()) -> bool {
+    if is_upload_window() {
+        return true;
+    }
+    info!("defer uploads until charging or idle");
+    on_wait();
+    IS_CANCELED.store(false, Ordering::Relaxed);
+    IS_WAITING.store(true, Ordering::Relaxed);
+    let started_at = now();
+    let is_open = loop {
+        if is_upload_window() {
+            info!("upload window is open");
+            break true;
+        }
+        if IS_CANCELED.load(Ordering::Relaxed) {
+            info!("wait of upload window is canceled");
+            break false;
+        }
+        if is_timed_out(started_at, now()) {
+            info!("wait of upload window is timed out");
+            break false;
+        }
+        backoff(Duration::from_secs(UPLOAD_WINDOW_CHECK_SECS));
+    };
+    IS_WAITING.store(false, Ordering::Relaxed);
+    is_open
+}
+
+fn is_timed_out(started_at: u64, now: u64) -> bool {
+    now.saturating_sub(started_at) >= UPLOAD_WINDOW_TIMEOUT_SECS
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constant::{UPLOAD_IDLE_SECS, UPLOAD_WINDOW_TIMEOUT_SECS};
+
+    use super::{is_idle, is_timed_out};
+
+    #[test]
+    fn test_is_idle() {
+        let now = 1_000_000;
+        assert!(is_idle(0, now));
+        assert!(is_idle(now - UPLOAD_IDLE_SECS, now));
+        assert!(!is_idle(now - UPLOAD_IDLE_SECS + 1, now));
+        assert!(!is_idle(now + 10, now));
+
+        assert!(!is_timed_out(now, now + UPLOAD_WINDOW_TIMEOUT_SECS - 1));
+        assert!(is_timed_out(now, now + UPLOAD_WINDOW_TIMEOUT_SECS));
+    }
+}
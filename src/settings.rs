@@ -0,0 +1,263 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::Path,
+    sync::{OnceLock, RwLock, RwLockReadGuard},
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+use zip::{write::FileOptions, CompressionMethod};
+
+use crate::{
+    constant::{
+        BACKUP_BLACK_LIST, SAVE_SFO_PATHS, SETTINGS_PATH, TRANSFER_MAX_PARALLELISM,
+        TRASH_KEEP_DAYS, TRASH_MAX_SIZE_MB,
+    },
+    glob::PathFilter,
+    profile::Profile,
+    redact::Redactor,
+    utils::write_file_atomic,
+};
+
+static SETTINGS: OnceLock<RwLock<Settings>> = OnceLock::new();
+/// error of loading settings, see `Settings::load_error`
+static LOAD_ERROR: OnceLock<String> = OnceLock::new();
+
+/// # compression of backup
+///
+/// all of them are zip, restore detects the method of each entry
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    Store,
+    DeflateFast,
+    #[default]
+    Deflate,
+    DeflateBest,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 4] = [
+        Compression::Store,
+        Compression::DeflateFast,
+        Compression::Deflate,
+        Compression::DeflateBest,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Store => "不压缩",
+            Compression::DeflateFast => "快速压缩",
+            Compression::Deflate => "标准压缩",
+            Compression::DeflateBest => "最大压缩",
+        }
+    }
+
+    pub fn next(&self) -> Compression {
+        let idx = Compression::ALL.iter().position(|c| c == self).unwrap_or(0);
+        Compression::ALL[(idx + 1) % Compression::ALL.len()]
+    }
+
+    pub fn zip_options(&self) -> FileOptions {
+        let options = FileOptions::default();
+        match self {
+            Compression::Store => options.compression_method(CompressionMethod::Stored),
+            Compression::DeflateFast => options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(1)),
+            Compression::Deflate => options.compression_method(CompressionMethod::Deflated),
+            Compression::DeflateBest => options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(9)),
+        }
+    }
+}
+
+/// # backup rules
+///
+/// globs of zip entries, such as `**/*.tmp` and `cache/**`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupRules {
+    /// excluded files and dirs
+    pub excludes: Vec<String>,
+    /// back up included files only if it is not empty
+    pub includes: Vec<String>,
+}
+
+impl BackupRules {
+    /// # path filter of rules
+    ///
+    /// `BACKUP_BLACK_LIST` is always excluded and `param.sfo` is always included
+    pub fn filter(&self) -> PathFilter {
+        let excludes = BACKUP_BLACK_LIST
+            .iter()
+            .map(|path| path.to_string())
+            .chain(self.excludes.iter().cloned())
+            .collect();
+        let mut includes = self.includes.clone();
+        if !includes.is_empty() {
+            // psp save dirs are in the root of zip
+            includes.extend(SAVE_SFO_PATHS.iter().map(|path| format!("**/{}", path)));
+        }
+        PathFilter::new(excludes, includes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.excludes.is_empty() && self.includes.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub compression: Compression,
+    /// compression of games, key is title id
+    pub game_compressions: HashMap<String, Compression>,
+    pub backup_rules: BackupRules,
+    /// backup rules of games instead of global rules, key is title id
+    pub game_backup_rules: HashMap<String, BackupRules>,
+    /// backup profiles of homebrews and other data
+    pub profiles: Vec<Profile>,
+    /// write log as json lines
+    pub json_log: bool,
+    /// redact values of these keys in log, besides `DEFAULT_REDACT_KEYS`
+    pub log_redact_keys: Vec<String>,
+    /// redact regex in log, see `Redactor`
+    pub log_redact_patterns: Vec<String>,
+    /// days to keep items in trash, `TRASH_KEEP_DAYS` if none, 0 is unlimited
+    pub trash_keep_days: Option<u64>,
+    /// max size of trash in MB, `TRASH_MAX_SIZE_MB` if none, 0 is unlimited
+    pub trash_max_size_mb: Option<u64>,
+    /// upload rate limit in KiB/s, none or 0 is unlimited
+    pub upload_limit_kb: Option<u64>,
+    /// download rate limit in KiB/s, none or 0 is unlimited
+    pub download_limit_kb: Option<u64>,
+    /// start queued uploads only if charging or idle
+    pub defer_uploads: bool,
+    /// connections of a transfer by slices, one at a time if none or 1,
+    /// the api streams a file only without an active account
+    pub transfer_parallelism: Option<usize>,
+}
+
+impl Settings {
+    fn get() -> &'static RwLock<Settings> {
+        SETTINGS.get_or_init(|| {
+            let settings = if Path::new(SETTINGS_PATH).exists() {
+                Settings::load().unwrap_or_else(|err| {
+                    // settings are loaded before the logger starts, see `load_error`
+                    let _ = LOAD_ERROR.set(format!("{:?}", err));
+                    Settings::default()
+                })
+            } else {
+                Settings::default()
+            };
+            RwLock::new(settings)
+        })
+    }
+
+    /// # error of loading settings
+    ///
+    /// logged by main after the logger starts
+    pub fn load_error() -> Option<&'static str> {
+        Self::get();
+        LOAD_ERROR.get().map(|err| err.as_str())
+    }
+
+    fn load() -> Result<Settings, Box<dyn Error>> {
+        let buf = fs::read(SETTINGS_PATH)?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = Path::new(SETTINGS_PATH).parent() {
+            if !parent.exists() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        write_file_atomic(SETTINGS_PATH, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn read() -> RwLockReadGuard<'static, Settings> {
+        Self::get().read().expect("read settings")
+    }
+
+    /// update settings and save to file
+    pub fn update(f: impl FnOnce(&mut Settings)) {
+        let mut settings = Self::get().write().expect("write settings");
+        f(&mut settings);
+        if let Err(err) = settings.save() {
+            error!("save settings failed: {:?}", err);
+        }
+    }
+
+    /// redactor of log with default rules and rules in settings
+    pub fn redactor(&self) -> Result<Redactor, regex_lite::Error> {
+        let redactor = self
+            .log_redact_keys
+            .iter()
+            .fold(Redactor::default(), |redactor, key| redactor.key(key));
+        self.log_redact_patterns
+            .iter()
+            .try_fold(redactor, |redactor, pattern| redactor.pattern(pattern))
+    }
+
+    pub fn trash_keep_days(&self) -> u64 {
+        self.trash_keep_days.unwrap_or(TRASH_KEEP_DAYS)
+    }
+
+    /// max size of trash in bytes
+    pub fn trash_max_size(&self) -> u64 {
+        self.trash_max_size_mb.unwrap_or(TRASH_MAX_SIZE_MB) * 1024 * 1024
+    }
+
+    /// upload rate limit in bytes per second
+    pub fn upload_limit(&self) -> Option<u64> {
+        self.upload_limit_kb
+            .filter(|kb| *kb > 0)
+            .map(|kb| kb * 1024)
+    }
+
+    /// download rate limit in bytes per second
+    pub fn download_limit(&self) -> Option<u64> {
+        self.download_limit_kb
+            .filter(|kb| *kb > 0)
+            .map(|kb| kb * 1024)
+    }
+
+    /// connections of a transfer, capped by `TRANSFER_MAX_PARALLELISM`
+    pub fn transfer_parallelism(&self) -> usize {
+        self.transfer_parallelism
+            .unwrap_or(1)
+            .clamp(1, TRANSFER_MAX_PARALLELISM)
+    }
+
+    /// compression of game, fallback to global compression
+    pub fn compression_of(title_id: &str) -> Compression {
+        let settings = Self::read();
+        settings
+            .game_compressions
+            .get(title_id)
+            .copied()
+            .unwrap_or(settings.compression)
+    }
+
+    /// # backup filter of game
+    ///
+    /// rules of game, fallback to global rules
+    pub fn backup_filter_of(title_id: &str) -> PathFilter {
+        let settings = Self::read();
+        settings
+            .game_backup_rules
+            .get(title_id)
+            .unwrap_or(&settings.backup_rules)
+            .filter()
+    }
+
+    /// # backup filter of global rules
+    pub fn global_backup_filter() -> PathFilter {
+        Self::read().backup_rules.filter()
+    }
+}
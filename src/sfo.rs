@@ -0,0 +1,388 @@
+//! # PARAM.SFO
+//!
+//! reader/writer of `param.sfo`, see <https://www.psdevwiki.com/vita/System_File_Object_(SFO)_(PSF)>
+//!
+//! ```text
+//! header | index table | key table | data table
+//! ```
+use std::{error::Error, fs, path::Path};
+
+use crate::utils::write_file_atomic;
+
+const SFO_MAGIC: u32 = 0x46535000; // \0PSF
+const HEADER_SIZE: usize = 20;
+const INDEX_SIZE: usize = 16;
+
+// data formats
+const FMT_UTF8_SPECIAL: u16 = 0x0004;
+const FMT_UTF8: u16 = 0x0204;
+const FMT_INT32: u16 = 0x0404;
+
+pub const KEY_ACCOUNT_ID: &str = "ACCOUNT_ID";
+pub const KEY_PARAMS: &str = "PARAMS";
+pub const KEY_SAVEDATA_DIRECTORY: &str = "SAVEDATA_DIRECTORY";
+pub const KEY_TITLE: &str = "TITLE";
+pub const KEY_TITLE_ID: &str = "TITLE_ID";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SfoValue {
+    /// utf8 without null terminator, used for binary data
+    Bytes(Vec<u8>),
+    /// utf8 with null terminator
+    Str(String),
+    Int(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SfoEntry {
+    pub key: String,
+    pub value: SfoValue,
+    /// size reserved in data table
+    pub max_len: u32,
+}
+
+impl SfoEntry {
+    fn fmt(&self) -> u16 {
+        match self.value {
+            SfoValue::Bytes(_) => FMT_UTF8_SPECIAL,
+            SfoValue::Str(_) => FMT_UTF8,
+            SfoValue::Int(_) => FMT_INT32,
+        }
+    }
+
+    fn data(&self) -> Vec<u8> {
+        match &self.value {
+            SfoValue::Bytes(data) => data.clone(),
+            SfoValue::Str(data) => {
+                let mut data = data.as_bytes().to_vec();
+                data.push(0);
+                data
+            }
+            SfoValue::Int(data) => data.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sfo {
+    pub version: u32,
+    pub entries: Vec<SfoEntry>,
+}
+
+/// `len` bytes at `offset`, none if it is out of range
+fn slice(buf: &[u8], offset: usize, len: usize) -> Option<&[u8]> {
+    buf.get(offset..offset.checked_add(len)?)
+}
+
+/// `base + offset`, offsets of a corrupted sfo may overflow
+fn offset_of(base: usize, offset: usize) -> Result<usize, Box<dyn Error>> {
+    base.checked_add(offset)
+        .ok_or_else(|| "sfo offset overflows".into())
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Result<u16, Box<dyn Error>> {
+    match slice(buf, offset, 2) {
+        Some(data) => Ok(u16::from_le_bytes([data[0], data[1]])),
+        None => Err("sfo is truncated".into()),
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    match slice(buf, offset, 4) {
+        Some(data) => Ok(u32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+        None => Err("sfo is truncated".into()),
+    }
+}
+
+impl Sfo {
+    pub fn from_bytes(buf: &[u8]) -> Result<Sfo, Box<dyn Error>> {
+        if read_u32(buf, 0)? != SFO_MAGIC {
+            return Err("sfo magic mismatch".into());
+        }
+        let version = read_u32(buf, 4)?;
+        let key_table_offset = read_u32(buf, 8)? as usize;
+        let data_table_offset = read_u32(buf, 12)? as usize;
+        let count = read_u32(buf, 16)? as usize;
+
+        let mut entries = vec![];
+        for i in 0..count {
+            let index = offset_of(HEADER_SIZE, INDEX_SIZE.saturating_mul(i))?;
+            // the fields below are in range if the index is
+            if slice(buf, index, INDEX_SIZE).is_none() {
+                return Err("sfo is truncated".into());
+            }
+            let key_offset = offset_of(key_table_offset, read_u16(buf, index)? as usize)?;
+            let fmt = read_u16(buf, index + 2)?;
+            let len = read_u32(buf, index + 4)? as usize;
+            let max_len = read_u32(buf, index + 8)?;
+            let data_offset = offset_of(data_table_offset, read_u32(buf, index + 12)? as usize)?;
+
+            let key = match buf.get(key_offset..) {
+                Some(keys) => {
+                    let end = keys.iter().position(|&c| c == 0).unwrap_or(keys.len());
+                    String::from_utf8_lossy(&keys[..end]).to_string()
+                }
+                None => return Err("sfo key is out of range".into()),
+            };
+            let data = match slice(buf, data_offset, len) {
+                Some(data) => data,
+                None => return Err(format!("sfo data of {} is out of range", key).into()),
+            };
+            let value = match fmt {
+                FMT_UTF8 => {
+                    let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+                    SfoValue::Str(String::from_utf8_lossy(&data[..end]).to_string())
+                }
+                FMT_INT32 => SfoValue::Int(read_u32(data, 0)?),
+                _ => SfoValue::Bytes(data.to_vec()),
+            };
+            entries.push(SfoEntry {
+                key,
+                value,
+                max_len,
+            });
+        }
+
+        Ok(Sfo { version, entries })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut index_table = vec![];
+        let mut key_table = vec![];
+        let mut data_table = vec![];
+        for entry in self.entries.iter() {
+            let data = entry.data();
+            let max_len = (entry.max_len as usize).max(data.len());
+            index_table.extend_from_slice(&(key_table.len() as u16).to_le_bytes());
+            index_table.extend_from_slice(&entry.fmt().to_le_bytes());
+            index_table.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            index_table.extend_from_slice(&(max_len as u32).to_le_bytes());
+            index_table.extend_from_slice(&(data_table.len() as u32).to_le_bytes());
+
+            key_table.extend_from_slice(entry.key.as_bytes());
+            key_table.push(0);
+
+            data_table.extend_from_slice(&data);
+            data_table.resize(data_table.len() + max_len - data.len(), 0);
+        }
+        // key table is 4 bytes aligned
+        key_table.resize(key_table.len().div_ceil(4) * 4, 0);
+
+        let key_table_offset = HEADER_SIZE + index_table.len();
+        let data_table_offset = key_table_offset + key_table.len();
+        let mut buf = Vec::with_capacity(data_table_offset + data_table.len());
+        buf.extend_from_slice(&SFO_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        buf.extend_from_slice(&(key_table_offset as u32).to_le_bytes());
+        buf.extend_from_slice(&(data_table_offset as u32).to_le_bytes());
+        buf.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&index_table);
+        buf.extend_from_slice(&key_table);
+        buf.extend_from_slice(&data_table);
+        buf
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Sfo, Box<dyn Error>> {
+        Sfo::from_bytes(&fs::read(path)?)
+    }
+
+    /// write to a temp file and rename it, a failed write keeps the old sfo
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        write_file_atomic(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&SfoValue> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .map(|entry| &entry.value)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(SfoValue::Str(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<u32> {
+        match self.get(key) {
+            Some(SfoValue::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        match self.get(key) {
+            Some(SfoValue::Bytes(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// set value of key, the entry is inserted with key order if not exists
+    pub fn set(&mut self, key: &str, value: SfoValue) {
+        match self.entries.iter_mut().find(|entry| entry.key == key) {
+            Some(entry) => entry.value = value,
+            None => {
+                let idx = self
+                    .entries
+                    .iter()
+                    .position(|entry| entry.key.as_str() > key)
+                    .unwrap_or(self.entries.len());
+                let entry = SfoEntry {
+                    key: key.to_string(),
+                    value,
+                    max_len: 0,
+                };
+                let max_len = (entry.data().len() as u32).div_ceil(4) * 4;
+                self.entries.insert(idx, SfoEntry { max_len, ..entry });
+            }
+        }
+    }
+
+    pub fn title_id(&self) -> Option<&str> {
+        self.get_str(KEY_TITLE_ID)
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get_str(KEY_TITLE)
+    }
+
+    pub fn savedata_directory(&self) -> Option<&str> {
+        self.get_str(KEY_SAVEDATA_DIRECTORY)
+    }
+
+    pub fn params(&self) -> Option<&[u8]> {
+        self.get_bytes(KEY_PARAMS)
+    }
+
+    /// account id is the first 8 bytes of `ACCOUNT_ID`
+    pub fn account_id(&self) -> Option<u64> {
+        match self.get_bytes(KEY_ACCOUNT_ID) {
+            Some(data) if data.len() >= 8 => {
+                let mut id = [0; 8];
+                id.copy_from_slice(&data[..8]);
+                Some(u64::from_le_bytes(id))
+            }
+            _ => None,
+        }
+    }
+
+    /// return false if `ACCOUNT_ID` not exists
+    pub fn set_account_id(&mut self, account_id: u64) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.key == KEY_ACCOUNT_ID)
+        {
+            Some(SfoEntry {
+                value: SfoValue::Bytes(data),
+                ..
+            }) if data.len() >= 8 => {
+                data[..8].copy_from_slice(&account_id.to_le_bytes());
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sfo, SfoValue};
+
+    const VITA_SAVE_SFO: &[u8] = include_bytes!("../tests/fixtures/sfo/vita_save.sfo");
+    const PSP_SAVE_SFO: &[u8] = include_bytes!("../tests/fixtures/sfo/psp_save.sfo");
+
+    #[test]
+    fn test_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        for buf in [VITA_SAVE_SFO, PSP_SAVE_SFO] {
+            let sfo = Sfo::from_bytes(buf)?;
+            assert_eq!(sfo.to_bytes(), buf);
+            assert_eq!(Sfo::from_bytes(&sfo.to_bytes())?, sfo);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_vita_save() -> Result<(), Box<dyn std::error::Error>> {
+        let sfo = Sfo::from_bytes(VITA_SAVE_SFO)?;
+        assert_eq!(sfo.title_id(), Some("PCSH00000"));
+        assert_eq!(sfo.title(), Some("Save Cloud Test"));
+        assert_eq!(sfo.savedata_directory(), Some("PCSH00000"));
+        assert_eq!(sfo.account_id(), Some(0x0123456789abcdef));
+        assert_eq!(sfo.params().map(|params| params.len()), Some(1024));
+        assert_eq!(sfo.get_int("ATTRIBUTE"), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_psp_save() -> Result<(), Box<dyn std::error::Error>> {
+        let sfo = Sfo::from_bytes(PSP_SAVE_SFO)?;
+        assert_eq!(sfo.savedata_directory(), Some("ULJM05000DATA00"));
+        assert_eq!(sfo.title(), Some("PSP Save Test"));
+        assert_eq!(sfo.get_str("CATEGORY"), Some("MS"));
+        assert_eq!(sfo.account_id(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_account_id() -> Result<(), Box<dyn std::error::Error>> {
+        let mut sfo = Sfo::from_bytes(VITA_SAVE_SFO)?;
+        assert!(sfo.set_account_id(0xfedcba9876543210));
+        let buf = sfo.to_bytes();
+        assert_eq!(buf.len(), VITA_SAVE_SFO.len());
+        let diff = buf
+            .iter()
+            .zip(VITA_SAVE_SFO.iter())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert!(diff <= 8);
+        assert_eq!(
+            Sfo::from_bytes(&buf)?.account_id(),
+            Some(0xfedcba9876543210)
+        );
+
+        let mut sfo = Sfo::from_bytes(PSP_SAVE_SFO)?;
+        assert!(!sfo.set_account_id(0xfedcba9876543210));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set() -> Result<(), Box<dyn std::error::Error>> {
+        let mut sfo = Sfo::from_bytes(PSP_SAVE_SFO)?;
+        sfo.set("TITLE", SfoValue::Str("新标题".to_string()));
+        sfo.set("PARENTAL_LEVEL", SfoValue::Int(1));
+        let sfo = Sfo::from_bytes(&sfo.to_bytes())?;
+        assert_eq!(sfo.title(), Some("新标题"));
+        assert_eq!(sfo.get_int("PARENTAL_LEVEL"), Some(1));
+        let keys = sfo
+            .entries
+            .iter()
+            .map(|entry| entry.key.as_str())
+            .collect::<Vec<&str>>();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Sfo::from_bytes(&[]).is_err());
+        assert!(Sfo::from_bytes(&VITA_SAVE_SFO[..40]).is_err());
+        assert!(Sfo::from_bytes(b"\0PSX\x01\x01\0\0").is_err());
+
+        // offsets and lengths near the max
+        let mut buf = VITA_SAVE_SFO.to_vec();
+        buf[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Sfo::from_bytes(&buf).is_err());
+        let mut buf = VITA_SAVE_SFO.to_vec();
+        buf[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Sfo::from_bytes(&buf).is_err());
+        let mut buf = VITA_SAVE_SFO.to_vec();
+        buf[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Sfo::from_bytes(&buf).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! # disk space
+//!
+//! free space of devices and pre-flight checks before writing files,
+//! refuse to start when the device is too full to finish
+use std::{error::Error, fs, path::Path};
+
+use log::info;
+
+use crate::{
+    constant::PSV_DEVICES,
+    tai::get_psv_device_space,
+    utils::{dir_size, format_size},
+};
+
+// keep some space for journal, settings and logs
+const SPACE_RESERVE: u64 = 1024 * 1024;
+
+/// # storage devices shown on desktop
+pub const STORAGE_DEVICES: [&str; 4] = ["ux0:", "uma0:", "grw0:", "ur0:"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceSpace {
+    pub total: u64,
+    pub free: u64,
+}
+
+/// # device of path
+///
+/// `ux0:data/a.zip` is on `ux0:`
+pub fn device_of(path: &str) -> Option<&'static str> {
+    PSV_DEVICES
+        .iter()
+        .find(|device| path.starts_with(*device))
+        .copied()
+}
+
+/// # space of device
+///
+/// return none if the device is not mounted
+pub fn device_space(device: &str) -> Option<DeviceSpace> {
+    get_psv_device_space(device).map(|(total, free)| DeviceSpace { total, free })
+}
+
+/// # uncompressed size of zip
+///
+/// sum of file sizes in the central directory
+pub fn zip_uncompressed_size(path: impl AsRef<Path>) -> Result<u64, Box<dyn Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(path)?)?;
+    let mut size = 0;
+    for i in 0..zip.len() {
+        size += zip.by_index_raw(i)?.size();
+    }
+    Ok(size)
+}
+
+/// # pre-flight check
+///
+/// make sure that `needed` bytes can be written to `to`,
+/// the space of existing `to` is counted as free because it is overwritten,
+/// pass if the space of device is unknown
+pub fn check_space(to: &str, needed: u64) -> Result<(), Box<dyn Error>> {
+    check_space_with(to, needed, true)
+}
+
+/// # pre-flight check of staged write
+///
+/// the staged copy is written before `to` is replaced,
+/// so the space of existing `to` is not counted as free
+pub fn check_staged_space(to: &str, needed: u64) -> Result<(), Box<dyn Error>> {
+    check_space_with(to, needed, false)
+}
+
+fn check_space_with(to: &str, needed: u64, is_reusable: bool) -> Result<(), Box<dyn Error>> {
+    let device = match device_of(to) {
+        Some(device) => device,
+        None => return Ok(()),
+    };
+    let space = match device_space(device) {
+        Some(space) => space,
+        None => return Ok(()),
+    };
+    let reusable = if is_reusable && Path::new(to).exists() {
+        dir_size(to).unwrap_or(0)
+    } else {
+        0
+    };
+    info!(
+        "check space of {}: needed {} free {} reusable {}",
+        to, needed, space.free, reusable
+    );
+    ensure_space(device, needed, space.free.saturating_add(reusable))?;
+    Ok(())
+}
+
+fn ensure_space(device: &str, needed: u64, free: u64) -> Result<(), String> {
+    if needed.saturating_add(SPACE_RESERVE) > free {
+        return Err(format!(
+            "{} 空间不足：需要 {}，可用 {}",
+            device,
+            format_size(needed.saturating_add(SPACE_RESERVE)),
+            format_size(free)
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use crate::utils::TestDir;
+
+    use super::{device_of, ensure_space, zip_uncompressed_size, SPACE_RESERVE};
+
+    #[test]
+    fn test_ensure_space() {
+        assert_eq!(device_of("ux0:data/save-cloud"), Some("ux0:"));
+        assert_eq!(device_of("uma0:"), Some("uma0:"));
+        assert_eq!(device_of("/apps/Backup"), None);
+
+        assert!(ensure_space("ux0:", 100, 100 + SPACE_RESERVE).is_ok());
+        assert!(ensure_space("ux0:", 101, 100 + SPACE_RESERVE).is_err());
+        assert!(ensure_space("ux0:", 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_zip_uncompressed_size() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("space");
+        let path = dir.join("a.zip");
+        let mut zip = ZipWriter::new(fs::File::create(&path)?);
+        zip.add_directory("a/", FileOptions::default())?;
+        zip.start_file("a/b.bin", FileOptions::default())?;
+        zip.write_all(&[0; 1000])?;
+        zip.start_file("c.bin", FileOptions::default())?;
+        zip.write_all(&[1; 24])?;
+        zip.finish()?;
+
+        assert_eq!(zip_uncompressed_size(&path)?, 1024);
+        Ok(())
+    }
+}
@@ -0,0 +1,454 @@
+//! # staged writes
+//!
+//! backups are written to a temp file and renamed after they are verified,
+//! extracted files are staged and moved into place after all entries are
+//! extracted, so an interrupted operation never leaves a truncated zip or a
+//! half overwritten game save, pending operations are recorded so they can
+//! be finished or cleaned up at startup
+//!
+//! ```text
+//! ux0:data/save-cloud/staging/1709100000123-0.json   pending operation
+//! ux0:data/save-cloud/staging/1709100000123-0/0      staged file
+//! ```
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constant::{GAME_CARD_SAVE_DIR, GAME_SAVE_DIR, STAGING_DIR},
+    crypto::{encrypt_file, is_encrypted_file},
+    space::device_of,
+    tai::{mount_pfs, unmount_pfs},
+    utils::{current_time, move_path, remove_path, replace_file, temp_path, write_file_atomic},
+};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Pending {
+    /// zip is being written to `temp`,
+    /// `encrypted` is true if it is being encrypted
+    Backup {
+        temp: String,
+        to: String,
+        #[serde(default)]
+        encrypted: bool,
+    },
+    /// staged files and their targets,
+    /// `extracted` is true if all entries are extracted
+    Extract {
+        files: Vec<(String, String)>,
+        extracted: bool,
+    },
+}
+
+/// # staged backup
+///
+/// write the zip to `temp()`, then `finish` with the result
+pub struct StagedBackup {
+    dir: PathBuf,
+    id: String,
+    temp: String,
+    to: String,
+    encrypted: bool,
+}
+
+impl StagedBackup {
+    pub fn begin(to: &str) -> io::Result<StagedBackup> {
+        StagedBackup::begin_in(staging_dir_of(to), to)
+    }
+
+    fn begin_in(dir: PathBuf, to: &str) -> io::Result<StagedBackup> {
+        let backup = StagedBackup {
+            dir,
+            id: new_id(),
+            temp: temp_path(to).to_string_lossy().to_string(),
+            to: to.to_string(),
+            encrypted: false,
+        };
+        write_record(
+            &backup.dir,
+            &backup.id,
+            &Pending::Backup {
+                temp: backup.temp.clone(),
+                to: backup.to.clone(),
+                encrypted: false,
+            },
+        )?;
+        Ok(backup)
+    }
+
+    pub fn temp(&self) -> &str {
+        &self.temp
+    }
+
+    /// # encrypt the temp zip
+    ///
+    /// the zip is verified before it is encrypted
+    pub fn encrypt(&mut self, passphrase: &str) -> Result<(), Box<dyn Error>> {
+        verify_zip(&self.temp)?;
+        // never finish the plain zip if it is interrupted
+        write_record(
+            &self.dir,
+            &self.id,
+            &Pending::Backup {
+                temp: self.temp.clone(),
+                to: self.to.clone(),
+                encrypted: true,
+            },
+        )?;
+        encrypt_file(&self.temp, passphrase)?;
+        self.encrypted = true;
+        Ok(())
+    }
+
+    /// # finish backup
+    ///
+    /// verify the temp zip and rename it to the target if `res` is ok,
+    /// the temp zip is removed otherwise
+    pub fn finish(self, res: Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+        let res = res.and_then(|_| {
+            if !self.encrypted {
+                verify_zip(&self.temp)?;
+            }
+            replace_file(&self.temp, &self.to)?;
+            Ok(())
+        });
+        if res.is_err() && Path::new(&self.temp).exists() {
+            let _ = fs::remove_file(&self.temp);
+        }
+        remove_record(&self.dir, &self.id);
+        res
+    }
+}
+
+/// # staged extract
+///
+/// extract files to the paths returned by `stage`, then `finish` with the
+/// result to move them into place
+pub struct StagedExtract {
+    dir: PathBuf,
+    id: String,
+    files: Vec<(String, String)>,
+}
+
+impl StagedExtract {
+    pub fn begin(to: &str) -> io::Result<StagedExtract> {
+        StagedExtract::begin_in(staging_dir_of(to))
+    }
+
+    fn begin_in(dir: PathBuf) -> io::Result<StagedExtract> {
+        let staged = StagedExtract {
+            dir,
+            id: new_id(),
+            files: vec![],
+        };
+        fs::create_dir_all(staged.dir.join(&staged.id))?;
+        write_record(
+            &staged.dir,
+            &staged.id,
+            &Pending::Extract {
+                files: vec![],
+                extracted: false,
+            },
+        )?;
+        Ok(staged)
+    }
+
+    /// path to extract the file which is moved to `target` at last
+    pub fn stage(&mut self, target: &Path) -> PathBuf {
+        let path = self.dir.join(&self.id).join(self.files.len().to_string());
+        self.files.push((
+            path.to_string_lossy().to_string(),
+            target.to_string_lossy().to_string(),
+        ));
+        path
+    }
+
+    /// # finish extract
+    ///
+    /// move staged files into place if `res` is ok, they are discarded
+    /// otherwise, kept for recovery at startup if moving is interrupted
+    pub fn finish(self, res: Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+        if res.is_ok() {
+            write_record(
+                &self.dir,
+                &self.id,
+                &Pending::Extract {
+                    files: self.files.clone(),
+                    extracted: true,
+                },
+            )?;
+            move_staged(&self.files)?;
+        }
+        remove_path(&self.dir.join(&self.id))?;
+        remove_record(&self.dir, &self.id);
+        res
+    }
+}
+
+/// # verify zip
+///
+/// read all entries to check crc
+pub fn verify_zip(path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(path)?)?;
+    for i in 0..zip.len() {
+        io::copy(&mut zip.by_index(i)?, &mut io::sink())?;
+    }
+    Ok(())
+}
+
+/// # recover interrupted operations
+///
+/// finish the backups which are verified and the extracts which are
+/// extracted, clean up the others
+pub fn recover_staging() -> Result<(), Box<dyn Error>> {
+    recover_in(Path::new(STAGING_DIR))
+}
+
+fn recover_in(dir: &Path) -> Result<(), Box<dyn Error>> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new("json")) {
+            // staged files without record
+            if path.is_dir() && !path.with_extension("json").exists() {
+                remove_path(&path)?;
+            }
+            continue;
+        }
+        let id = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+        match serde_json::from_slice::<Pending>(&fs::read(&path)?) {
+            Ok(Pending::Backup {
+                temp,
+                to,
+                encrypted,
+            }) => {
+                if Path::new(&temp).exists() {
+                    let is_finished = if encrypted {
+                        is_encrypted_file(&temp).unwrap_or(false)
+                    } else {
+                        verify_zip(&temp).is_ok()
+                    };
+                    if is_finished {
+                        info!("finish interrupted backup {}", to);
+                        replace_file(&temp, &to)?;
+                    } else {
+                        info!("remove interrupted backup {}", temp);
+                        fs::remove_file(&temp)?;
+                    }
+                }
+            }
+            Ok(Pending::Extract {
+                files,
+                extracted: true,
+            }) => {
+                info!("finish interrupted extract {}", id);
+                // files of game save are written through pfs
+                let save_dir = files.iter().find_map(|(_, target)| save_dir_of(target));
+                if let Some(save_dir) = &save_dir {
+                    mount_pfs(save_dir);
+                }
+                let res = move_staged(&files);
+                if save_dir.is_some() {
+                    unmount_pfs();
+                }
+                if let Err(err) = res {
+                    error!("finish interrupted extract {} failed: {:?}", id, err);
+                    continue;
+                }
+            }
+            Ok(Pending::Extract { .. }) => info!("discard interrupted extract {}", id),
+            Err(err) => error!("invalid staging record {:?}: {:?}", path, err),
+        }
+        let staged = dir.join(&id);
+        if staged.exists() {
+            remove_path(&staged)?;
+        }
+        remove_record(dir, &id);
+    }
+    Ok(())
+}
+
+/// files of game save are copied through pfs instead of renamed
+fn move_staged(files: &[(String, String)]) -> io::Result<()> {
+    for (staged, target) in files {
+        let staged = Path::new(staged);
+        // moved before interrupted
+        if !staged.exists() {
+            continue;
+        }
+        let target_path = Path::new(target);
+        if save_dir_of(target).is_some() {
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(staged, target_path)?;
+            fs::remove_file(staged)?;
+        } else {
+            move_path(staged, target_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// `ux0:user/00/savedata/PCSG00001/a.bin` is in `ux0:user/00/savedata/PCSG00001`
+fn save_dir_of(path: &str) -> Option<String> {
+    [GAME_SAVE_DIR, GAME_CARD_SAVE_DIR]
+        .into_iter()
+        .find_map(|dir| {
+            let title_id = path
+                .strip_prefix(dir)?
+                .strip_prefix('/')?
+                .split('/')
+                .next()?;
+            if title_id.is_empty() {
+                return None;
+            }
+            Some(format!("{}/{}", dir, title_id))
+        })
+}
+
+/// staged on the device of vita, or temp dir of host
+fn staging_dir_of(path: &str) -> PathBuf {
+    if device_of(path).is_some() {
+        PathBuf::from(STAGING_DIR)
+    } else {
+        std::env::temp_dir().join("save-cloud-staging")
+    }
+}
+
+fn new_id() -> String {
+    format!(
+        "{}-{}",
+        current_time(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn record_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+fn write_record(dir: &Path, id: &str, pending: &Pending) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    write_file_atomic(record_path(dir, id), serde_json::to_vec(pending)?)
+}
+
+fn remove_record(dir: &Path, id: &str) {
+    if let Err(err) = fs::remove_file(record_path(dir, id)) {
+        error!("remove staging record {} failed: {:?}", id, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use crate::{crypto::is_encrypted_file, utils::TestDir};
+
+    use super::{
+        record_path, recover_in, save_dir_of, write_record, Pending, StagedBackup, StagedExtract,
+    };
+
+    #[test]
+    fn test_staging() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("staging");
+        let staging = dir.join("staging");
+        let to = dir.join("a.zip");
+        let to = to.to_str().unwrap();
+
+        // broken zip is not renamed
+        let backup = StagedBackup::begin_in(staging.clone(), to)?;
+        fs::write(backup.temp(), "broken")?;
+        assert!(backup.finish(Ok(())).is_err());
+        assert!(!dir.join("a.zip").exists() && !dir.join("a.zip.tmp").exists());
+
+        let backup = StagedBackup::begin_in(staging.clone(), to)?;
+        let mut zip = ZipWriter::new(fs::File::create(backup.temp())?);
+        zip.start_file("a.bin", FileOptions::default())?;
+        zip.write_all(b"data")?;
+        zip.finish()?;
+        backup.finish(Ok(()))?;
+        assert!(dir.join("a.zip").exists() && !dir.join("a.zip.tmp").exists());
+
+        // zip is encrypted before renamed
+        let mut backup = StagedBackup::begin_in(staging.clone(), to)?;
+        let mut zip = ZipWriter::new(fs::File::create(backup.temp())?);
+        zip.start_file("a.bin", FileOptions::default())?;
+        zip.write_all(b"data")?;
+        zip.finish()?;
+        backup.encrypt("passphrase")?;
+        backup.finish(Ok(()))?;
+        assert!(is_encrypted_file(to)? && !dir.join("a.zip.tmp").exists());
+
+        // failed extract does not touch the target
+        let target = dir.join("save/a.bin");
+        let mut extract = StagedExtract::begin_in(staging.clone())?;
+        fs::write(extract.stage(&target), "new")?;
+        assert!(extract.finish(Err("failed".into())).is_err());
+        assert!(!target.exists());
+
+        let mut extract = StagedExtract::begin_in(staging.clone())?;
+        fs::write(extract.stage(&target), "new")?;
+        extract.finish(Ok(()))?;
+        assert_eq!(fs::read(&target)?, b"new");
+        assert_eq!(fs::read_dir(&staging)?.count(), 0);
+
+        // interrupted after extracted is finished at startup
+        let mut extract = StagedExtract::begin_in(staging.clone())?;
+        fs::write(extract.stage(&target), "recovered")?;
+        write_record(
+            &staging,
+            &extract.id,
+            &Pending::Extract {
+                files: extract.files.clone(),
+                extracted: true,
+            },
+        )?;
+        // interrupted before extracted is discarded
+        let mut discarded = StagedExtract::begin_in(staging.clone())?;
+        fs::write(discarded.stage(&dir.join("b.bin")), "discarded")?;
+        // interrupted backup is removed if it is broken
+        let backup = StagedBackup::begin_in(staging.clone(), to)?;
+        fs::write(backup.temp(), "broken")?;
+        fs::write(record_path(&staging, "broken"), "{")?;
+
+        recover_in(&staging)?;
+        assert_eq!(fs::read(&target)?, b"recovered");
+        assert!(!dir.join("b.bin").exists());
+        assert!(dir.join("a.zip").exists() && !dir.join("a.zip.tmp").exists());
+        assert_eq!(fs::read_dir(&staging)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_dir_of() {
+        assert_eq!(
+            save_dir_of("ux0:user/00/savedata/PCSG00001/sce_sys/param.sfo"),
+            Some("ux0:user/00/savedata/PCSG00001".to_string())
+        );
+        assert_eq!(
+            save_dir_of("grw0:savedata/PCSG00001"),
+            Some("grw0:savedata/PCSG00001".to_string())
+        );
+        assert_eq!(save_dir_of("ux0:user/00/savedata/"), None);
+        assert_eq!(save_dir_of("ux0:data/save-cloud/a.bin"), None);
+    }
+}
@@ -1,7 +1,25 @@
-use core::slice;
-use std::{cell::RefCell, ffi::CStr, fs, os::raw::*, path::Path, ptr::null};
-
-use crate::utils::{create_parent_if_not_exists, str_to_c_str};
+use std::{
+    cell::OnceCell,
+    env,
+    error::Error,
+    ffi::CStr,
+    fs,
+    os::raw::*,
+    path::Path,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constant::{GAME_CARD_SAVE_DIR, GAME_SAVE_DIR, PSP_SAVE_DIR, TITLES_FIXTURE_ENV_KEY},
+    settings::Settings,
+    sfo::Sfo,
+    title_source::{AppDbSource, JsonTitleSource, TitleSource},
+    utils::{create_parent_if_not_exists, str_to_c_str},
+};
 
 const APP_KERNEL_PATH: &str = "app0:sce_sys/resources/kernel.skprx";
 const PLUGIN_KERNEL_PATHS: [&str; 3] = [
@@ -17,149 +35,423 @@ extern "C" {
     fn sceLoad() -> i32;
     fn pfs_mount(path: *const c_char) -> i32;
     fn pfs_unmount() -> i32;
-    fn applist_init();
-    fn applist_get() -> *const TitleList;
-    fn applist_free(appList: *const TitleList);
+    // return < 0 if failed
+    fn app_db_query(sql: *const c_char, callback: AppDbRowCallback, data: *mut c_void) -> c_int;
     // return 0 if failed
     fn get_account_id() -> c_ulonglong;
-    // return < 0 if failed
-    fn change_account_id(fs_path: *const c_char, account_id: c_ulonglong) -> c_char;
     fn prevent_to_sleep();
     fn launch_app_by_title_id(title_id: *const c_char);
+    // return < 0 if failed
+    fn get_device_space(
+        device: *const c_char,
+        max_size: *mut c_ulonglong,
+        free_size: *mut c_ulonglong,
+    ) -> c_int;
+    // return < 0 if failed
+    fn get_net_state() -> c_int;
+    fn is_charging() -> c_int;
 }
 
-#[repr(C)]
+type AppDbRowCallback = extern "C" fn(data: *mut c_void, argc: c_int, argv: *const *const c_char);
+
+/// row of app.db, null value is none
+pub type AppDbRow = Vec<Option<String>>;
+
+fn c_str_to_string(c_str: *const c_char) -> String {
+    unsafe { CStr::from_ptr(c_str).to_string_lossy().to_string() }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleKind {
+    /// save data in `GAME_CARD_SAVE_DIR` or `GAME_SAVE_DIR`
+    #[default]
+    Vita,
+    /// psp or psone save data in `PSP_SAVE_DIR`, `real_id` is the game id
+    Psp,
+    /// backup profile in settings, `real_id` is the title id of profile
+    Profile,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Title {
-    title_id: *const c_char,
-    real_id: *const c_char,
-    name: *const c_char,
-    iconpath: *const c_char,
+    title_id: String,
+    real_id: String,
+    name: String,
+    #[serde(default)]
+    iconpath: String,
+    #[serde(default)]
+    kind: TitleKind,
+    /// false for orphaned game save whose game is deleted
+    #[serde(default = "default_true")]
+    installed: bool,
+    /// false for installed game which has not created save yet
+    #[serde(default = "default_true")]
+    has_save: bool,
+    /// `APP_VER` of app
+    #[serde(default)]
+    version: String,
+    /// `CATEGORY` of app, such as `gd` or `gdc`
+    #[serde(default)]
+    category: String,
+    /// seconds since epoch, the last time game save is written
+    #[serde(default)]
+    last_played: Option<u64>,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Title {
-    pub fn title_id(&self) -> &str {
-        unsafe {
-            let c_str = CStr::from_ptr(self.title_id);
-            c_str.to_str().unwrap()
+    pub fn new(
+        title_id: &str,
+        real_id: &str,
+        name: &str,
+        iconpath: &str,
+        kind: TitleKind,
+    ) -> Title {
+        Title {
+            title_id: title_id.to_string(),
+            real_id: real_id.to_string(),
+            name: name.to_string(),
+            iconpath: iconpath.to_string(),
+            kind,
+            installed: true,
+            has_save: true,
+            version: String::new(),
+            category: String::new(),
+            last_played: None,
         }
     }
 
+    pub fn info(mut self, version: &str, category: &str, last_played: Option<u64>) -> Title {
+        self.version = version.to_string();
+        self.category = category.to_string();
+        self.last_played = last_played;
+        self
+    }
+
+    pub fn state(mut self, installed: bool, has_save: bool) -> Title {
+        self.installed = installed;
+        self.has_save = has_save;
+        self
+    }
+
+    pub fn title_id(&self) -> &str {
+        &self.title_id
+    }
+
     pub fn real_id(&self) -> &str {
-        unsafe {
-            let c_str = CStr::from_ptr(self.real_id);
-            c_str.to_str().unwrap()
-        }
+        &self.real_id
     }
 
     pub fn name(&self) -> &str {
-        unsafe {
-            let c_str = CStr::from_ptr(self.name);
-            c_str.to_str().unwrap()
-        }
+        &self.name
     }
 
     pub fn iconpath(&self) -> &str {
-        unsafe {
-            let c_str = CStr::from_ptr(self.iconpath);
-            c_str.to_str().unwrap()
+        &self.iconpath
+    }
+
+    pub fn kind(&self) -> TitleKind {
+        self.kind
+    }
+
+    pub fn is_psp(&self) -> bool {
+        self.kind == TitleKind::Psp
+    }
+
+    pub fn installed(&self) -> bool {
+        self.installed
+    }
+
+    pub fn has_save(&self) -> bool {
+        self.has_save
+    }
+
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    /// # version and last played time
+    ///
+    /// empty if both are unknown, `now` is seconds since epoch
+    pub fn info_text(&self, now: u64) -> String {
+        let mut info = vec![];
+        if !self.version.is_empty() {
+            info.push(format!("版本 {}", self.version));
         }
+        if let Some(last_played) = self.last_played {
+            let days = now.saturating_sub(last_played) / (24 * 60 * 60);
+            info.push(if days == 0 {
+                "今天玩过".to_string()
+            } else {
+                format!("{} 天前玩过", days)
+            });
+        }
+        info.join("  ")
     }
-}
 
-#[repr(C)]
-pub struct TitleList {
-    size: c_int,
-    list: *const Title,
+    /// # state of title
+    ///
+    /// empty for installed game with save
+    pub fn state_text(&self) -> &'static str {
+        if !self.installed {
+            "未安装"
+        } else if !self.has_save {
+            "无存档"
+        } else {
+            ""
+        }
+    }
 }
 
 pub struct Titles {
-    data: RefCell<Option<*const TitleList>>,
+    apps: Receiver<Vec<Title>>,
+    data: OnceCell<Vec<Title>>,
 }
 
 impl Titles {
-    /// get all list
+    /// # get all list
+    ///
+    /// installed apps are read from app.db,
+    /// or from json file of `TITLES_FIXTURE_ENV_KEY` if it is set
     pub fn new() -> Titles {
-        unsafe {
-            applist_init();
+        match env::var(TITLES_FIXTURE_ENV_KEY) {
+            Ok(path) => Titles::with_source(JsonTitleSource::new(path)),
+            Err(_) => Titles::with_source(AppDbSource),
         }
+    }
+
+    /// # titles of source
+    ///
+    /// installed apps are loaded from `source` in background
+    pub fn with_source(source: impl TitleSource + 'static) -> Titles {
+        let (sender, apps) = mpsc::channel();
+        thread::spawn(move || {
+            let apps = source.load().unwrap_or_else(|err| {
+                error!("load titles failed: {:?}", err);
+                vec![]
+            });
+            let _ = sender.send(apps);
+        });
         Titles {
-            data: RefCell::new(None),
+            apps,
+            data: OnceCell::new(),
         }
     }
 
-    pub fn data(&self) -> *const TitleList {
-        if self.data.borrow().is_none() {
-            unsafe {
-                let ptr = applist_get();
-                if !ptr.is_null() {
-                    (*self.data.borrow_mut()) = Some(ptr);
-                    return ptr;
-                }
-            }
-            null()
-        } else {
-            self.data.borrow().expect("get data ptr of titles")
+    /// titles of app list, psp game saves, profiles and orphaned game saves,
+    /// empty before app list is loaded
+    pub fn data(&self) -> &[Title] {
+        if let Some(data) = self.data.get() {
+            return data;
+        }
+        match self.apps.try_recv() {
+            Ok(apps) => self.init(apps),
+            Err(_) => &[],
         }
     }
 
-    pub fn size(&self) -> usize {
-        if self.data().is_null() {
-            return 0;
+    /// titles after app list is loaded
+    pub fn wait(&self) -> &[Title] {
+        if let Some(data) = self.data.get() {
+            return data;
         }
-        unsafe { (*self.data()).size as usize }
+        self.init(self.apps.recv().unwrap_or_default())
+    }
+
+    fn init(&self, mut data: Vec<Title>) -> &[Title] {
+        let mut orphaned = get_orphaned_titles(&data);
+        data.append(&mut get_psp_titles());
+        data.append(&mut get_profile_titles());
+        data.append(&mut orphaned);
+        self.data.get_or_init(|| data)
+    }
+
+    pub fn size(&self) -> usize {
+        self.data().len()
     }
 
     pub fn get_title_by_idx(&self, idx: i32) -> Option<&Title> {
-        let size = self.size() as i32;
-        if idx < size {
-            unsafe {
-                let list = slice::from_raw_parts((*self.data()).list, size as usize);
-                return Some(&list[idx as usize]);
-            }
+        if idx < 0 {
+            return None;
         }
-        None
+        self.data().get(idx as usize)
     }
 
-    pub fn iter(&self) -> TitlesIterator {
-        TitlesIterator {
-            index: 0,
-            titles: self,
-        }
+    pub fn iter(&self) -> std::slice::Iter<'_, Title> {
+        self.data().iter()
     }
 }
 
-impl Drop for Titles {
-    fn drop(&mut self) {
-        let ptr = self.data();
-        unsafe {
-            applist_free(ptr);
+/// # get psp titles
+///
+/// psp and psone game saves are grouped by game id, which is the first 9 chars of save dir,
+/// title name and icon are read from the save dir
+pub fn get_psp_titles() -> Vec<Title> {
+    let mut titles: Vec<Title> = vec![];
+    let mut dirs = match fs::read_dir(PSP_SAVE_DIR) {
+        Ok(dirs) => dirs
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+            .collect::<Vec<String>>(),
+        Err(_) => return titles,
+    };
+    dirs.sort();
+    for dir in dirs {
+        let game_id = match get_psp_game_id(&dir) {
+            Some(game_id) => game_id,
+            None => continue,
+        };
+        let path = Path::new(PSP_SAVE_DIR).join(&dir);
+        let name = Sfo::open(path.join("PARAM.SFO"))
+            .ok()
+            .and_then(|sfo| sfo.title().map(|title| title.replace('\n', " ")));
+        let iconpath = path.join("ICON0.PNG");
+        let iconpath = if iconpath.exists() {
+            iconpath.to_str().map(|path| path.to_string())
+        } else {
+            None
+        };
+        match titles.iter_mut().find(|title| title.real_id == game_id) {
+            Some(title) => {
+                // fill metadata from other save dirs of the game
+                if title.name == title.real_id {
+                    if let Some(name) = name {
+                        title.name = name;
+                    }
+                }
+                if title.iconpath.is_empty() {
+                    if let Some(iconpath) = iconpath {
+                        title.iconpath = iconpath;
+                    }
+                }
+            }
+            None => titles.push(Title::new(
+                game_id,
+                game_id,
+                name.as_deref().unwrap_or(game_id),
+                iconpath.as_deref().unwrap_or(""),
+                TitleKind::Psp,
+            )),
         }
     }
+    titles
 }
 
-pub struct TitlesIterator<'a> {
-    index: usize,
-    titles: &'a Titles,
+/// # get orphaned titles
+///
+/// game save dirs of vita which are not in app list
+pub fn get_orphaned_titles(apps: &[Title]) -> Vec<Title> {
+    get_orphaned_titles_in(&[GAME_CARD_SAVE_DIR, GAME_SAVE_DIR], apps)
 }
 
-impl<'a> Iterator for TitlesIterator<'a> {
-    type Item = &'a Title;
-    fn next(&mut self) -> Option<Self::Item> {
-        if !self.titles.data().is_null() {
-            unsafe {
-                let size = self.titles.size();
-                if self.index < size {
-                    let list = slice::from_raw_parts((*self.titles.data()).list, size);
-                    let item = Some(&list[self.index]);
-                    self.index += 1;
-                    return item;
-                }
+fn get_orphaned_titles_in(save_dirs: &[&str], apps: &[Title]) -> Vec<Title> {
+    let mut titles: Vec<Title> = vec![];
+    for save_dir in save_dirs {
+        let mut dirs = match fs::read_dir(save_dir) {
+            Ok(dirs) => dirs
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+                .collect::<Vec<String>>(),
+            Err(_) => continue,
+        };
+        dirs.sort();
+        for dir in dirs {
+            if apps
+                .iter()
+                .chain(titles.iter())
+                .any(|title| title.real_id == dir)
+            {
+                continue;
             }
+            titles.push(Title::new(&dir, &dir, &dir, "", TitleKind::Vita).state(false, true));
+        }
+    }
+    titles
+}
+
+/// # get titles of backup profiles
+///
+/// invalid profiles are ignored
+pub fn get_profile_titles() -> Vec<Title> {
+    let mut titles: Vec<Title> = vec![];
+    for profile in Settings::read().profiles.iter() {
+        if !profile.is_valid() {
+            error!("invalid backup profile: {:?}", profile);
+            continue;
         }
+        if titles
+            .iter()
+            .any(|title| title.real_id == profile.title_id())
+        {
+            error!("duplicate backup profile: {}", profile.title_id());
+            continue;
+        }
+        titles.push(Title::new(
+            profile.title_id(),
+            profile.title_id(),
+            if profile.name.is_empty() {
+                profile.title_id()
+            } else {
+                &profile.name
+            },
+            &profile.icon,
+            TitleKind::Profile,
+        ));
+    }
+    titles
+}
+
+/// game id of psp save dir, such as `ULJM05000` of `ULJM05000DATA00`
+pub fn get_psp_game_id(dir: &str) -> Option<&str> {
+    let game_id = dir.get(0..9)?;
+    let bytes = game_id.as_bytes();
+    if bytes[..4].iter().all(|c| c.is_ascii_uppercase())
+        && bytes[4..].iter().all(|c| c.is_ascii_digit())
+    {
+        Some(game_id)
+    } else {
         None
     }
 }
 
+extern "C" fn collect_app_db_row(data: *mut c_void, argc: c_int, argv: *const *const c_char) {
+    let rows = unsafe { &mut *(data as *mut Vec<AppDbRow>) };
+    let row = (0..argc.max(0) as usize)
+        .map(|i| {
+            let value = unsafe { *argv.add(i) };
+            if value.is_null() {
+                None
+            } else {
+                Some(c_str_to_string(value))
+            }
+        })
+        .collect();
+    rows.push(row);
+}
+
+/// # query app.db of vita
+pub fn query_app_db(sql: &str) -> Result<Vec<AppDbRow>, Box<dyn Error>> {
+    let mut rows: Vec<AppDbRow> = vec![];
+    let c_str = str_to_c_str(sql);
+    let res = unsafe {
+        app_db_query(
+            c_str.as_slice().as_ptr() as *const c_char,
+            collect_app_db_row,
+            &mut rows as *mut Vec<AppDbRow> as *mut c_void,
+        )
+    };
+    if res < 0 {
+        return Err(format!("query app.db failed: {}", res).into());
+    }
+    Ok(rows)
+}
+
 pub fn sce_app_util_load() {
     unsafe {
         sceAppUtilLoad();
@@ -260,13 +552,6 @@ pub fn get_psv_account_id() -> u64 {
     unsafe { get_account_id() }
 }
 
-pub fn change_psv_account_id(sfo_path: &str, account_id: u64) -> i8 {
-    unsafe {
-        let c_str = str_to_c_str(sfo_path);
-        change_account_id(c_str.as_slice().as_ptr() as *const c_char, account_id)
-    }
-}
-
 pub fn psv_prevent_sleep() {
     unsafe {
         prevent_to_sleep();
@@ -279,3 +564,88 @@ pub fn psv_launch_app_by_title_id(title_id: &str) {
         launch_app_by_title_id(c_str.as_slice().as_ptr() as *const c_char);
     }
 }
+
+/// # total and free size of device
+///
+/// `device` is like `ux0:`, return none if it is not mounted
+pub fn get_psv_device_space(device: &str) -> Option<(u64, u64)> {
+    let mut max_size: c_ulonglong = 0;
+    let mut free_size: c_ulonglong = 0;
+    let c_str = str_to_c_str(device);
+    let res = unsafe {
+        get_device_space(
+            c_str.as_slice().as_ptr() as *const c_char,
+            &mut max_size,
+            &mut free_size,
+        )
+    };
+    if res < 0 {
+        return None;
+    }
+    Some((max_size, free_size))
+}
+
+/// # state of internet connection
+///
+/// return none if it is unknown, such as net ctl is not initialized
+pub fn get_psv_net_state() -> Option<i32> {
+    let res = unsafe { get_net_state() };
+    if res < 0 {
+        return None;
+    }
+    Some(res)
+}
+
+/// is battery charging or the power adapter plugged
+pub fn is_psv_charging() -> bool {
+    unsafe { is_charging() != 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::utils::TestDir;
+
+    use super::{get_orphaned_titles_in, Title, TitleKind};
+
+    #[test]
+    fn test_orphaned_titles() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("orphaned");
+        let card = dir.join("grw0");
+        let internal = dir.join("ux0");
+        for path in [
+            card.join("PCSG00002"),
+            internal.join("PCSG00001"),
+            internal.join("PCSG00002"),
+            internal.join("PCSG00003"),
+        ] {
+            fs::create_dir_all(path)?;
+        }
+        fs::write(internal.join("PCSG00004"), "not a dir")?;
+
+        let apps = [Title::new(
+            "PCSG00001",
+            "PCSG00001",
+            "game",
+            "",
+            TitleKind::Vita,
+        )];
+        let titles =
+            get_orphaned_titles_in(&[card.to_str().unwrap(), internal.to_str().unwrap()], &apps);
+        assert_eq!(
+            titles
+                .iter()
+                .map(|title| title.real_id())
+                .collect::<Vec<_>>(),
+            ["PCSG00002", "PCSG00003"]
+        );
+        assert!(titles
+            .iter()
+            .all(|title| !title.installed() && title.has_save()));
+        assert_eq!(titles[0].state_text(), "未安装");
+        assert_eq!(apps[0].state_text(), "");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! # bandwidth limit
+//!
+//! transfers take tokens of bytes from a bucket refilled at the rate limit,
+//! the time is passed in so the bucket itself is deterministic
+use std::{
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::{
+    constant::{DOWNLOAD_BUF_SIZE, UPLOAD_SLICE_PER_SIZE},
+    retry::backoff,
+    settings::Settings,
+};
+
+static UPLOAD_BUCKET: Mutex<Option<TokenBucket>> = Mutex::new(None);
+static DOWNLOAD_BUCKET: Mutex<Option<TokenBucket>> = Mutex::new(None);
+static STARTED_AT: OnceLock<Instant> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBucket {
+    /// bytes per second
+    rate: u64,
+    /// max bytes of a burst
+    capacity: u64,
+    /// below zero if bytes are taken in advance
+    tokens: f64,
+    updated_at: Duration,
+}
+
+impl TokenBucket {
+    /// full bucket
+    pub fn new(rate: u64, capacity: u64, now: Duration) -> TokenBucket {
+        TokenBucket {
+            rate: rate.max(1),
+            capacity,
+            tokens: capacity as f64,
+            updated_at: now,
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    fn refill(&mut self, now: Duration) {
+        let elapsed = now.saturating_sub(self.updated_at);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.rate as f64).min(self.capacity as f64);
+        self.updated_at = self.updated_at.max(now);
+    }
+
+    /// # take bytes
+    ///
+    /// return how long to wait before sending them,
+    /// bytes more than the tokens are paid by waiting
+    pub fn take(&mut self, bytes: u64, now: Duration) -> Duration {
+        self.refill(now);
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate as f64)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+fn elapsed() -> Duration {
+    STARTED_AT.get_or_init(Instant::now).elapsed()
+}
+
+/// # wait for bandwidth of transfer
+///
+/// the bucket holds a slice of upload or a buffer of download,
+/// the limit is read from settings every time
+pub fn throttle(direction: Direction, bytes: u64) {
+    let (limit, capacity, bucket) = {
+        let settings = Settings::read();
+        match direction {
+            Direction::Upload => (
+                settings.upload_limit(),
+                UPLOAD_SLICE_PER_SIZE,
+                &UPLOAD_BUCKET,
+            ),
+            Direction::Download => (
+                settings.download_limit(),
+                DOWNLOAD_BUF_SIZE,
+                &DOWNLOAD_BUCKET,
+            ),
+        }
+    };
+    let mut bucket = bucket.lock().expect("lock token bucket");
+    let limit = match limit {
+        Some(limit) => limit,
+        None => {
+            *bucket = None;
+            return;
+        }
+    };
+    let now = elapsed();
+    if bucket.as_ref().map(TokenBucket::rate) != Some(limit) {
+        *bucket = Some(TokenBucket::new(limit, capacity as u64, now));
+    }
+    let wait = bucket
+        .as_mut()
+        .map_or(Duration::ZERO, |bucket| bucket.take(bytes, now));
+    // other transfers wait behind the taken tokens
+    drop(bucket);
+    if !wait.is_zero() {
+        info!(
+            "{:?} of {} bytes throttled for {:?}",
+            direction, bytes, wait
+        );
+        backoff(wait);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::TokenBucket;
+
+    #[test]
+    fn test_token_bucket() {
+        let secs = Duration::from_secs;
+        let mut bucket = TokenBucket::new(100, 200, secs(0));
+        // burst of capacity
+        assert_eq!(bucket.take(150, secs(0)), Duration::ZERO);
+        assert_eq!(bucket.take(50, secs(0)), Duration::ZERO);
+        // 100 bytes short
+        assert_eq!(bucket.take(100, secs(0)), secs(1));
+        // debt is paid at 1s
+        assert_eq!(bucket.take(100, secs(1)), secs(1));
+        // refill is capped by capacity
+        assert_eq!(bucket.take(200, secs(100)), Duration::ZERO);
+        // bigger than capacity
+        assert_eq!(bucket.take(500, secs(100)), secs(5));
+        // time going back does not refill
+        assert_eq!(bucket.take(100, secs(50)), secs(6));
+    }
+}
@@ -0,0 +1,193 @@
+//! # title sources
+//!
+//! installed apps are read from app.db of vita by `AppDbSource`,
+//! or from a json fixture by `JsonTitleSource` to run the ui on linux
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    constant::{GAME_CARD_SAVE_DIR, GAME_SAVE_DIR},
+    sfo::Sfo,
+    tai::{query_app_db, AppDbRow, Title, TitleKind},
+};
+
+// hashes of param.sfo keys in `tbl_appinfo`
+const KEY_CATEGORY: u32 = 566916785;
+const KEY_REAL_ID: u32 = 278217076;
+// patch first, it has the latest version
+const APP_DIRS: [&str; 3] = ["ux0:patch", "ux0:app", "gro0:app"];
+
+/// # source of installed apps
+pub trait TitleSource: Send {
+    /// load installed apps, called in background thread
+    fn load(&self) -> Result<Vec<Title>, Box<dyn Error>>;
+}
+
+/// # apps in app.db of vita
+///
+/// system apps are excluded
+pub struct AppDbSource;
+
+impl TitleSource for AppDbSource {
+    fn load(&self) -> Result<Vec<Title>, Box<dyn Error>> {
+        let sql = format!(
+            "select a.titleid, b.realid, c.title, e.iconpath, a.category
+               from (select titleid, val as category
+                       from tbl_appinfo
+                      where key = {}
+                        and titleid not like 'NPXS%') a,
+                    (select titleid, val as realid
+                       from tbl_appinfo
+                      where key = {}) b,
+                    tbl_appinfo_icon c,
+                    (select titleid, iconpath
+                       from tbl_appinfo_icon
+                      where type = 0) e
+              where a.titleid = b.titleid
+                and a.titleid = c.titleid
+                and a.titleid = e.titleid
+              order by a.titleid",
+            KEY_CATEGORY, KEY_REAL_ID
+        );
+        Ok(query_app_db(&sql)?
+            .iter()
+            .filter_map(title_from_row)
+            .map(|title| {
+                let save_dir = find_save_dir(title.real_id());
+                let version = read_app_version(title.title_id()).unwrap_or_default();
+                let last_played = save_dir.as_deref().and_then(modified_secs);
+                let category = title.category().to_string();
+                title
+                    .state(true, save_dir.is_some())
+                    .info(&version, &category, last_played)
+            })
+            .collect())
+    }
+}
+
+/// # apps in json file
+///
+/// a json array of titles, only `title_id`, `real_id` and `name` are required
+pub struct JsonTitleSource {
+    path: PathBuf,
+}
+
+impl JsonTitleSource {
+    pub fn new(path: impl AsRef<Path>) -> JsonTitleSource {
+        JsonTitleSource {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl TitleSource for JsonTitleSource {
+    fn load(&self) -> Result<Vec<Title>, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&fs::read(&self.path)?)?)
+    }
+}
+
+#[cfg(test)]
+const TITLES_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/titles.json");
+
+/// # titles of the json fixture in tests
+#[cfg(test)]
+pub fn fixture_titles() -> crate::tai::Titles {
+    crate::tai::Titles::with_source(JsonTitleSource::new(TITLES_FIXTURE))
+}
+
+/// title id, real id, name, icon path and category
+fn title_from_row(row: &AppDbRow) -> Option<Title> {
+    let value = |idx: usize| row.get(idx).cloned().flatten().unwrap_or_default();
+    let title_id = value(0);
+    if title_id.is_empty() {
+        return None;
+    }
+    let real_id = match value(1) {
+        real_id if real_id.is_empty() => title_id.clone(),
+        real_id => real_id,
+    };
+    let name = value(2).replace('\n', " ");
+    Some(
+        Title::new(&title_id, &real_id, &name, &value(3), TitleKind::Vita).info(
+            "",
+            &value(4),
+            None,
+        ),
+    )
+}
+
+fn find_save_dir(real_id: &str) -> Option<String> {
+    [GAME_CARD_SAVE_DIR, GAME_SAVE_DIR]
+        .into_iter()
+        .map(|dir| format!("{}/{}", dir, real_id))
+        .find(|dir| Path::new(dir).is_dir())
+}
+
+fn read_app_version(title_id: &str) -> Option<String> {
+    APP_DIRS.into_iter().find_map(|dir| {
+        let sfo = Sfo::open(format!("{}/{}/sce_sys/param.sfo", dir, title_id)).ok()?;
+        sfo.get_str("APP_VER").map(|version| version.to_string())
+    })
+}
+
+fn modified_secs(path: &str) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tai::{Title, TitleKind};
+
+    use super::{fixture_titles, title_from_row, JsonTitleSource, TitleSource, TITLES_FIXTURE};
+
+    #[test]
+    fn test_title_from_row() {
+        let row = vec![
+            Some("PCSG00001".to_string()),
+            None,
+            Some("Game\nName".to_string()),
+            Some("ur0:appmeta/PCSG00001/icon0.png".to_string()),
+            Some("gd".to_string()),
+        ];
+        let title = title_from_row(&row).unwrap();
+        assert_eq!(title.real_id(), "PCSG00001");
+        assert_eq!(title.name(), "Game Name");
+        assert_eq!(title.category(), "gd");
+        assert_eq!(title_from_row(&vec![None]), None);
+    }
+
+    #[test]
+    fn test_json_title_source() -> Result<(), Box<dyn std::error::Error>> {
+        let apps = JsonTitleSource::new(TITLES_FIXTURE).load()?;
+        assert_eq!(apps.len(), 3);
+        assert_eq!(
+            apps[0],
+            Title::new(
+                "PCSG00001",
+                "PCSG00001",
+                "Game",
+                "fixtures/icon0.png",
+                TitleKind::Vita
+            )
+            .info("01.02", "gd", Some(1709100000))
+        );
+        assert_eq!(
+            apps[0].info_text(1709100000 + 3 * 24 * 60 * 60),
+            "版本 01.02  3 天前玩过"
+        );
+        assert_eq!(apps[1].info_text(1709100000), "");
+        assert!(!apps[1].has_save() && apps[1].installed());
+        assert_eq!(apps[1].category(), "gdc");
+        assert_eq!(apps[2].real_id(), "PCSE00002");
+
+        let titles = fixture_titles();
+        assert_eq!(&titles.wait()[..3], &apps[..]);
+        assert_eq!(titles.get_title_by_idx(1), Some(&apps[1]));
+        Ok(())
+    }
+}
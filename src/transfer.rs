@@ -0,0 +1,549 @@
+//! # parallel transfers
+//!
+//! uploads and downloads of baidu drive with several connections,
+//! slices of `UPLOAD_SLICE_PER_SIZE` are uploaded at once and committed in
+//! order, downloads are split into range requests written at their offsets
+//!
+//! md5 of the whole file is computed while the data is read or streamed,
+//! a failed slice fails the transfer, which is retried as a whole by caller
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use log::{info, warn};
+use serde::Deserialize;
+use ureq::Agent;
+
+use crate::{
+    constant::{
+        BAIDU_FILE_URL, BAIDU_MULTIMEDIA_URL, BAIDU_SUPERFILE_URL, CORRUPTED_TEXT,
+        DOWNLOAD_BUF_SIZE, UPLOAD_SLICE_PER_SIZE,
+    },
+    throttle::{throttle, Direction},
+    utils::{current_time, get_file_md5, get_str_md5},
+};
+
+const USER_AGENT: &str = "pan.baidu.com";
+// max fs ids of one filemetas call
+const FILE_METAS_MAX_IDS: usize = 100;
+
+#[derive(Deserialize)]
+struct PrecreateRes {
+    errno: i32,
+    #[serde(default)]
+    uploadid: String,
+    /// 2 if the file is created by the md5s already
+    #[serde(default)]
+    return_type: i32,
+    /// index of slices to upload
+    #[serde(default)]
+    block_list: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct SliceRes {
+    #[serde(default)]
+    md5: String,
+}
+
+#[derive(Deserialize)]
+struct ErrnoRes {
+    errno: i32,
+}
+
+#[derive(Deserialize)]
+struct FileMetasRes {
+    errno: i32,
+    #[serde(default)]
+    list: Vec<FileMeta>,
+}
+
+#[derive(Deserialize)]
+struct FileMeta {
+    #[serde(default)]
+    fs_id: u64,
+    #[serde(default)]
+    dlink: String,
+    size: u64,
+    #[serde(default)]
+    md5: String,
+}
+
+/// # checksum reported by server
+///
+/// some md5s of baidu drive are obfuscated with letters after `f`,
+/// none if it is not a plain md5 which can be compared
+pub fn server_md5(md5: &str) -> Option<String> {
+    let md5 = md5.trim().to_lowercase();
+    (md5.len() == 32 && md5.chars().all(|c| c.is_ascii_hexdigit())).then_some(md5)
+}
+
+fn fetch_file_metas(
+    agent: &Agent,
+    access_token: &str,
+    fs_ids: &[u64],
+    is_dlink: bool,
+) -> Result<Vec<FileMeta>, Box<dyn Error>> {
+    let res = agent
+        .get(BAIDU_MULTIMEDIA_URL)
+        .set("User-Agent", USER_AGENT)
+        .query("method", "filemetas")
+        .query("access_token", access_token)
+        .query("fsids", &serde_json::to_string(fs_ids)?)
+        .query("dlink", if is_dlink { "1" } else { "0" })
+        .call()?
+        .into_string()?;
+    let metas: FileMetasRes = serde_json::from_str(&res)?;
+    if metas.errno != 0 || metas.list.is_empty() {
+        return Err(res.into());
+    }
+    Ok(metas.list)
+}
+
+fn fetch_file_meta(
+    agent: &Agent,
+    access_token: &str,
+    fs_id: u64,
+    is_dlink: bool,
+) -> Result<FileMeta, Box<dyn Error>> {
+    fetch_file_metas(agent, access_token, &[fs_id], is_dlink)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("找不到文件 {}", fs_id).into())
+}
+
+/// # md5 of cloud file reported by server
+///
+/// none if the server does not report a plain md5
+pub fn fetch_file_md5(access_token: &str, fs_id: u64) -> Result<Option<String>, Box<dyn Error>> {
+    let meta = fetch_file_meta(&ureq::agent(), access_token, fs_id, false)?;
+    Ok(server_md5(&meta.md5))
+}
+
+/// # size of cloud file reported by server
+pub fn fetch_file_size(access_token: &str, fs_id: u64) -> Result<u64, Box<dyn Error>> {
+    Ok(fetch_file_meta(&ureq::agent(), access_token, fs_id, false)?.size)
+}
+
+/// # md5s of cloud files reported by server
+///
+/// by fs id, files without plain md5 are left out
+pub fn fetch_file_md5s(
+    access_token: &str,
+    fs_ids: &[u64],
+) -> Result<HashMap<u64, String>, Box<dyn Error>> {
+    let agent = ureq::agent();
+    let mut md5s = HashMap::new();
+    for fs_ids in fs_ids.chunks(FILE_METAS_MAX_IDS) {
+        for meta in fetch_file_metas(&agent, access_token, fs_ids, false)? {
+            if let Some(md5) = server_md5(&meta.md5) {
+                md5s.insert(meta.fs_id, md5);
+            }
+        }
+    }
+    Ok(md5s)
+}
+
+/// # ranges of slices
+///
+/// `(offset, len)` of each slice, one empty slice for empty file
+fn slice_ranges(size: u64, slice_size: u64) -> Vec<(u64, u64)> {
+    if size == 0 {
+        return vec![(0, 0)];
+    }
+    (0..size.div_ceil(slice_size))
+        .map(|idx| {
+            let offset = idx * slice_size;
+            (offset, slice_size.min(size - offset))
+        })
+        .collect()
+}
+
+/// total size of `Content-Range: bytes 0-0/1234`
+fn parse_content_range(content_range: &str) -> Option<u64> {
+    content_range
+        .strip_prefix("bytes ")?
+        .split_once('/')?
+        .1
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn multipart_body(boundary: &str, name: &str, file_name: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+        boundary, name, file_name
+    )
+    .into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+/// # run jobs with workers
+///
+/// the first error stops the workers which are idle
+fn run_parallel<T: Sync>(
+    jobs: &[T],
+    parallelism: usize,
+    f: impl Fn(&T) -> Result<(), String> + Sync,
+) -> Result<(), Box<dyn Error>> {
+    let next = AtomicUsize::new(0);
+    let failed: Mutex<Option<String>> = Mutex::new(None);
+    thread::scope(|scope| {
+        for _ in 0..parallelism.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                if failed.lock().expect("lock failed job").is_some() {
+                    break;
+                }
+                let job = match jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    Some(job) => job,
+                    None => break,
+                };
+                if let Err(err) = f(job) {
+                    failed.lock().expect("lock failed job").get_or_insert(err);
+                    break;
+                }
+            });
+        }
+    });
+    match failed.into_inner().expect("lock failed job") {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+fn read_slice(path: &str, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0; len as usize];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// # upload file with slices at once
+///
+/// the md5 returned for each slice is checked, the upload fails
+/// if it does not match, return md5 of the whole file
+pub fn upload_parallel(
+    access_token: &str,
+    dir: &str,
+    name: &str,
+    path: &str,
+    is_overwrite: bool,
+    parallelism: usize,
+) -> Result<String, Box<dyn Error>> {
+    let size = fs::metadata(path)?.len();
+    let ranges = slice_ranges(size, UPLOAD_SLICE_PER_SIZE as u64);
+    let mut context = md5::Context::new();
+    let md5s = ranges
+        .iter()
+        .map(|&(offset, len)| {
+            let data = read_slice(path, offset, len)?;
+            context.consume(&data);
+            Ok(get_str_md5(&data))
+        })
+        .collect::<io::Result<Vec<String>>>()?;
+    let file_md5 = format!("{:x}", context.compute());
+    let block_list = serde_json::to_string(&md5s)?;
+    let cloud_path = format!("{}/{}", dir.trim_end_matches('/'), name);
+    // 0 fails if exists, 3 overwrites
+    let rtype = if is_overwrite { "3" } else { "0" };
+    let size = size.to_string();
+    let agent = ureq::agent();
+
+    let res = agent
+        .post(BAIDU_FILE_URL)
+        .set("User-Agent", USER_AGENT)
+        .query("method", "precreate")
+        .query("access_token", access_token)
+        .send_form(&[
+            ("path", &cloud_path),
+            ("size", &size),
+            ("isdir", "0"),
+            ("autoinit", "1"),
+            ("rtype", rtype),
+            ("block_list", &block_list),
+        ])?
+        .into_string()?;
+    let precreate: PrecreateRes = serde_json::from_str(&res)?;
+    if precreate.errno != 0 {
+        return Err(res.into());
+    }
+    if precreate.return_type == 2 {
+        info!("{} is uploaded by md5", cloud_path);
+        return Ok(file_md5);
+    }
+    info!(
+        "upload {} slices of {} with {} connections",
+        precreate.block_list.len(),
+        path,
+        parallelism
+    );
+
+    run_parallel(&precreate.block_list, parallelism, |&idx| {
+        let (offset, len) = *ranges.get(idx).ok_or("invalid slice index")?;
+        throttle(Direction::Upload, len);
+        let data = read_slice(path, offset, len).map_err(|err| err.to_string())?;
+        let boundary = format!("----save-cloud-{}-{}", current_time(), idx);
+        let res = agent
+            .post(BAIDU_SUPERFILE_URL)
+            .set("User-Agent", USER_AGENT)
+            .set(
+                "Content-Type",
+                &format!("multipart/form-data; boundary={}", boundary),
+            )
+            .query("method", "upload")
+            .query("access_token", access_token)
+            .query("type", "tmpfile")
+            .query("path", &cloud_path)
+            .query("uploadid", &precreate.uploadid)
+            .query("partseq", &idx.to_string())
+            .send_bytes(&multipart_body(&boundary, "file", name, &data))
+            .map_err(|err| err.to_string())?
+            .into_string()
+            .map_err(|err| err.to_string())?;
+        let slice: SliceRes = serde_json::from_str(&res).map_err(|_| res.clone())?;
+        if slice.md5 != md5s[idx] {
+            warn!(
+                "md5 of slice {} is {}, expect {}",
+                idx, slice.md5, md5s[idx]
+            );
+            return Err(format!("分片 {} {}：{}", idx, CORRUPTED_TEXT, res));
+        }
+        Ok(())
+    })?;
+
+    // slices are committed in order of block list
+    let res = agent
+        .post(BAIDU_FILE_URL)
+        .set("User-Agent", USER_AGENT)
+        .query("method", "create")
+        .query("access_token", access_token)
+        .send_form(&[
+            ("path", &cloud_path),
+            ("size", &size),
+            ("isdir", "0"),
+            ("rtype", rtype),
+            ("uploadid", &precreate.uploadid),
+            ("block_list", &block_list),
+        ])?
+        .into_string()?;
+    let create: ErrnoRes = serde_json::from_str(&res)?;
+    if create.errno != 0 {
+        return Err(res.into());
+    }
+    Ok(file_md5)
+}
+
+/// # download file with range requests at once
+///
+/// fall back to one stream if the server does not support ranges,
+/// the file is written to `{path}.part` and renamed when done,
+/// the part file is removed on error, return md5 of the file
+pub fn download_parallel(
+    access_token: &str,
+    fs_id: u64,
+    path: &str,
+    parallelism: usize,
+) -> Result<String, Box<dyn Error>> {
+    let part_path = format!("{}.part", path);
+    let res = download_part(access_token, fs_id, &part_path, parallelism)
+        .and_then(|md5| Ok(fs::rename(&part_path, path).map(|_| md5)?));
+    if res.is_err() && fs::metadata(&part_path).is_ok() {
+        if let Err(err) = fs::remove_file(&part_path) {
+            warn!("remove {} failed: {:?}", part_path, err);
+        }
+    }
+    res
+}
+
+fn download_part(
+    access_token: &str,
+    fs_id: u64,
+    part_path: &str,
+    parallelism: usize,
+) -> Result<String, Box<dyn Error>> {
+    let agent = ureq::agent();
+    let meta = fetch_file_meta(&agent, access_token, fs_id, true)?;
+    let url = format!("{}&access_token={}", meta.dlink, access_token);
+    // range of an empty file is not satisfiable
+    if meta.size == 0 {
+        info!("download {} which is empty", part_path);
+        File::create(part_path)?;
+        return Ok(format!("{:x}", md5::compute([])));
+    }
+
+    let probe = agent
+        .get(&url)
+        .set("User-Agent", USER_AGENT)
+        .set("Range", "bytes=0-0")
+        .call()?;
+    let is_range = probe.status() == 206
+        && probe
+            .header("Content-Range")
+            .and_then(parse_content_range)
+            .is_some_and(|total| total == meta.size);
+    // read the byte of range, so the connection is reused by the next request,
+    // a whole body without range is dropped with its connection
+    if is_range {
+        io::copy(&mut probe.into_reader(), &mut io::sink())?;
+    }
+    let md5 = if !is_range || parallelism <= 1 {
+        info!("download {} with one stream", part_path);
+        download_stream(&agent, &url, part_path)?
+    } else {
+        info!("download {} with {} connections", part_path, parallelism);
+        let file = File::create(part_path)?;
+        file.set_len(meta.size)?;
+        drop(file);
+        let ranges = slice_ranges(meta.size, UPLOAD_SLICE_PER_SIZE as u64);
+        run_parallel(&ranges, parallelism, |&(offset, len)| {
+            if len == 0 {
+                return Ok(());
+            }
+            throttle(Direction::Download, len);
+            download_range(&agent, &url, part_path, offset, len)
+        })?;
+        // ranges are written out of order
+        get_file_md5(part_path)?
+    };
+    Ok(md5)
+}
+
+/// return md5 of the stream
+fn download_stream(agent: &Agent, url: &str, path: &str) -> Result<String, Box<dyn Error>> {
+    let mut reader = agent
+        .get(url)
+        .set("User-Agent", USER_AGENT)
+        .call()?
+        .into_reader();
+    let mut file = File::create(path)?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0; DOWNLOAD_BUF_SIZE];
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        throttle(Direction::Download, len as u64);
+        file.write_all(&buf[..len])?;
+        context.consume(&buf[..len]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
+
+fn download_range(
+    agent: &Agent,
+    url: &str,
+    path: &str,
+    offset: u64,
+    len: u64,
+) -> Result<(), String> {
+    let res = agent
+        .get(url)
+        .set("User-Agent", USER_AGENT)
+        .set("Range", &format!("bytes={}-{}", offset, offset + len - 1))
+        .call()
+        .map_err(|err| err.to_string())?;
+    if res.status() != 206 {
+        return Err(format!(
+            "range of {} is not supported: {}",
+            offset,
+            res.status()
+        ));
+    }
+    let mut buf = Vec::with_capacity(len as usize);
+    res.into_reader()
+        .take(len)
+        .read_to_end(&mut buf)
+        .map_err(|err| err.to_string())?;
+    if buf.len() as u64 != len {
+        return Err(format!(
+            "range of {} is short: {}/{}, {}",
+            offset,
+            buf.len(),
+            len,
+            CORRUPTED_TEXT
+        ));
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|err| err.to_string())?;
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.write_all(&buf))
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{multipart_body, parse_content_range, run_parallel, server_md5, slice_ranges};
+
+    #[test]
+    fn test_slice_ranges() {
+        assert_eq!(slice_ranges(0, 4), vec![(0, 0)]);
+        assert_eq!(slice_ranges(4, 4), vec![(0, 4)]);
+        assert_eq!(slice_ranges(10, 4), vec![(0, 4), (4, 4), (8, 2)]);
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 0-0/1234"), Some(1234));
+        assert_eq!(parse_content_range("bytes */1234"), Some(1234));
+        assert_eq!(parse_content_range("bytes 0-0/*"), None);
+        assert_eq!(parse_content_range("1234"), None);
+    }
+
+    #[test]
+    fn test_server_md5() {
+        assert_eq!(
+            server_md5("E10ADC3949BA59ABBE56E057F20F883E"),
+            Some("e10adc3949ba59abbe56e057f20f883e".to_string())
+        );
+        assert_eq!(server_md5("e10adc394pba59abbe56e057f20f883e"), None);
+        assert_eq!(server_md5(""), None);
+    }
+
+    #[test]
+    fn test_multipart_body() {
+        let body = multipart_body("b", "file", "a.zip", b"data");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.zip\"\r\nContent-Type: application/octet-stream\r\n\r\ndata\r\n--b--\r\n"
+        );
+    }
+
+    #[test]
+    fn test_run_parallel() {
+        let jobs = (0..10).collect::<Vec<usize>>();
+        let done = AtomicUsize::new(0);
+        run_parallel(&jobs, 3, |_| {
+            done.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(done.load(Ordering::Relaxed), 10);
+
+        // the failed job stops its worker
+        let res = run_parallel(&jobs, 1, |&job| {
+            if job == 3 {
+                Err(format!("job {}", job))
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(res.unwrap_err().to_string(), "job 3");
+    }
+}
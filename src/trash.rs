@@ -0,0 +1,392 @@
+//! # trash
+//!
+//! deleted local files go to `TRASH_DIR` first, deleted cloud files go to
+//! `CLOUD_TRASH_DIR`, items are purged after some days or above the size cap
+//!
+//! ```text
+//! ux0:data/save-cloud/trash/1709100000123.json      info of item
+//! ux0:data/save-cloud/trash/1709100000123/a.zip     deleted file or dir
+//! ux0:data/save-cloud/trash/cloud.json              info of cloud items
+//! ```
+use std::{error::Error, ffi::OsStr, fs, io, path::Path, sync::Mutex};
+
+use log::{error, info};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accounts::active_account_name,
+    api::{Api, ApiOperates},
+    cloud::{start_create_dir, start_file_manager},
+    constant::{CLOUD_TRASH_DIR, TRASH_DIR},
+    settings::Settings,
+    utils::{current_time, dir_size, join_path, move_path, remove_path, write_file_atomic},
+};
+
+const CLOUD_INDEX_NAME: &str = "cloud.json";
+
+// serialize changes of cloud index
+static CLOUD_INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashItem {
+    /// name in trash
+    pub id: String,
+    /// original path
+    pub path: String,
+    /// unix time in seconds
+    pub deleted_at: u64,
+    pub size: u64,
+    #[serde(default)]
+    pub cloud: bool,
+    /// cloud account of cloud item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+impl TrashItem {
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    /// is the cloud item in drive of active account
+    fn is_in_active_account(&self) -> bool {
+        self.account.is_none() || self.account == active_account_name()
+    }
+}
+
+fn now() -> u64 {
+    (current_time() / 1000) as u64
+}
+
+/// # move local file or dir to trash
+///
+/// files in trash are deleted permanently,
+/// the trash is purged on startup and when it is opened,
+/// so the item just deleted is never purged at once
+pub fn move_to_trash(path: &str) -> io::Result<()> {
+    if Path::new(path).starts_with(TRASH_DIR) {
+        return remove_path(Path::new(path));
+    }
+    let item = trash_to(TRASH_DIR, path, current_time())?;
+    info!("move {} to trash {}", path, item.id);
+    Ok(())
+}
+
+/// # move cloud file or dir to cloud trash
+///
+/// files in cloud trash are deleted permanently
+pub fn move_to_cloud_trash(path: &str) -> Result<(), Box<dyn Error>> {
+    if path.starts_with(CLOUD_TRASH_DIR) {
+        return delete_cloud(path);
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let item = TrashItem {
+        id: format!("{} {}", current_time(), name),
+        path: path.to_string(),
+        deleted_at: now(),
+        size: 0,
+        cloud: true,
+        account: active_account_name(),
+    };
+    if let Err(err) = move_cloud(path, CLOUD_TRASH_DIR, &item.id) {
+        // create the cloud trash and retry
+        info!("move {} to cloud trash failed: {:?}", path, err);
+        let (parent, dir_name) = split_path(CLOUD_TRASH_DIR);
+        let _ = start_create_dir(parent, dir_name);
+        move_cloud(path, CLOUD_TRASH_DIR, &item.id)?;
+    }
+    let _lock = CLOUD_INDEX_LOCK.lock();
+    let mut items = read_cloud_index(TRASH_DIR);
+    items.push(item);
+    write_cloud_index(TRASH_DIR, &items)?;
+    Ok(())
+}
+
+/// # items in trash
+///
+/// local and cloud items, the latest first
+pub fn list_trash() -> Vec<TrashItem> {
+    let mut items = list_in(TRASH_DIR).unwrap_or_else(|err| {
+        if err.kind() != io::ErrorKind::NotFound {
+            error!("list trash failed: {:?}", err);
+        }
+        vec![]
+    });
+    items.extend(read_cloud_index(TRASH_DIR));
+    items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+    items
+}
+
+/// # restore item to the original path
+pub fn restore_from_trash(item: &TrashItem) -> Result<(), Box<dyn Error>> {
+    if !item.cloud {
+        return Ok(restore_in(TRASH_DIR, item)?);
+    }
+    check_account(item)?;
+    let (parent, name) = split_path(&item.path);
+    move_cloud(&join_path(CLOUD_TRASH_DIR, &item.id), parent, name)?;
+    remove_cloud_index_item(&item.id)
+}
+
+/// # delete item in trash permanently
+pub fn delete_from_trash(item: &TrashItem) -> Result<(), Box<dyn Error>> {
+    if !item.cloud {
+        return Ok(remove_in(TRASH_DIR, item)?);
+    }
+    check_account(item)?;
+    delete_cloud(&join_path(CLOUD_TRASH_DIR, &item.id))?;
+    remove_cloud_index_item(&item.id)
+}
+
+fn check_account(item: &TrashItem) -> Result<(), String> {
+    match &item.account {
+        Some(account) if !item.is_in_active_account() => {
+            Err(format!("请先切换到云端账号 {}", account))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// # purge expired items and items above the size cap
+///
+/// cloud items are purged by time only and when logged in,
+/// items of other accounts are purged after switching to them
+pub fn purge_trash() -> Result<(), Box<dyn Error>> {
+    purge_local_trash()?;
+    let (keep_secs, _) = purge_limits();
+    if keep_secs == 0 || !Api::get_read().is_login() {
+        return Ok(());
+    }
+    let expired = read_cloud_index(TRASH_DIR)
+        .into_iter()
+        .filter(|item| {
+            item.is_in_active_account() && now().saturating_sub(item.deleted_at) > keep_secs
+        })
+        .collect::<Vec<_>>();
+    for item in expired {
+        info!("purge cloud trash {}: {}", item.id, item.path);
+        delete_from_trash(&item)?;
+    }
+    Ok(())
+}
+
+/// # purge expired local items and items above the size cap
+pub fn purge_local_trash() -> io::Result<()> {
+    let (keep_secs, max_size) = purge_limits();
+    for item in purge_in(TRASH_DIR, now(), keep_secs, max_size)? {
+        info!("purge trash {}: {}", item.id, item.path);
+    }
+    Ok(())
+}
+
+/// keep seconds and max size of trash, 0 is unlimited
+fn purge_limits() -> (u64, u64) {
+    let settings = Settings::read();
+    (
+        settings.trash_keep_days() * 24 * 60 * 60,
+        settings.trash_max_size(),
+    )
+}
+
+fn info_path(dir: &str, id: &str) -> String {
+    format!("{}/{}.json", dir, id)
+}
+
+fn item_path(dir: &str, item: &TrashItem) -> String {
+    format!("{}/{}/{}", dir, item.id, item.name())
+}
+
+/// split path into parent and name
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => ("", path),
+    }
+}
+
+fn trash_to(dir: &str, path: &str, id: u128) -> io::Result<TrashItem> {
+    let from = Path::new(path);
+    if !from.exists() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, path.to_string()));
+    }
+    // id is unique in trash
+    let mut id = id;
+    while Path::new(&info_path(dir, &id.to_string())).exists() {
+        id += 1;
+    }
+    let item = TrashItem {
+        id: id.to_string(),
+        path: path.to_string(),
+        deleted_at: (id / 1000) as u64,
+        size: dir_size(from)?,
+        cloud: false,
+        account: None,
+    };
+    move_path(from, Path::new(&item_path(dir, &item)))?;
+    write_file_atomic(info_path(dir, &item.id), serde_json::to_vec(&item)?)?;
+    Ok(item)
+}
+
+fn list_in(dir: &str) -> io::Result<Vec<TrashItem>> {
+    let mut items = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new("json"))
+            || path.file_name() == Some(OsStr::new(CLOUD_INDEX_NAME))
+        {
+            continue;
+        }
+        match fs::read(&path)
+            .ok()
+            .and_then(|buf| serde_json::from_slice::<TrashItem>(&buf).ok())
+        {
+            Some(item) => items.push(item),
+            None => error!("invalid trash info: {:?}", path),
+        }
+    }
+    items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+    Ok(items)
+}
+
+fn restore_in(dir: &str, item: &TrashItem) -> io::Result<()> {
+    let to = Path::new(&item.path);
+    if to.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            item.path.to_string(),
+        ));
+    }
+    move_path(Path::new(&item_path(dir, item)), to)?;
+    remove_in(dir, item)
+}
+
+fn remove_in(dir: &str, item: &TrashItem) -> io::Result<()> {
+    let item_dir = format!("{}/{}", dir, item.id);
+    if Path::new(&item_dir).exists() {
+        fs::remove_dir_all(item_dir)?;
+    }
+    fs::remove_file(info_path(dir, &item.id))
+}
+
+/// purge items older than `keep_secs`, then the oldest items above `max_size`
+fn purge_in(dir: &str, now: u64, keep_secs: u64, max_size: u64) -> io::Result<Vec<TrashItem>> {
+    if !Path::new(dir).exists() {
+        return Ok(vec![]);
+    }
+    let mut purged = vec![];
+    let mut total = 0;
+    // the latest first
+    for item in list_in(dir)? {
+        let expired = keep_secs > 0 && now.saturating_sub(item.deleted_at) > keep_secs;
+        let full = max_size > 0 && total + item.size > max_size;
+        if expired || full {
+            remove_in(dir, &item)?;
+            purged.push(item);
+        } else {
+            total += item.size;
+        }
+    }
+    Ok(purged)
+}
+
+fn read_cloud_index(dir: &str) -> Vec<TrashItem> {
+    fs::read(format!("{}/{}", dir, CLOUD_INDEX_NAME))
+        .ok()
+        .and_then(|buf| serde_json::from_slice(&buf).ok())
+        .unwrap_or_default()
+}
+
+fn write_cloud_index(dir: &str, items: &[TrashItem]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    write_file_atomic(
+        format!("{}/{}", dir, CLOUD_INDEX_NAME),
+        serde_json::to_vec(items)?,
+    )
+}
+
+fn remove_cloud_index_item(id: &str) -> Result<(), Box<dyn Error>> {
+    let _lock = CLOUD_INDEX_LOCK.lock();
+    let mut items = read_cloud_index(TRASH_DIR);
+    items.retain(|item| item.id != id);
+    write_cloud_index(TRASH_DIR, &items)?;
+    Ok(())
+}
+
+fn encode(path: &str) -> String {
+    utf8_percent_encode(path, NON_ALPHANUMERIC).to_string()
+}
+
+fn move_cloud(path: &str, to_dir: &str, name: &str) -> Result<(), Box<dyn Error>> {
+    start_file_manager(
+        &encode(path),
+        Some(&encode(to_dir)),
+        Some(&encode(name)),
+        || ApiOperates::Move,
+    )?;
+    Ok(())
+}
+
+fn delete_cloud(path: &str) -> Result<(), Box<dyn Error>> {
+    start_file_manager(&encode(path), None, None, || ApiOperates::Delete)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::utils::TestDir;
+
+    use super::{list_in, purge_in, restore_in, split_path, trash_to};
+
+    #[test]
+    fn test_trash() -> Result<(), Box<dyn std::error::Error>> {
+        let root = TestDir::new("trash");
+        let trash = root.join("trash");
+        let trash = trash.to_str().unwrap();
+        let file = root.join("saves/a.zip");
+        let dir = root.join("saves/PCSG00001");
+        fs::create_dir_all(&dir)?;
+        fs::write(&file, "12345")?;
+        fs::write(dir.join("data.bin"), "123")?;
+
+        let file_item = trash_to(trash, file.to_str().unwrap(), 1_000_000)?;
+        let dir_item = trash_to(trash, dir.to_str().unwrap(), 1_000_000)?;
+        assert!(!file.exists() && !dir.exists());
+        assert_eq!(file_item.size, 5);
+        assert_eq!(file_item.name(), "a.zip");
+        // same time gets another id
+        assert_eq!(dir_item.id, "1000001");
+        assert_eq!(dir_item.size, 3);
+        assert_eq!(list_in(trash)?.len(), 2);
+
+        // restore
+        restore_in(trash, &dir_item)?;
+        assert_eq!(fs::read_to_string(dir.join("data.bin"))?, "123");
+        assert_eq!(list_in(trash)?, vec![file_item.clone()]);
+        fs::write(&file, "new")?;
+        assert!(restore_in(trash, &file_item).is_err());
+        fs::remove_file(&file)?;
+
+        // purge by time and size
+        let old = trash_to(trash, dir.to_str().unwrap(), 2_000_000)?;
+        assert_eq!(purge_in(trash, 2_001, 1_000, 0)?, vec![file_item]);
+        assert_eq!(purge_in(trash, 2_001, 1_000, 0)?, vec![]);
+        fs::write(&file, "1234567890")?;
+        let new = trash_to(trash, file.to_str().unwrap(), 3_000_000)?;
+        assert_eq!(purge_in(trash, 3_000, 0, 10)?, vec![old]);
+        assert_eq!(list_in(trash)?, vec![new]);
+        assert!(!Path::new(&format!("{}/2000000", trash)).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(split_path("/apps/a b.zip"), ("/apps", "a b.zip"));
+        assert_eq!(split_path("/a.zip"), ("/", "a.zip"));
+        assert_eq!(split_path("a.zip"), ("", "a.zip"));
+    }
+}
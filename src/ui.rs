@@ -1,10 +1,17 @@
+pub mod ui_account;
+pub mod ui_accounts;
 pub mod ui_base;
+pub mod ui_check_list;
 pub mod ui_cloud;
 pub mod ui_desktop;
 pub mod ui_dialog;
 pub mod ui_drawer;
+pub mod ui_journal;
 pub mod ui_list;
 pub mod ui_loading;
+pub mod ui_log;
+pub mod ui_modal;
 pub mod ui_scroll_progress;
 pub mod ui_titles;
 pub mod ui_toast;
+pub mod ui_trash;
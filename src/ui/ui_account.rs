@@ -0,0 +1,87 @@
+use std::sync::{Arc, RwLock};
+
+use log::error;
+
+use crate::{
+    accounts::{now, CloudAccount},
+    cloud::{fetch_quota, Quota},
+    constant::ACCOUNT_BOTTOM_BAR_TEXT,
+    utils::{format_duration, format_size},
+    vita2d::{is_button, SceCtrlButtons},
+};
+
+use super::{ui_dialog::UIDialog, ui_modal::UIModal};
+
+type QuotaState = Arc<RwLock<Option<Result<Quota, String>>>>;
+
+pub enum AccountInfoAction {
+    Reauth,
+    Logout,
+}
+
+fn info_texts(account: &CloudAccount, quota: &QuotaState) -> [String; 4] {
+    let expiry = if account.expires_at == 0 {
+        "未知".to_string()
+    } else if account.is_expired(now()) {
+        "已过期".to_string()
+    } else {
+        format!(
+            "剩余 {}",
+            format_duration(account.expires_at.saturating_sub(now()))
+        )
+    };
+    let quota = match &*quota.read().expect("read quota") {
+        Some(Ok(quota)) => format!(
+            "已用 {} / 共 {}",
+            format_size(quota.used),
+            format_size(quota.total)
+        ),
+        Some(Err(err)) => format!("获取失败：{}", err),
+        None => "正在获取...".to_string(),
+    };
+    [
+        format!("账号：{}", account.name),
+        format!("令牌有效期：{}", expiry),
+        format!("备份目录：{}", account.root_dir),
+        format!("网盘空间：{}", quota),
+    ]
+}
+
+pub struct UIAccount;
+
+impl UIAccount {
+    /// # present user, token expiry and quota of active account
+    ///
+    /// quota is fetched in background, return the confirmed action
+    pub fn present(account: &CloudAccount) -> Option<AccountInfoAction> {
+        let quota: QuotaState = Arc::new(RwLock::new(None));
+        let quota_res = Arc::clone(&quota);
+        tokio::spawn(async move {
+            let res = fetch_quota().map_err(|err| {
+                error!("fetch quota failed: {:?}", err);
+                err.to_string()
+            });
+            *quota_res.write().expect("write quota") = Some(res);
+        });
+        let mut modal = UIModal::new(ACCOUNT_BOTTOM_BAR_TEXT);
+        let mut action = None;
+        loop {
+            let buttons = modal.buttons();
+            if is_button(buttons, SceCtrlButtons::SceCtrlCross) {
+                break;
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlCircle) {
+                action = Some(AccountInfoAction::Reauth);
+                break;
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlTriangle)
+                && UIDialog::present(&format!("退出登录 {}？", account.name))
+            {
+                action = Some(AccountInfoAction::Logout);
+                break;
+            }
+
+            modal.draw_lines("账号详情", &info_texts(account, &quota));
+        }
+        modal.close(|modal| modal.draw_lines("账号详情", &info_texts(account, &quota)));
+        action
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use log::error;
+
+use crate::{
+    accounts::{
+        deactivate_account, logout_and_remove_account, refresh_account_token, set_root_dir,
+        switch_account, Accounts, CloudAccount,
+    },
+    constant::ACCOUNTS_BOTTOM_BAR_TEXT,
+    ime::show_keyboard,
+    log::Operation,
+    utils::current_time,
+    vita2d::{is_button, rgba, SceCtrlButtons},
+};
+
+use super::{
+    ui_account::{AccountInfoAction, UIAccount},
+    ui_cloud::list_state::ListState,
+    ui_dialog::UIDialog,
+    ui_loading::Loading,
+    ui_modal::{UIModal, DISPLAY_ROW},
+    ui_toast::Toast,
+};
+
+const ADD_ACCOUNT_TEXT: &str = "+ 添加账号";
+
+pub enum AccountAction {
+    Switch(String),
+    Details(CloudAccount),
+    Add,
+    Remove(String),
+}
+
+fn account_texts(accounts: &Accounts) -> Vec<String> {
+    let now = (current_time() / 1000) as u64;
+    accounts
+        .accounts
+        .iter()
+        .map(|account| {
+            format!(
+                "{} {}  {}{}",
+                if accounts.active.as_ref() == Some(&account.name) {
+                    "●"
+                } else {
+                    "○"
+                },
+                account.name,
+                account.root_dir,
+                if account.is_expired(now) {
+                    "  [已过期]"
+                } else {
+                    ""
+                }
+            )
+        })
+        .chain([ADD_ACCOUNT_TEXT.to_string()])
+        .collect()
+}
+
+pub struct UIAccounts;
+
+impl UIAccounts {
+    fn draw(modal: &UIModal, texts: &[String], list_state: &ListState) {
+        let title = format!("云端账号  ({})", texts.len() - 1);
+        modal.draw_list(&title, list_state, texts.len(), |i| {
+            (texts[i].to_string(), rgba(0xff, 0xff, 0xff, 0xff))
+        });
+    }
+
+    /// # present accounts
+    ///
+    /// the backup dir of account is changed here,
+    /// return the confirmed action
+    pub fn present() -> Option<AccountAction> {
+        let mut texts = account_texts(&Accounts::read());
+        let mut list_state = ListState::new(DISPLAY_ROW);
+        let mut modal = UIModal::new(ACCOUNTS_BOTTOM_BAR_TEXT);
+        let mut action = None;
+        loop {
+            let buttons = modal.buttons();
+            let selected = Accounts::read()
+                .accounts
+                .get(list_state.selected_idx as usize)
+                .cloned();
+            if is_button(buttons, SceCtrlButtons::SceCtrlCross) {
+                break;
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlCircle) {
+                match selected {
+                    Some(account) => {
+                        action = Some(if Accounts::read().active.as_ref() == Some(&account.name) {
+                            AccountAction::Details(account)
+                        } else {
+                            AccountAction::Switch(account.name)
+                        });
+                    }
+                    None => action = Some(AccountAction::Add),
+                }
+                break;
+            } else if let Some(account) = selected {
+                if is_button(buttons, SceCtrlButtons::SceCtrlSquare) {
+                    let root_dir = show_keyboard(&account.root_dir).trim().to_string();
+                    if root_dir.starts_with('/') {
+                        set_root_dir(&account.name, &root_dir);
+                        texts = account_texts(&Accounts::read());
+                    } else if !root_dir.is_empty() {
+                        Toast::show("备份目录需要以 / 开头！".to_string());
+                    }
+                } else if is_button(buttons, SceCtrlButtons::SceCtrlTriangle)
+                    && UIDialog::present(&format!("移除账号 {}？", account.name))
+                {
+                    action = Some(AccountAction::Remove(account.name));
+                    break;
+                }
+            }
+            list_state.update(texts.len() as i32, buttons);
+
+            UIAccounts::draw(&modal, &texts, &list_state);
+        }
+        modal.close(|modal| UIAccounts::draw(modal, &texts, &list_state));
+        action
+    }
+
+    /// # present accounts and do the action
+    ///
+    /// listings follow the active account, they are swapped by the callers
+    pub fn present_and_do(pending: &Arc<AtomicBool>) {
+        match UIAccounts::present() {
+            Some(AccountAction::Switch(name)) => {
+                let pending = Arc::clone(pending);
+                pending.store(true, Ordering::Relaxed);
+                Loading::show();
+                tokio::spawn(Operation::begin().run(async move {
+                    Loading::notify_title("正在切换账号".to_string());
+                    Loading::notify_desc(name.clone());
+                    match switch_account(&name) {
+                        Ok(_) => Toast::show(format!("已切换到账号 {}", name)),
+                        Err(err) => {
+                            error!("switch to account {} failed: {:?}", name, err);
+                            Toast::show(format!("切换账号失败：{}", err));
+                        }
+                    }
+                    Loading::hide();
+                    pending.store(false, Ordering::Relaxed);
+                }));
+            }
+            Some(AccountAction::Details(account)) => match UIAccount::present(&account) {
+                Some(AccountInfoAction::Reauth) => {
+                    let pending = Arc::clone(pending);
+                    pending.store(true, Ordering::Relaxed);
+                    Loading::show();
+                    tokio::spawn(Operation::begin().run(async move {
+                        Loading::notify_title("正在重新授权".to_string());
+                        Loading::notify_desc(account.name.clone());
+                        match refresh_account_token(&account.name) {
+                            Ok(_) => Toast::show("重新授权完成！".to_string()),
+                            Err(err) => {
+                                // refresh token is invalid too, log in again
+                                error!("refresh token of {} failed: {:?}", account.name, err);
+                                deactivate_account();
+                                Toast::show("重新授权失败，请扫码登录！".to_string());
+                            }
+                        }
+                        Loading::hide();
+                        pending.store(false, Ordering::Relaxed);
+                    }));
+                }
+                Some(AccountInfoAction::Logout) => {
+                    logout_and_remove_account(&account.name);
+                    Toast::show(format!("已退出登录 {}", account.name));
+                }
+                None => {}
+            },
+            Some(AccountAction::Add) => {
+                deactivate_account();
+                Toast::show("请扫码登录新账号！".to_string());
+            }
+            Some(AccountAction::Remove(name)) => {
+                logout_and_remove_account(&name);
+                Toast::show(format!("已移除账号 {}", name));
+            }
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{
+    constant::CHECK_LIST_BOTTOM_BAR_TEXT,
+    vita2d::{is_button, rgba, SceCtrlButtons},
+};
+
+use super::{
+    ui_cloud::list_state::ListState,
+    ui_modal::{UIModal, DISPLAY_ROW},
+};
+
+pub struct UICheckList;
+
+impl UICheckList {
+    fn draw(
+        modal: &UIModal,
+        title: &str,
+        items: &[String],
+        checked: &[bool],
+        list_state: &ListState,
+    ) {
+        let count = checked.iter().filter(|&&c| c).count();
+        let title = format!("{}  ({}/{})", title, count, items.len());
+        modal.draw_list(&title, list_state, items.len(), |i| {
+            let text = format!("{}  {}", if checked[i] { "■" } else { "□" }, items[i]);
+            (text, rgba(0xff, 0xff, 0xff, 0xff))
+        });
+    }
+
+    /// # present check list
+    ///
+    /// return checked state of items, `None` if canceled
+    pub fn present(title: &str, items: &[String], checked: bool) -> Option<Vec<bool>> {
+        let mut checked = vec![checked; items.len()];
+        let mut list_state = ListState::new(DISPLAY_ROW);
+        let mut modal = UIModal::new(CHECK_LIST_BOTTOM_BAR_TEXT);
+        let is_confirm = loop {
+            let buttons = modal.buttons();
+            if is_button(buttons, SceCtrlButtons::SceCtrlCross) {
+                break false;
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlStart) {
+                break true;
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlCircle) {
+                if let Some(c) = checked.get_mut(list_state.selected_idx as usize) {
+                    *c = !*c;
+                }
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlSquare) {
+                let is_all_checked = checked.iter().all(|&c| c);
+                checked.iter_mut().for_each(|c| *c = !is_all_checked);
+            }
+            list_state.update(items.len() as i32, buttons);
+
+            UICheckList::draw(&modal, title, items, &checked, &list_state);
+        };
+        modal.close(|modal| UICheckList::draw(modal, title, items, &checked, &list_state));
+        if is_confirm {
+            Some(checked)
+        } else {
+            None
+        }
+    }
+}
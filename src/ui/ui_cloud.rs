@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     fs,
     path::Path,
     sync::{
@@ -11,17 +12,31 @@ use log::{error, info};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
 use crate::{
-    api::{Api, AuthData},
+    accounts::{active_account_name, save_login_account},
+    api::{Api, ApiOperates, AuthData},
     app::AppData,
+    cloud::{
+        fetch_cloud_file_size, start_create_dir, start_download, start_file_manager,
+        upload_to_cloud,
+    },
     constant::{
-        HOME_PAGE_URL, SAVE_LIST_QR_CODE_SIZE, SCAN_QR_CODE_TIPS, SCREEN_HEIGHT, SCREEN_WIDTH,
+        HOME_PAGE_URL, OFFLINE_TEXT, SAVE_LIST_QR_CODE_SIZE, SCAN_QR_CODE_TIPS, SCREEN_HEIGHT,
+        SCREEN_WIDTH,
     },
+    glob::PathFilter,
     ime::{get_current_format_time, show_keyboard},
+    journal::{JournalEntry, JournalOp},
+    listing_cache::invalidate_listing,
+    log::Operation,
+    network::is_online,
+    settings::Settings,
+    space::check_space,
     tai::{mount_pfs, unmount_pfs},
+    trash::{move_to_cloud_trash, move_to_trash},
     ui::ui_toast::Toast,
     utils::{
-        copy_dir_all, join_path, normalize_path, update_sfo_file_with_current_account_id, zip_dir,
-        zip_extract, zip_file,
+        copy_dir_all, dir_size, join_path, normalize_path, update_sfo_file_with_current_account_id,
+        zip_dir, zip_extract, zip_file,
     },
     vita2d::{
         is_button, rgba, vita2d_draw_text, vita2d_draw_texture, vita2d_line, vita2d_load_png_buf,
@@ -37,7 +52,8 @@ use self::{
 };
 
 use super::{
-    ui_base::UIBase, ui_dialog::UIDialog, ui_loading::Loading, ui_scroll_progress::ScrollProgress,
+    ui_accounts::UIAccounts, ui_base::UIBase, ui_dialog::UIDialog, ui_loading::Loading,
+    ui_scroll_progress::ScrollProgress,
     ui_titles::save_menu::save_list::save_list_cloud::QrCodeState,
 };
 
@@ -55,6 +71,10 @@ pub struct UICloud {
     pub qr_code_state: QrCodeState,
     pub menu: Menu,
     pub scroll_progress: ScrollProgress,
+    // active account of cloud panel
+    pub account: Option<String>,
+    // cloud panels of other accounts
+    pub cloud_panels: HashMap<String, Panel>,
 }
 
 impl UICloud {
@@ -72,10 +92,31 @@ impl UICloud {
             qr_code_state: QrCodeState::new(),
             menu: Menu::new(),
             scroll_progress: ScrollProgress::new(40.0, 110.0),
+            account: active_account_name(),
+            cloud_panels: HashMap::new(),
+        }
+    }
+
+    /// # swap cloud panel when active account changes
+    ///
+    /// dirs of each account are kept until the app exits
+    fn sync_account(&mut self) {
+        let account = active_account_name();
+        if account == self.account {
+            return;
+        }
+        let panel = account
+            .as_ref()
+            .and_then(|name| self.cloud_panels.remove(name))
+            .unwrap_or_else(|| Panel::new_cloud(SCREEN_WIDTH / 2 + 12));
+        let panel = std::mem::replace(&mut self.panels[2], panel);
+        if let Some(old) = std::mem::replace(&mut self.account, account) {
+            self.cloud_panels.insert(old, panel);
         }
     }
 
     pub fn init(&mut self) {
+        self.sync_account();
         for panel in self.panels.iter_mut() {
             panel.init();
         }
@@ -98,7 +139,7 @@ impl UICloud {
         if Arc::strong_count(&self.qr_code_state.qr_code_buf) > 1 {
             return;
         }
-        if !Api::get_read().is_login() {
+        if !Api::get_read().is_login() && is_online() {
             self.start_auth();
         }
     }
@@ -141,6 +182,7 @@ impl UICloud {
                             token_res.access_token.as_ref().unwrap(),
                         ) {
                             Ok(name_of_pancake) => {
+                                save_login_account(&token_res, &name_of_pancake);
                                 Api::update_auth_data(
                                     api_type,
                                     Some(AuthData::new(token_res, name_of_pancake)),
@@ -238,10 +280,10 @@ impl UICloud {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在创建文件夹".to_string());
             Loading::notify_desc(input.clone());
-            match Api::start_create_dir(&from_path, &input) {
+            match start_create_dir(&from_path, &input) {
                 Ok(_) => {
                     do_cloud_action(
                         &from_path,
@@ -258,7 +300,7 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
@@ -275,7 +317,9 @@ impl UICloud {
             Toast::show("名字相同，重命名取消！".to_string());
             return false;
         }
-        match fs::rename(old_name, new_name) {
+        let res = fs::rename(&old_name, &new_name);
+        JournalEntry::new(JournalOp::Rename, "", &old_name, &new_name).record(&res);
+        match res {
             Ok(_) => {
                 self.get_from_panel().refresh_current_dir();
                 if from_path == to_path {
@@ -303,15 +347,18 @@ impl UICloud {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在重命名".to_string());
             Loading::notify_desc(input.clone());
-            match Api::start_file_manager(
+            let res = start_file_manager(
                 &utf8_percent_encode(&from, NON_ALPHANUMERIC).to_string(),
                 None,
                 Some(&utf8_percent_encode(&input, NON_ALPHANUMERIC).to_string()),
-                crate::api::ApiOperates::Rename,
-            ) {
+                || ApiOperates::Rename,
+            );
+            JournalEntry::new(JournalOp::Rename, "", &from, &join_path(&from_path, &input))
+                .record(&res);
+            match res {
                 Ok(_) => {
                     do_cloud_action(
                         &from_path,
@@ -328,19 +375,13 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
 
-    pub fn delete_local(
-        &mut self,
-        is_dir: bool,
-        from_path: &str,
-        name: &str,
-        to_path: &str,
-    ) -> bool {
-        if !UIDialog::present(&format!("确定删除 {} ？", name)) {
+    pub fn delete_local(&mut self, from_path: &str, name: &str, to_path: &str) -> bool {
+        if !UIDialog::present(&format!("确定删除 {} 到回收站？", name)) {
             return false;
         }
         let (from_dir_pending_to_enter, to_dir_pending_to_enter, from_path, to_path, _, _, name) =
@@ -348,13 +389,12 @@ impl UICloud {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             let abs_path = join_path(&from_path, &name);
-            match if is_dir {
-                fs::remove_dir_all(abs_path)
-            } else {
-                fs::remove_file(abs_path)
-            } {
+            let entry = JournalEntry::new(JournalOp::Delete, "", &abs_path, "").size_of(&abs_path);
+            let res = move_to_trash(&abs_path);
+            entry.record(&res);
+            match res {
                 Ok(_) => {
                     do_local_action(
                         &from_path,
@@ -379,13 +419,13 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
 
     pub fn delete_cloud(&mut self, from_path: &str, name: &str, to_path: &str) -> bool {
-        if !UIDialog::present(&format!("确定删除 {} ？", name)) {
+        if !UIDialog::present(&format!("确定删除 {} 到回收站？", name)) {
             return false;
         }
         let (from_dir_pending_to_enter, _, from_path, _, _, _, name) =
@@ -393,15 +433,13 @@ impl UICloud {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在删除文件".to_string());
             Loading::notify_desc(name.to_string());
-            match Api::start_file_manager(
-                &utf8_percent_encode(&join_path(&from_path, &name), NON_ALPHANUMERIC).to_string(),
-                None,
-                None,
-                crate::api::ApiOperates::Delete,
-            ) {
+            let cloud_path = join_path(&from_path, &name);
+            let res = move_to_cloud_trash(&cloud_path);
+            JournalEntry::new(JournalOp::Delete, "", &cloud_path, "").record(&res);
+            match res {
                 Ok(_) => {
                     do_cloud_action(
                         &from_path,
@@ -418,7 +456,7 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
@@ -434,10 +472,14 @@ impl UICloud {
             Toast::show("目标文件夹不能是源文件夹的子文件夹！".to_string());
             return false;
         }
+        if let Err(err) = check_space(&to, dir_size(&from).unwrap_or(0)) {
+            Toast::show(err.to_string());
+            return false;
+        }
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             match if is_dir {
                 copy_dir_all(from, to)
             } else {
@@ -459,7 +501,7 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
@@ -485,7 +527,7 @@ impl UICloud {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             match fs::rename(from, to) {
                 Ok(_) => {
                     do_local_action(
@@ -509,7 +551,7 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
@@ -532,17 +574,27 @@ impl UICloud {
             return false;
         }
         let input_path = join_path(from_path, name);
+        if let Err(err) = check_space(&output_path, dir_size(&input_path).unwrap_or(0)) {
+            Toast::show(err.to_string());
+            return false;
+        }
         let (from_dir_pending_to_enter, to_dir_pending_to_enter, from_path, to_path, _, _, name) =
             self.get_action_params(from_path, name, to_path);
+        let compression = Settings::read().compression;
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在压缩".to_string());
             match if is_dir {
-                zip_dir(&input_path, &output_path, &[])
+                zip_dir(
+                    &input_path,
+                    &output_path,
+                    &PathFilter::default(),
+                    compression,
+                )
             } else {
-                zip_file(&from_path, &name, &output_path)
+                zip_file(&from_path, &name, &output_path, compression)
             } {
                 Ok(_) => {
                     do_local_action(
@@ -568,7 +620,7 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
@@ -596,7 +648,7 @@ impl UICloud {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在解压".to_string());
             Loading::notify_desc(name.clone());
             match zip_extract(join_path(&from_path, &name), output_dir, None) {
@@ -624,7 +676,7 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
@@ -635,10 +687,14 @@ impl UICloud {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在上传".to_string());
             Loading::notify_desc(name.to_string());
-            match Api::upload_to_cloud(&to_path, &name, &from, false) {
+            let res = upload_to_cloud(&to_path, &name, &from, false);
+            JournalEntry::new(JournalOp::Upload, "", &from, &join_path(&to_path, &name))
+                .size_of(&from)
+                .record(&res);
+            match res {
                 Ok(_) => {
                     do_cloud_action(
                         &to_path,
@@ -655,7 +711,7 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
@@ -678,15 +734,29 @@ impl UICloud {
             Toast::show("目标文件已存在！".to_string());
             return false;
         }
-        let (_, to_dir_pending_to_enter, _, to_path, _, to, name) =
+        let (_, to_dir_pending_to_enter, _, to_path, from, to, name) =
             self.get_action_params(&from_path, name, to_path);
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在下载".to_string());
             Loading::notify_desc(name.to_string());
-            match Api::start_download(fs_id, &to) {
+            // the part file is renamed to `to` after it is finished
+            if let Err(err) =
+                fetch_cloud_file_size(fs_id).and_then(|size| check_space(&to, size.unwrap_or(0)))
+            {
+                error!("check space of {} failed: {:?}", to, err);
+                Toast::show(err.to_string());
+                pending.store(false, Ordering::Relaxed);
+                Loading::hide();
+                return;
+            }
+            let res = start_download(fs_id, &to);
+            JournalEntry::new(JournalOp::Download, "", &from, &to)
+                .size_of(&to)
+                .record(&res);
+            match res {
                 Ok(_) => {
                     do_local_action(
                         &to_path,
@@ -698,12 +768,14 @@ impl UICloud {
                 }
                 Err(err) => {
                     error!("download failed: {:?}", err);
+                    // do not leave the partial file
+                    let _ = fs::remove_file(&to);
                     Toast::show(format!("下载失败"));
                 }
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
@@ -720,17 +792,27 @@ impl UICloud {
         let input_path = join_path(from_path, name);
         let (_, to_dir_pending_to_enter, from_path, to_path, output_path, _, _) =
             self.get_action_params(from_path, &tmp_name_with_ext, to_path);
+        if let Err(err) = check_space(&output_path, dir_size(&input_path).unwrap_or(0)) {
+            Toast::show(err.to_string());
+            return false;
+        }
         let name = name.to_string();
+        let compression = Settings::read().compression;
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在压缩".to_string());
             Loading::notify_desc(name.to_string());
             let is_success = match if is_dir {
-                zip_dir(&input_path, &output_path, &[])
+                zip_dir(
+                    &input_path,
+                    &output_path,
+                    &PathFilter::default(),
+                    compression,
+                )
             } else {
-                zip_file(&from_path, &name, &output_path)
+                zip_file(&from_path, &name, &output_path, compression)
             } {
                 Ok(_) => true,
                 Err(err) => {
@@ -741,7 +823,16 @@ impl UICloud {
             };
             if is_success {
                 Loading::notify_title("正在上传".to_string());
-                match Api::upload_to_cloud(&to_path, &name_with_ext, &output_path, false) {
+                let res = upload_to_cloud(&to_path, &name_with_ext, &output_path, false);
+                JournalEntry::new(
+                    JournalOp::Upload,
+                    "",
+                    &input_path,
+                    &join_path(&to_path, &name_with_ext),
+                )
+                .size_of(&output_path)
+                .record(&res);
+                match res {
                     Ok(_) => {
                         do_cloud_action(
                             &to_path,
@@ -764,11 +855,17 @@ impl UICloud {
             }
             pending.store(false, Ordering::Relaxed);
             Loading::hide();
-        });
+        }));
 
         true
     }
 
+    /// refetch the listing of cloud dir even if its cache is fresh
+    pub fn refresh_cloud_dir(&mut self, path: &str) {
+        invalidate_listing(path);
+        self.get_from_panel().refresh_current_dir();
+    }
+
     pub fn get_from_panel(&mut self) -> &mut Panel {
         self.panels.get_mut(self.active_panel).unwrap()
     }
@@ -789,10 +886,10 @@ impl UICloud {
         let left_text = format!("本地：{}", &left_panel.current_dir_path());
         let right_text = format!(
             "{}：{}",
-            if self.right_panel == 1 {
-                "本地（右）"
-            } else {
-                "网盘"
+            match (self.right_panel, &self.account) {
+                (1, _) => "本地（右）".to_string(),
+                (_, Some(account)) => format!("网盘（{}）", account),
+                _ => "网盘".to_string(),
             },
             &right_panel.current_dir_path()
         );
@@ -882,9 +979,23 @@ impl UIBase for UICloud {
                 let from_path = from_panel.current_dir_path();
                 let to_path = to_panel.current_dir_path();
                 let is_from_local = !from_path.starts_with("/");
+                let is_cloud_action = !is_from_local
+                    || matches!(
+                        self.menu.get_selected_action(),
+                        Some(menu::MenuAction::Upload | menu::MenuAction::ZipUpload)
+                    );
+                if is_cloud_action && !is_online() {
+                    Toast::show(OFFLINE_TEXT.to_string());
+                    self.menu.close();
+                    return;
+                }
                 let item = from_panel.current_item();
                 if item.is_none() {
                     match self.menu.get_selected_action().unwrap() {
+                        menu::MenuAction::Refresh => {
+                            self.refresh_cloud_dir(&from_path);
+                            self.menu.close();
+                        }
                         menu::MenuAction::NewDir => {
                             if is_from_local {
                                 self.create_local_dir(&from_path, &to_path);
@@ -902,6 +1013,10 @@ impl UIBase for UICloud {
                 } else {
                     let item = item.unwrap();
                     let is_close_menu = match self.menu.get_selected_action().unwrap() {
+                        menu::MenuAction::Refresh => {
+                            self.refresh_cloud_dir(&from_path);
+                            true
+                        }
                         menu::MenuAction::NewDir => {
                             if is_from_local {
                                 self.create_local_dir(&from_path, &to_path)
@@ -928,12 +1043,7 @@ impl UIBase for UICloud {
                         }
                         menu::MenuAction::Delete => {
                             if is_from_local {
-                                self.delete_local(
-                                    item.is_dir,
-                                    &from_path,
-                                    &item.name.to_string(),
-                                    &to_path,
-                                )
+                                self.delete_local(&from_path, &item.name.to_string(), &to_path)
                             } else {
                                 if Api::is_eat_pancake_valid() {
                                     self.delete_cloud(&from_path, &item.name.to_string(), &to_path)
@@ -998,9 +1108,16 @@ impl UIBase for UICloud {
                         menu::MenuAction::ChangeAccountId => {
                             if let Some(path) = Path::new(&from_path).parent() {
                                 mount_pfs(path.to_str().unwrap());
-                                if let Ok(()) = update_sfo_file_with_current_account_id(&join_path(
-                                    &from_path, &item.name,
-                                )) {
+                                let sfo_path = join_path(&from_path, &item.name);
+                                let res = update_sfo_file_with_current_account_id(&sfo_path);
+                                JournalEntry::new(
+                                    JournalOp::ChangeAccountId,
+                                    "",
+                                    &sfo_path,
+                                    &sfo_path,
+                                )
+                                .record(&res);
+                                if let Ok(()) = res {
                                     Toast::show("修改为当前账号完成！".to_string());
                                 } else {
                                     Toast::show("修改为当前账号失败！".to_string());
@@ -1030,6 +1147,8 @@ impl UIBase for UICloud {
             self.active_panel = 0
         } else if is_button(buttons, SceCtrlButtons::SceCtrlRight) {
             self.active_panel = self.right_panel
+        } else if is_button(buttons, SceCtrlButtons::SceCtrlSelect) {
+            UIAccounts::present_and_do(&self.pending);
         } else if is_button(buttons, SceCtrlButtons::SceCtrlSquare) {
             self.right_panel = if self.right_panel == 2 { 1 } else { 2 };
             if self.active_panel != 0 {
@@ -1073,7 +1192,16 @@ impl UIBase for UICloud {
 
             panel.draw(idx == self.active_panel, &self.no_data_tex);
 
-            if idx == 2 && self.qr_code_state.qr_code.is_some() {
+            if idx == 2 && !is_online() && panel.current_dir().is_none() {
+                let left = SCREEN_WIDTH / 2;
+                vita2d_draw_text(
+                    left + (SCREEN_WIDTH / 2 - vita2d_text_width(1.0, OFFLINE_TEXT)) / 2,
+                    SCREEN_HEIGHT / 2,
+                    rgba(0xff, 0xff, 0xff, 0xff),
+                    1.0,
+                    OFFLINE_TEXT,
+                );
+            } else if idx == 2 && self.qr_code_state.qr_code.is_some() {
                 let left = SCREEN_WIDTH / 2;
                 let x = (left + (SCREEN_WIDTH / 2 - SAVE_LIST_QR_CODE_SIZE) / 2) as f32;
                 let y = 130.0;
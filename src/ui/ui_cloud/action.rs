@@ -5,7 +5,9 @@ use std::{
 
 use log::error;
 
-use crate::{api::Api, ui::ui_toast::Toast, utils::join_path};
+use crate::{
+    cloud::fetch_dir_list, listing_cache::CachedItem, ui::ui_toast::Toast, utils::join_path,
+};
 
 use super::panel::{Dir, DirPending, DirPendingAction, Item};
 
@@ -81,17 +83,11 @@ pub fn do_cloud_action(
     dir: Arc<RwLock<Option<DirPending>>>,
 ) {
     let (abs_path, name) = get_path_and_name(path, item_name, &action);
-    let api_type = Api::get_read().api_type;
-    let url = Api::get_read().get_file_list_url(&abs_path, 0);
-    match Api::start_fetch_dir_list(&url, api_type) {
+    match fetch_dir_list(&abs_path) {
         Ok(list) => {
-            let mut dir_new = Dir::new(name, vec![]);
-            for item in list {
-                dir_new.add_item(item.isdir == 1, item.server_filename, Some(item.fs_id));
-            }
             *dir.write().expect("get dir write lock") = Some(DirPending {
                 action,
-                dir: dir_new,
+                dir: cloud_dir(name, list),
             });
         }
         Err(err) => {
@@ -101,6 +97,14 @@ pub fn do_cloud_action(
     }
 }
 
+pub fn cloud_dir(name: String, list: Vec<CachedItem>) -> Dir {
+    let mut dir_new = Dir::new(name, vec![]);
+    for item in list {
+        dir_new.add_item(item.is_dir, item.name, Some(item.fs_id));
+    }
+    dir_new
+}
+
 pub fn get_path_and_name(
     path: &str,
     item_name: &str,
@@ -3,38 +3,95 @@ use std::{
     time::{Duration, Instant},
 };
 
+use log::error;
+
 use crate::{
+    accounts::now,
     api::Api,
+    cloud::fetch_dir_list,
+    constant::OFFLINE_TEXT,
+    listing_cache::{cached_listing, normalize_cloud_path, CachedItem, Listing},
+    log::Operation,
+    network::is_online,
     ui::{
-        ui_cloud::panel::{Dir, DirPending, DirPendingAction},
+        ui_cloud::panel::{dirs_path, Dir, DirPending, DirPendingAction},
         ui_loading::Loading,
+        ui_toast::Toast,
     },
 };
 
 const INIT_RETRY_DURATION: Duration = Duration::from_millis(1000 * 10);
 
-use super::{do_cloud_action, Action};
+use super::{cloud_dir, do_cloud_action, get_path_and_name, Action};
+
+type Refreshed = Arc<RwLock<Option<(String, Vec<CachedItem>)>>>;
 
 pub struct CloudAction {
     last_init_at: Arc<RwLock<Instant>>,
+    // path and items of the listing changed since cached
+    refreshed: Refreshed,
 }
 
 impl CloudAction {
     pub fn new() -> CloudAction {
         CloudAction {
             last_init_at: Arc::new(RwLock::new(Instant::now() - INIT_RETRY_DURATION)),
+            refreshed: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// # fetch stale listing in background
+    ///
+    /// the shown listing is replaced only if its tag changes
+    fn refresh_in_background(&self, path: String, tag: String) {
+        let refreshed = Arc::clone(&self.refreshed);
+        tokio::spawn(async move {
+            match fetch_dir_list(&path) {
+                Ok(items) => {
+                    if Listing::new(items.clone(), 0).tag != tag {
+                        *refreshed.write().expect("write refreshed listing") = Some((path, items));
+                    }
+                }
+                Err(err) => error!("refresh {} list failed: {}", path, err),
+            }
+        });
+    }
+
+    fn apply_refreshed(&self, dirs: &mut [Dir]) {
+        let refreshed = match self.refreshed.try_write() {
+            Ok(mut refreshed) => refreshed.take(),
+            Err(_) => None,
+        };
+        let (path, items) = match refreshed {
+            Some(refreshed) => refreshed,
+            None => return,
+        };
+        // the user may have left the dir
+        if normalize_cloud_path(&dirs_path(dirs)) != normalize_cloud_path(&path) {
+            return;
+        }
+        if let Some(dir) = dirs.last_mut() {
+            dir.items = cloud_dir(dir.name.clone(), items).items;
         }
     }
 }
 
 impl Action for CloudAction {
     fn init(&mut self, dirs: &mut Vec<Dir>, dir: &Arc<RwLock<Option<DirPending>>>) {
+        self.apply_refreshed(dirs);
         if dirs.len() > 0 {
             return;
         }
         if !Api::get_read().is_login() {
             return;
         }
+        if !is_online() {
+            // browse the cached tree
+            if cached_listing("/").is_some() {
+                self.do_action("", "/", DirPendingAction::Enter, dir);
+            }
+            return;
+        }
         if Arc::strong_count(&self.last_init_at) > 1 {
             return;
         }
@@ -53,18 +110,34 @@ impl Action for CloudAction {
         action: DirPendingAction,
         dir: &Arc<RwLock<Option<DirPending>>>,
     ) {
+        // cached listing is shown at once
+        let (abs_path, name) = get_path_and_name(path, item_name, &action);
+        if let Some(listing) = cached_listing(&abs_path) {
+            if listing.is_stale(now()) && is_online() {
+                self.refresh_in_background(abs_path, listing.tag.clone());
+            }
+            *dir.write().expect("get dir write lock") = Some(DirPending {
+                action,
+                dir: cloud_dir(name, listing.items),
+            });
+            return;
+        }
+        if !is_online() {
+            Toast::show(OFFLINE_TEXT.to_string());
+            return;
+        }
         let dir = Arc::clone(dir);
         let path = path.to_string();
         let name = item_name.to_string();
         let last_init_at = Arc::clone(&self.last_init_at);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             do_cloud_action(&path, &name, action, dir);
             if let Ok(mut last_init_at) = last_init_at.write() {
                 *last_init_at = Instant::now();
             }
             Loading::hide();
-        });
+        }));
     }
 
     fn pop_dir(&self, dirs: &mut Vec<Dir>) {
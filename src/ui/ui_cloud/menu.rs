@@ -10,6 +10,7 @@ use crate::{
 use super::{list_state::ListState, panel::Item};
 
 pub enum MenuAction {
+    Refresh,
     NewDir,
     Copy,
     Move,
@@ -26,6 +27,7 @@ pub enum MenuAction {
 impl MenuAction {
     pub fn as_str(&self) -> &'static str {
         match self {
+            MenuAction::Refresh => "刷新",
             MenuAction::NewDir => "新建文件夹",
             MenuAction::Copy => "复制",
             MenuAction::Move => "移动",
@@ -73,6 +75,10 @@ impl Menu {
         self.actions.clear();
         let is_from_local = !from_path.starts_with("/");
         let is_to_local = !to_path.starts_with("/");
+        // cached listing of cloud
+        if !is_from_local {
+            self.actions.push(MenuAction::Refresh);
+        }
         // new dir
         self.actions.push(MenuAction::NewDir);
         if item.is_none() {
@@ -56,6 +56,21 @@ impl Dir {
     }
 }
 
+// end with /
+pub fn dirs_path(dirs: &[Dir]) -> String {
+    dirs.iter()
+        .map(|dir| {
+            // psv device end with :
+            if dir.name == "" || dir.name == "/" {
+                dir.name.clone()
+            } else {
+                format!("{}/", dir.name)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
 pub enum DirPendingAction {
     Enter,
     Refresh,
@@ -132,18 +147,7 @@ impl Panel {
 
     // end with /
     pub fn current_dir_path(&self) -> String {
-        self.dirs
-            .iter()
-            .map(|dir| {
-                // psv device end with :
-                if dir.name == "" || dir.name == "/" {
-                    dir.name.clone()
-                } else {
-                    format!("{}/", dir.name)
-                }
-            })
-            .collect::<Vec<String>>()
-            .join("")
+        dirs_path(&self.dirs)
     }
 
     pub fn is_forces(&self) -> bool {
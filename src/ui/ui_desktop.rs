@@ -6,9 +6,11 @@ use std::{
 use crate::{
     app::AppData,
     constant::{
-        DESKTOP_BOTTOM_BAR_CLOUD_TEXT, DESKTOP_BOTTOM_BAR_TEXT, SCREEN_HEIGHT, SCREEN_WIDTH,
-        TEXT_L, TEXT_R,
+        APP_VERSION, DESKTOP_BOTTOM_BAR_CLOUD_TEXT, DESKTOP_BOTTOM_BAR_TEXT, SCREEN_HEIGHT,
+        SCREEN_WIDTH, TEXT_L, TEXT_R,
     },
+    space::{device_space, DeviceSpace, STORAGE_DEVICES},
+    utils::format_size,
     vita2d::{
         is_button, rgba, vita2d_draw_rect, vita2d_draw_text, vita2d_draw_texture, vita2d_line,
         vita2d_load_jpg_buf, vita2d_load_png_buf, vita2d_text_height, vita2d_text_width,
@@ -25,13 +27,14 @@ const IMAGE_DEVICE_BG_PATH: &str = "app0:sce_sys/resources/device_bg.jpg";
 const ICON_SIZE: i32 = 70;
 const ICON_OFFSET: i32 = 10;
 const ICON_GAP: i32 = 20;
-const TEXT_VERSION: &str = "V2024.02.28";
+const DEVICE_SPACE_REFRESH_SECS: u64 = 10;
 
 pub struct UIDesktop {
     selected_idx: i32,
     pub children: [Box<dyn UIBase>; 2],
     pub assets: Vec<Vita2dTexture>,
     asset_bufs: Arc<RwLock<Option<Vec<Vec<u8>>>>>,
+    device_spaces: Arc<RwLock<Vec<(&'static str, DeviceSpace)>>>,
 }
 
 impl UIDesktop {
@@ -41,11 +44,29 @@ impl UIDesktop {
             children,
             assets: vec![],
             asset_bufs: Arc::new(RwLock::new(None)),
+            device_spaces: Arc::new(RwLock::new(vec![])),
         };
         res.init_assets();
+        res.init_device_spaces();
         res
     }
 
+    // refresh space of devices in background
+    fn init_device_spaces(&self) {
+        let device_spaces = Arc::clone(&self.device_spaces);
+        tokio::spawn(async move {
+            loop {
+                let spaces = STORAGE_DEVICES
+                    .into_iter()
+                    .filter_map(|device| device_space(device).map(|space| (device, space)))
+                    .collect();
+                *device_spaces.write().expect("get device_spaces write lock") = spaces;
+                tokio::time::sleep(tokio::time::Duration::from_secs(DEVICE_SPACE_REFRESH_SECS))
+                    .await;
+            }
+        });
+    }
+
     // async load images
     fn init_assets(&self) {
         let asset_bufs = Arc::clone(&self.asset_bufs);
@@ -102,11 +123,11 @@ impl UIDesktop {
     fn draw_top_line(&self) {
         // version
         vita2d_draw_text(
-            ICON_OFFSET + (70 - vita2d_text_width(0.61, TEXT_VERSION)) / 2,
-            80 + vita2d_text_height(0.61, TEXT_VERSION) / 2,
+            ICON_OFFSET + (70 - vita2d_text_width(0.61, APP_VERSION)) / 2,
+            80 + vita2d_text_height(0.61, APP_VERSION) / 2,
             rgba(0xff, 0xff, 0xff, 0xff),
             0.61,
-            TEXT_VERSION,
+            APP_VERSION,
         );
         vita2d_draw_text(
             ICON_OFFSET + ICON_SIZE + ICON_GAP - vita2d_text_width(0.61, TEXT_L),
@@ -156,6 +177,27 @@ impl UIDesktop {
         }
     }
 
+    // free space of devices at right top
+    fn draw_device_space(&self) {
+        if let Ok(lock) = self.device_spaces.try_read() {
+            for (idx, (device, space)) in lock.iter().enumerate() {
+                let text = format!(
+                    "{} 可用 {} / {}",
+                    device,
+                    format_size(space.free),
+                    format_size(space.total)
+                );
+                vita2d_draw_text(
+                    SCREEN_WIDTH - 12 - vita2d_text_width(0.61, &text),
+                    22 + 20 * idx as i32,
+                    rgba(0xff, 0xff, 0xff, 0xff),
+                    0.61,
+                    &text,
+                );
+            }
+        }
+    }
+
     fn draw_device_bg(&self) {
         if self.assets.len() > 0 {
             vita2d_draw_texture(&self.assets[0], 0.0, 0.0);
@@ -241,6 +283,8 @@ impl UIBase for UIDesktop {
         self.draw_device();
         // draw cloud
         self.draw_cloud();
+        // draw free space of devices
+        self.draw_device_space();
         // draw bottom bar
         self.draw_bottom_bar();
 
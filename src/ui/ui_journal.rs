@@ -0,0 +1,119 @@
+use crate::{
+    constant::JOURNAL_BOTTOM_BAR_TEXT,
+    journal::{last_undoable_restore, read_journal, JournalEntry},
+    utils::format_size,
+    vita2d::{is_button, rgba, SceCtrlButtons},
+};
+
+use super::{
+    ui_cloud::list_state::ListState,
+    ui_dialog::UIDialog,
+    ui_modal::{UIModal, DISPLAY_ROW},
+};
+
+fn load_entries(title_id: &str, all: bool) -> Vec<JournalEntry> {
+    read_journal(if all { None } else { Some(title_id) })
+}
+
+fn entry_color(entry: &JournalEntry) -> u32 {
+    if entry.ok {
+        rgba(0xff, 0xff, 0xff, 0xff)
+    } else {
+        rgba(0xff, 0x66, 0x66, 0xff)
+    }
+}
+
+fn entry_text(entry: &JournalEntry) -> String {
+    let mut text = format!(
+        "{}  {}{}  ",
+        entry.time,
+        entry.op.name(),
+        if entry.ok { "" } else { "失败" }
+    );
+    if !entry.title_id.is_empty() {
+        text.push_str(&format!("{}  ", entry.title_id));
+    }
+    if let Some(account) = &entry.account {
+        text.push_str(&format!("[{}] ", account));
+    }
+    text.push_str(&entry.source);
+    if !entry.destination.is_empty() && entry.destination != entry.source {
+        text.push_str(&format!(" -> {}", entry.destination));
+    }
+    if entry.size > 0 {
+        text.push_str(&format!("  ({})", format_size(entry.size)));
+    }
+    if let Some(md5) = &entry.md5 {
+        text.push_str(&format!("  md5:{}", &md5[..md5.len().min(8)]));
+    }
+    if !entry.error.is_empty() {
+        text.push_str(&format!("  {}", entry.error));
+    }
+    text
+}
+
+pub struct UIJournal;
+
+impl UIJournal {
+    fn draw(
+        modal: &UIModal,
+        entries: &[JournalEntry],
+        title_id: &str,
+        all: bool,
+        list_state: &ListState,
+    ) {
+        let title = format!(
+            "操作记录  |  筛选：{}  ({})",
+            if all { "全部" } else { title_id },
+            entries.len()
+        );
+        modal.draw_list(&title, list_state, entries.len(), |i| {
+            // the latest first
+            let entry = &entries[entries.len() - 1 - i];
+            (entry_text(entry), entry_color(entry))
+        });
+    }
+
+    /// # present operation journal
+    ///
+    /// entries of `title_id` or all entries, the latest first,
+    /// return the restore entry to undo if it is confirmed
+    pub fn present(title_id: &str) -> Option<JournalEntry> {
+        let mut all = false;
+        let mut entries = load_entries(title_id, all);
+        let mut list_state = ListState::new(DISPLAY_ROW);
+        let mut modal = UIModal::new(JOURNAL_BOTTOM_BAR_TEXT);
+        let mut undo = None;
+        loop {
+            let buttons = modal.buttons();
+            if is_button(buttons, SceCtrlButtons::SceCtrlCross) {
+                break;
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlSquare) {
+                all = !all;
+                entries = load_entries(title_id, all);
+                list_state = ListState::new(DISPLAY_ROW);
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlTriangle) {
+                let text = match last_undoable_restore(&entries) {
+                    Some(entry) => {
+                        undo = Some(entry.clone());
+                        format!(
+                            "撤销上次恢复：使用自动备份 {} 恢复 {}？",
+                            entry.auto_backup.as_deref().unwrap_or(""),
+                            entry.destination
+                        )
+                    }
+                    None => "没有可以撤销的恢复！".to_string(),
+                };
+                if UIDialog::present(&text) && undo.is_some() {
+                    break;
+                }
+                undo = None;
+            }
+            list_state.update(entries.len() as i32, buttons);
+
+            UIJournal::draw(&modal, &entries, title_id, all, &list_state);
+        }
+        modal.close(|modal| UIJournal::draw(modal, &entries, title_id, all, &list_state));
+        undo
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::{Duration, Instant};
+
+use log::{error, Level, LevelFilter};
+
+use crate::{
+    constant::{LOG_VIEWER_BOTTOM_BAR_TEXT, SAVE_LOG_PATH},
+    log::{read_lines, LogLine},
+    vita2d::{is_button, rgba, SceCtrlButtons},
+};
+
+use super::{
+    ui_cloud::list_state::ListState,
+    ui_modal::{UIModal, DISPLAY_ROW},
+};
+
+// the tail of log file to read
+const MAX_LOG_SIZE: u64 = 64 * 1024;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const LEVELS: [LevelFilter; 5] = [
+    LevelFilter::Trace,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+];
+
+fn level_name(level: LevelFilter) -> &'static str {
+    match level {
+        LevelFilter::Error => "ERROR",
+        LevelFilter::Warn => "WARN 及以上",
+        LevelFilter::Info => "INFO 及以上",
+        LevelFilter::Debug => "DEBUG 及以上",
+        _ => "全部",
+    }
+}
+
+fn level_color(level: Level) -> u32 {
+    match level {
+        Level::Error => rgba(0xff, 0x66, 0x66, 0xff),
+        Level::Warn => rgba(0xff, 0xcc, 0x66, 0xff),
+        Level::Info => rgba(0xff, 0xff, 0xff, 0xff),
+        _ => rgba(0x99, 0x99, 0x99, 0xff),
+    }
+}
+
+fn load_lines() -> Vec<LogLine> {
+    read_lines(SAVE_LOG_PATH, MAX_LOG_SIZE).unwrap_or_else(|err| {
+        error!("read log failed: {:?}", err);
+        vec![]
+    })
+}
+
+fn filter_lines(lines: &[LogLine], level: LevelFilter) -> Vec<&LogLine> {
+    lines.iter().filter(|line| line.level <= level).collect()
+}
+
+fn line_text(line: &LogLine) -> String {
+    format!(
+        "{}  {:<5}  {}{}{}",
+        line.time,
+        line.level,
+        match line.op {
+            Some(op) => format!("#{}  ", op),
+            None => "".to_string(),
+        },
+        if line.module.is_empty() {
+            "".to_string()
+        } else {
+            format!("{}: ", line.module)
+        },
+        line.msg.replace('\n', " "),
+    )
+}
+
+pub struct UILogViewer;
+
+impl UILogViewer {
+    fn draw(
+        modal: &UIModal,
+        lines: &[&LogLine],
+        level: LevelFilter,
+        follow: bool,
+        list_state: &ListState,
+    ) {
+        let title = format!(
+            "日志  |  级别：{}  |  跟随：{}  ({})",
+            level_name(level),
+            if follow { "开" } else { "关" },
+            lines.len()
+        );
+        modal.draw_list(&title, list_state, lines.len(), |i| {
+            (line_text(lines[i]), level_color(lines[i].level))
+        });
+    }
+
+    /// # present log viewer
+    ///
+    /// show the tail of log file, follow the new lines if follow is on
+    pub fn present() {
+        let mut lines = load_lines();
+        let mut loaded_at = Instant::now();
+        let mut level = LevelFilter::Trace;
+        let mut follow = true;
+        let mut list_state = ListState::new(DISPLAY_ROW);
+        let mut modal = UIModal::new(LOG_VIEWER_BOTTOM_BAR_TEXT);
+        loop {
+            let buttons = modal.buttons();
+            if is_button(buttons, SceCtrlButtons::SceCtrlCross) {
+                break;
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlSquare) {
+                let idx = LEVELS.iter().position(|&l| l == level).unwrap_or(0);
+                level = LEVELS[(idx + 1) % LEVELS.len()];
+                list_state = ListState::new(DISPLAY_ROW);
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlTriangle) {
+                follow = !follow;
+            } else if is_button(buttons, SceCtrlButtons::SceCtrlUp) {
+                follow = false;
+            }
+            if follow && loaded_at.elapsed() >= REFRESH_INTERVAL {
+                lines = load_lines();
+                loaded_at = Instant::now();
+            }
+            let filtered = filter_lines(&lines, level);
+            let size = filtered.len() as i32;
+            if follow && size > 0 {
+                // keep the last line selected
+                list_state.selected_idx = size - 1;
+                list_state.top_row = (size - DISPLAY_ROW).max(0);
+            } else {
+                list_state.update(size, buttons);
+            }
+
+            UILogViewer::draw(&modal, &filtered, level, follow, &list_state);
+        }
+        let filtered = filter_lines(&lines, level);
+        modal.close(|modal| UILogViewer::draw(modal, &filtered, level, follow, &list_state));
+    }
+}
@@ -0,0 +1,166 @@
+//! # modal screen
+//!
+//! full screen list or text lines with a title and a bottom bar,
+//! presented in its own loop above the current ui
+use crate::{
+    app::ButtonState,
+    constant::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    utils::get_active_color,
+    vita2d::{
+        rgba, vita2d_ctrl_peek_positive, vita2d_draw_rect, vita2d_draw_text, vita2d_drawing,
+        vita2d_line, vita2d_present, vita2d_set_clip, vita2d_text_height, vita2d_text_width,
+        vita2d_unset_clip,
+    },
+};
+
+use super::ui_cloud::list_state::ListState;
+
+/// rows of list
+pub const DISPLAY_ROW: i32 = 13;
+
+pub struct UIModal {
+    bottom_bar_text: &'static str,
+    button_state: ButtonState,
+    is_released: bool,
+}
+
+impl UIModal {
+    pub fn new(bottom_bar_text: &'static str) -> UIModal {
+        UIModal {
+            bottom_bar_text,
+            button_state: ButtonState::new(),
+            is_released: false,
+        }
+    }
+
+    /// # buttons of this frame
+    ///
+    /// ignore the buttons which open the modal until they are released
+    pub fn buttons(&mut self) -> u32 {
+        let buttons_origins = vita2d_ctrl_peek_positive();
+        if buttons_origins == 0 {
+            self.is_released = true;
+        }
+        let buttons = self.button_state.update(buttons_origins);
+        if self.is_released {
+            buttons
+        } else {
+            0
+        }
+    }
+
+    /// # draw list
+    ///
+    /// `row` returns text and color of the item at index
+    pub fn draw_list(
+        &self,
+        title: &str,
+        list_state: &ListState,
+        size: usize,
+        row: impl Fn(usize) -> (String, u32),
+    ) {
+        self.draw(title, || {
+            let ListState {
+                top_row,
+                selected_idx,
+                display_row,
+            } = *list_state;
+            vita2d_set_clip(12, 50, SCREEN_WIDTH - 12, SCREEN_HEIGHT - 58);
+            for idx in 0..display_row {
+                let i = top_row + idx;
+                if i >= size as i32 {
+                    break;
+                }
+                let x = 12;
+                let y = 78;
+                let h = 30 * idx;
+                if i == selected_idx {
+                    vita2d_draw_rect(
+                        x as f32,
+                        (y + h - 21) as f32,
+                        (SCREEN_WIDTH - 24) as f32,
+                        30.0,
+                        get_active_color(),
+                    );
+                    vita2d_draw_rect(
+                        (x + 2) as f32,
+                        (y + 2 + h - 21) as f32,
+                        (SCREEN_WIDTH - 28) as f32,
+                        26.0,
+                        rgba(0x18, 0x18, 0x18, 0xff),
+                    );
+                }
+                let (text, color) = row(i as usize);
+                vita2d_draw_text(x + 8, y + h, color, 1.0, &text);
+            }
+            vita2d_unset_clip();
+        });
+    }
+
+    /// draw text lines
+    pub fn draw_lines(&self, title: &str, lines: &[String]) {
+        self.draw(title, || {
+            for (idx, line) in lines.iter().enumerate() {
+                vita2d_draw_text(
+                    20,
+                    88 + 40 * idx as i32,
+                    rgba(0xff, 0xff, 0xff, 0xff),
+                    1.0,
+                    line,
+                );
+            }
+        });
+    }
+
+    /// # close modal
+    ///
+    /// wait buttons released, avoid passing them to the ui below
+    pub fn close(&self, draw: impl Fn(&UIModal)) {
+        while vita2d_ctrl_peek_positive() > 0 {
+            draw(self);
+        }
+    }
+
+    fn draw(&self, title: &str, content: impl FnOnce()) {
+        vita2d_drawing();
+        vita2d_draw_rect(
+            0.0,
+            0.0,
+            SCREEN_WIDTH as f32,
+            SCREEN_HEIGHT as f32,
+            rgba(0x18, 0x18, 0x18, 0xff),
+        );
+        // title
+        vita2d_draw_text(
+            12,
+            10 + vita2d_text_height(1.0, title),
+            rgba(0xff, 0xff, 0xff, 0xff),
+            1.0,
+            title,
+        );
+        vita2d_line(
+            12.0,
+            50.0,
+            (SCREEN_WIDTH - 12) as f32,
+            50.0,
+            rgba(0x99, 0x99, 0x99, 0xff),
+        );
+        content();
+        // bottom bar
+        vita2d_line(
+            12.0,
+            (SCREEN_HEIGHT - 58) as f32,
+            (SCREEN_WIDTH - 12) as f32,
+            (SCREEN_HEIGHT - 58) as f32,
+            rgba(0x99, 0x99, 0x99, 0xff),
+        );
+        vita2d_draw_text(
+            SCREEN_WIDTH - 12 - vita2d_text_width(1.0, self.bottom_bar_text),
+            SCREEN_HEIGHT - (58 / 2) + vita2d_text_height(1.0, self.bottom_bar_text) / 2,
+            rgba(0xff, 0xff, 0xff, 0xff),
+            1.0,
+            self.bottom_bar_text,
+        );
+        vita2d_present();
+    }
+}
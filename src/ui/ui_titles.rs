@@ -3,23 +3,28 @@ use std::{
     fs,
     path::Path,
     sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
 
 use log::error;
 
 use crate::{
     app::AppData,
-    constant::{GAME_CARD_SAVE_DIR, GAME_SAVE_DIR, HOME_PAGE_URL},
-    utils::get_active_color,
+    constant::HOME_PAGE_URL,
+    save_location::SaveLocation,
+    settings::Settings,
+    sfo::Sfo,
+    tai::{get_psv_account_id, unmount_pfs, TitleKind},
+    utils::{current_time, get_active_color, get_game_save_dir},
     vita2d::{
         is_button, rgba, vita2d_draw_rect, vita2d_draw_text, vita2d_draw_texture_scale,
-        vita2d_load_png_buf, vita2d_text_height, SceCtrlButtons, Vita2dTexture,
+        vita2d_load_png_buf, vita2d_text_height, vita2d_text_width, SceCtrlButtons, Vita2dTexture,
     },
 };
 
 use self::{game_menu::GameMenu, save_menu::SaveMenu};
 
-use super::{ui_base::UIBase, ui_dialog::UIDialog};
+use super::{ui_base::UIBase, ui_dialog::UIDialog, ui_loading::Loading};
 
 pub mod game_menu;
 pub mod save_menu;
@@ -29,6 +34,8 @@ const ICON_COL: i32 = 10;
 const ICON_ROW: i32 = 4;
 const OFFSET_TOP: i32 = 100;
 const OFFSET_LEFT: i32 = 10;
+// delay of loading save info, avoid mounting save data while scrolling
+const SAVE_INFO_DELAY: Duration = Duration::from_millis(300);
 
 pub struct UITitles {
     pub top_row: i32,
@@ -37,6 +44,9 @@ pub struct UITitles {
     pub icon_bufs: Arc<RwLock<HashMap<u32, Option<Vec<u8>>>>>,
     save_menu: SaveMenu,
     game_menu: GameMenu,
+    selected_at: Instant,
+    save_info_idx: i32,
+    save_info: Option<String>,
 }
 
 impl UITitles {
@@ -48,6 +58,9 @@ impl UITitles {
             icon_bufs: Arc::new(RwLock::new(HashMap::new())),
             save_menu: SaveMenu::new(),
             game_menu: GameMenu::new(),
+            selected_at: Instant::now(),
+            save_info_idx: -1,
+            save_info: None,
         }
     }
 
@@ -110,6 +123,79 @@ impl UITitles {
             }
             _ => {}
         };
+        if self.selected_idx != idx {
+            self.selected_at = Instant::now();
+        }
+    }
+
+    /// # load save info
+    ///
+    /// read `param.sfo` of selected title's save data
+    fn update_save_info(&mut self, app_data: &AppData) {
+        if self.save_info_idx == self.selected_idx
+            || self.selected_at.elapsed() < SAVE_INFO_DELAY
+            // save data may be mounted by other operation
+            || Loading::is_active()
+        {
+            return;
+        }
+        self.save_info_idx = self.selected_idx;
+        self.save_info = None;
+
+        let title = match app_data.titles.get_title_by_idx(self.selected_idx) {
+            Some(title) => title,
+            None => return,
+        };
+        let location = match get_game_save_dir(title) {
+            Some(location) => location,
+            None => return,
+        };
+        let (sfo_path, sfo) = match &location {
+            SaveLocation::Vita(save_dir) => {
+                let sfo_path = format!("{}/sce_sys/param.sfo", save_dir);
+                if !Path::new(&sfo_path).exists() {
+                    return;
+                }
+                location.mount();
+                let sfo = Sfo::open(&sfo_path);
+                unmount_pfs();
+                (sfo_path, sfo)
+            }
+            SaveLocation::Profile(_) => return,
+            // psp save is not encrypted by pfs, show the first save dir
+            SaveLocation::Psp { parent, .. } => {
+                let dir = match location.psp_dirs() {
+                    Ok(dirs) if !dirs.is_empty() => dirs[0].clone(),
+                    _ => return,
+                };
+                let sfo_path = format!("{}/{}/PARAM.SFO", parent, dir);
+                let sfo = Sfo::open(&sfo_path);
+                (sfo_path, sfo)
+            }
+        };
+        match sfo {
+            Ok(sfo) => {
+                let mut info = vec![];
+                if let Some(dir) = sfo.savedata_directory() {
+                    info.push(format!("存档目录：{}", dir));
+                }
+                if let Some(account_id) = sfo.account_id() {
+                    info.push(format!(
+                        "账号：{:016x}{}",
+                        account_id,
+                        if account_id == get_psv_account_id() {
+                            ""
+                        } else {
+                            "（非当前账号）"
+                        }
+                    ));
+                }
+                self.save_info = Some(info.join("  |  "));
+            }
+            Err(err) => {
+                error!("read sfo failed {}: {:?}", sfo_path, err);
+            }
+        }
     }
 
     fn update_icons(&mut self, app_data: &mut AppData) {
@@ -152,7 +238,7 @@ impl UITitles {
                                 .write()
                                 .expect("get write lock of icon bufs in spawn")
                                 .insert(idx as u32, Some(file));
-                        } else {
+                        } else if !iconpath.is_empty() {
                             error!("app iconpath not exists: {}", iconpath);
                         }
                     });
@@ -174,20 +260,42 @@ impl UITitles {
             .get_title_by_idx(self.selected_idx)
             .expect("get title id by idx");
         let title_id = title.title_id();
-        let real_id = title.real_id();
+        // not to scan psp save dirs and profile files on every frame
+        let save_path = match title.kind() {
+            TitleKind::Vita => get_game_save_dir(title).map(|location| location.to_string()),
+            TitleKind::Psp => Some(SaveLocation::psp(title.real_id()).to_string()),
+            TitleKind::Profile => Settings::read()
+                .profiles
+                .iter()
+                .find(|profile| profile.title_id() == title.real_id())
+                .map(|profile| profile.paths.join("  ")),
+        };
+        let state = title.state_text();
+        let info = title.info_text((current_time() / 1000) as u64);
         let title = format!(
-            "{}  |  {}",
+            "{}  |  {}{}",
             title_id,
             titles
                 .get_title_by_idx(self.selected_idx)
                 .expect("get title by idx")
                 .name(),
+            if state.is_empty() {
+                String::new()
+            } else {
+                format!("  [{}]", state)
+            }
         );
-        let mut save_path = format!("{}/{}", GAME_CARD_SAVE_DIR, real_id);
-        if !Path::new(&save_path).exists() {
-            save_path = format!("{}/{}", GAME_SAVE_DIR, real_id);
+        let save_path = save_path.unwrap_or("没有游戏存档".to_string());
+        let mut num = format!("→ {}/{}", self.selected_idx + 1, titles.size());
+        if !info.is_empty() {
+            num.push_str(&format!("  |  {}", info));
+        }
+        match &self.save_info {
+            Some(info) if self.save_info_idx == self.selected_idx && !info.is_empty() => {
+                num.push_str(&format!("  |  {}", info));
+            }
+            _ => {}
         }
-        let num = format!("→ {}/{}", self.selected_idx + 1, titles.size());
 
         let left = 330;
         // title
@@ -205,11 +313,7 @@ impl UITitles {
             35 + vita2d_text_height(1.0, &save_path),
             rgba(0xff, 0xff, 0xff, 0xff),
             1.0,
-            if Path::new(&save_path).exists() {
-                &save_path
-            } else {
-                "没有游戏存档"
-            },
+            &save_path,
         );
         // num
         vita2d_draw_text(
@@ -267,6 +371,26 @@ impl UITitles {
                     (ICON_SIZE - pad) as f32 / 128.0,
                 )
             }
+            // state of game without save or orphaned save
+            if let Some(title) = app_data.titles.get_title_by_idx(icon_idx as i32) {
+                let state = title.state_text();
+                if !state.is_empty() {
+                    vita2d_draw_rect(
+                        x as f32,
+                        (y + ICON_SIZE - pad - 22) as f32,
+                        (ICON_SIZE - pad) as f32,
+                        22.0,
+                        rgba(0x00, 0x00, 0x00, 0xaa),
+                    );
+                    vita2d_draw_text(
+                        x + (ICON_SIZE - pad - vita2d_text_width(0.61, state)) / 2,
+                        y + ICON_SIZE - pad - 5,
+                        rgba(0xff, 0xff, 0xff, 0xff),
+                        0.61,
+                        state,
+                    );
+                }
+            }
         }
     }
 
@@ -315,6 +439,10 @@ impl UIBase for UITitles {
             }
             // update selected title icon
             UITitles::update_selected(self, app_data, buttons);
+            // update selected title save info
+            if !self.save_menu.is_active() && !self.game_menu.is_active() {
+                UITitles::update_save_info(self, app_data);
+            }
         }
 
         // free save menu
@@ -339,3 +467,30 @@ impl UIBase for UITitles {
         self.save_menu.is_forces() || self.game_menu.is_forces()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{app::AppData, title_source::fixture_titles, vita2d::SceCtrlButtons};
+
+    use super::UITitles;
+
+    #[test]
+    fn test_update_selected() {
+        let mut app_data = AppData {
+            titles: fixture_titles(),
+        };
+        app_data.titles.wait();
+        let mut ui = UITitles::new();
+        for (button, idx) in [
+            (SceCtrlButtons::SceCtrlRight, 1),
+            (SceCtrlButtons::SceCtrlRight, 2),
+            (SceCtrlButtons::SceCtrlLeft, 1),
+        ] {
+            ui.update_selected(&mut app_data, button as u32);
+            assert_eq!(ui.selected_idx, idx);
+        }
+        let title = app_data.titles.get_title_by_idx(ui.selected_idx).unwrap();
+        assert_eq!(title.name(), "Game Without Save");
+        assert_eq!(title.state_text(), "无存档");
+    }
+}
@@ -9,21 +9,41 @@ use std::{
     },
 };
 
-use log::error;
+use log::{error, info};
 
 use crate::{
+    accounts::title_cloud_dir,
     api::Api,
+    cloud::{fetch_save_cloud_list, find_cloud_duplicate, upload_to_cloud},
     constant::{
-        GAME_CARD_SAVE_DIR, GAME_SAVE_CLOUD_DIR, GAME_SAVE_DIR, HOME_PAGE_URL, SCREEN_WIDTH,
+        DIAGNOSTICS_CLOUD_DIR, DIAGNOSTICS_DIR, HOME_PAGE_URL, OFFLINE_TEXT, RATE_LIMIT_PRESETS_KB,
+        SCREEN_WIDTH, TRANSFER_MAX_PARALLELISM,
     },
-    ime::get_current_format_time,
-    tai::{mount_pfs, psv_launch_app_by_title_id, unmount_pfs, Title, Titles},
+    diagnostics::{device_summary, export_diagnostics},
+    ime::{get_current_format_time, show_keyboard},
+    journal::{JournalEntry, JournalOp},
+    log::{self, Operation},
+    network::is_online,
+    profile::Profile,
+    save_location::SaveLocation,
+    schedule::wait_upload_window,
+    settings::{BackupRules, Compression, Settings},
+    tai::{mount_pfs, psv_launch_app_by_title_id, unmount_pfs, Title, TitleKind, Titles},
+    trash::{delete_from_trash, move_to_trash, restore_from_trash, TrashItem},
     ui::{
-        ui_cloud::list_state::ListState, ui_dialog::UIDialog, ui_loading::Loading, ui_toast::Toast,
+        ui_cloud::list_state::ListState,
+        ui_dialog::UIDialog,
+        ui_journal::UIJournal,
+        ui_loading::Loading,
+        ui_log::UILogViewer,
+        ui_titles::save_menu::save_list::input_encrypt_passphrase,
+        ui_toast::Toast,
+        ui_trash::{TrashAction, UITrash},
     },
     utils::{
-        backup_game_save, delete_dir_if_empty, get_active_color, get_game_local_backup_dir,
-        normalize_path, update_sfo_file_with_current_account_id,
+        backup_game_save_with, delete_dir_if_empty, format_size, get_active_color, get_file_md5,
+        get_game_local_backup_dir, get_game_save_dir, normalize_path, remove_game_save,
+        restore_game_save_with, update_sfo_file_with_current_account_id,
     },
     vita2d::{is_button, rgba, vita2d_draw_rect, vita2d_draw_text, SceCtrlButtons},
 };
@@ -32,10 +52,23 @@ enum GameMenuAction {
     BackupAllGameSave,
     BackupAllGameSaveToCloud,
     ChangeAccountId,
+    Compression,
+    GameCompression,
     DeleteGameSave,
     DeleteSelectedGameSave,
     DeleteAllGameSaves,
     LaunchApp,
+    History,
+    Trash,
+    ViewLog,
+    LogFormat,
+    ExportDiagnostics,
+    RateLimit,
+    DeferUploads,
+    Parallelism,
+    BackupRules,
+    GameBackupRules,
+    AddProfile,
 }
 
 impl Deref for GameMenuAction {
@@ -46,10 +79,23 @@ impl Deref for GameMenuAction {
             GameMenuAction::BackupAllGameSave => "备份所有游戏存档",
             GameMenuAction::BackupAllGameSaveToCloud => "备份所有游戏存档到云端",
             GameMenuAction::ChangeAccountId => "修改存档账号为当前账号",
+            GameMenuAction::Compression => "全局备份压缩方式",
+            GameMenuAction::GameCompression => "该游戏备份压缩方式",
             GameMenuAction::DeleteGameSave => "删除该游戏存档",
             GameMenuAction::DeleteSelectedGameSave => "删除该游戏本地存档备份",
             GameMenuAction::DeleteAllGameSaves => "删除所有游戏本地存档备份",
             GameMenuAction::LaunchApp => "启动游戏",
+            GameMenuAction::History => "操作记录",
+            GameMenuAction::Trash => "回收站",
+            GameMenuAction::ViewLog => "查看日志",
+            GameMenuAction::LogFormat => "日志格式",
+            GameMenuAction::ExportDiagnostics => "导出诊断信息",
+            GameMenuAction::RateLimit => "传输限速",
+            GameMenuAction::DeferUploads => "批量上传等待充电或空闲",
+            GameMenuAction::Parallelism => "并行传输连接数",
+            GameMenuAction::BackupRules => "全局备份规则",
+            GameMenuAction::GameBackupRules => "该游戏备份规则",
+            GameMenuAction::AddProfile => "新建备份方案",
         }
     }
 }
@@ -63,9 +109,10 @@ impl Display for GameMenuAction {
 pub struct GameList {
     pending: Arc<AtomicBool>,
     list_state: ListState,
-    list: [GameMenuAction; 7],
-    game_save_dir_prepare_to_mount: Arc<RwLock<Option<String>>>,
-    game_save_dir_on_mounted: Arc<RwLock<Option<String>>>,
+    list: [GameMenuAction; 20],
+    title_id: String,
+    game_save_dir_prepare_to_mount: Arc<RwLock<Option<SaveLocation>>>,
+    game_save_dir_on_mounted: Arc<RwLock<Option<SaveLocation>>>,
 }
 
 impl GameList {
@@ -78,10 +125,24 @@ impl GameList {
                 GameMenuAction::BackupAllGameSave,
                 GameMenuAction::BackupAllGameSaveToCloud,
                 GameMenuAction::ChangeAccountId,
+                GameMenuAction::Compression,
+                GameMenuAction::GameCompression,
+                GameMenuAction::BackupRules,
+                GameMenuAction::GameBackupRules,
+                GameMenuAction::RateLimit,
+                GameMenuAction::DeferUploads,
+                GameMenuAction::Parallelism,
+                GameMenuAction::AddProfile,
                 GameMenuAction::DeleteGameSave,
                 GameMenuAction::DeleteSelectedGameSave,
                 GameMenuAction::DeleteAllGameSaves,
+                GameMenuAction::History,
+                GameMenuAction::Trash,
+                GameMenuAction::ViewLog,
+                GameMenuAction::LogFormat,
+                GameMenuAction::ExportDiagnostics,
             ],
+            title_id: String::new(),
             game_save_dir_prepare_to_mount: Arc::new(RwLock::new(None)),
             game_save_dir_on_mounted: Arc::new(RwLock::new(None)),
         }
@@ -92,19 +153,19 @@ impl GameList {
     }
 
     pub fn delete_game_save(&self, title: &Title) {
-        let real_id = title.real_id().to_string();
+        let game_save_dir = get_game_save_dir(title);
+        let title_id = title.title_id().to_string();
         let name = title.name().to_string();
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
         unmount_pfs();
-        tokio::spawn(async move {
-            let dirs = [
-                format!("{}/{}", GAME_CARD_SAVE_DIR, real_id),
-                format!("{}/{}", GAME_SAVE_DIR, real_id),
-            ];
-            if let Some(game_save_dir) = dirs.iter().find(|dir| Path::new(&dir).exists()) {
-                if let Err(err) = fs::remove_dir_all(&game_save_dir) {
+        tokio::spawn(Operation::begin().run(async move {
+            if let Some(game_save_dir) = game_save_dir {
+                let res = remove_game_save(&game_save_dir);
+                JournalEntry::new(JournalOp::Delete, &title_id, &game_save_dir.to_string(), "")
+                    .record(&res);
+                if let Err(err) = res {
                     error!("remove {} failed: {}", game_save_dir, err);
                     Toast::show(format!("删除 {} 存档失败！", name));
                 } else {
@@ -115,7 +176,7 @@ impl GameList {
             }
             Loading::hide();
             pending.store(false, Ordering::Relaxed);
-        });
+        }));
     }
 
     pub fn delete_selected_game_save(&self, title: &Title) {
@@ -124,10 +185,12 @@ impl GameList {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             let local_dir = get_game_local_backup_dir(&title_id, &name);
             if Path::new(&local_dir).exists() {
-                if let Err(err) = fs::remove_dir_all(&local_dir) {
+                let res = move_to_trash(&local_dir);
+                JournalEntry::new(JournalOp::Delete, &title_id, &local_dir, "").record(&res);
+                if let Err(err) = res {
                     error!("remove {} failed: {}", local_dir, err);
                     Toast::show(format!("删除 {} 本地备份失败！", name));
                 } else {
@@ -138,7 +201,7 @@ impl GameList {
             }
             Loading::hide();
             pending.store(false, Ordering::Relaxed);
-        });
+        }));
     }
 
     pub fn delete_all_game_saves(&self, titles: &Titles) {
@@ -150,12 +213,14 @@ impl GameList {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             let mut delete_failed_count = 0;
             for (_idx, (title_id, name)) in list.iter().enumerate() {
                 let local_dir = get_game_local_backup_dir(&title_id, &name);
                 if Path::new(&local_dir).exists() {
-                    if let Err(err) = fs::remove_dir_all(&local_dir) {
+                    let res = move_to_trash(&local_dir);
+                    JournalEntry::new(JournalOp::Delete, title_id, &local_dir, "").record(&res);
+                    if let Err(err) = res {
                         error!("remove {} failed: {}", local_dir, err);
                         Toast::show(format!("删除 {} 本地备份失败！", name));
                         delete_failed_count += 1;
@@ -172,44 +237,157 @@ impl GameList {
             }
             Loading::hide();
             pending.store(false, Ordering::Relaxed);
-        });
+        }));
+    }
+
+    /// # undo restore
+    ///
+    /// restore the auto backup made before the restore of `entry`
+    pub fn undo_restore(&self, entry: JournalEntry) {
+        let auto_backup = match entry.auto_backup {
+            Some(auto_backup) => auto_backup,
+            None => return,
+        };
+        let game_save_dir = SaveLocation::parse(&entry.destination);
+        let title_id = entry.title_id;
+        let filter = Settings::backup_filter_of(&title_id);
+        let pending = Arc::clone(&self.pending);
+        pending.store(true, Ordering::Relaxed);
+        Loading::show();
+        game_save_dir.mount();
+        tokio::spawn(Operation::begin().run(async move {
+            // the undo can be undone too
+            let (undo_backup, res) = restore_game_save_with(
+                &auto_backup,
+                &game_save_dir,
+                Settings::compression_of(&title_id),
+                &filter,
+                None,
+                false,
+            );
+            JournalEntry::new(
+                JournalOp::Restore,
+                &title_id,
+                &auto_backup,
+                &game_save_dir.to_string(),
+            )
+            .size_of(&auto_backup)
+            .auto_backup(undo_backup)
+            .record(&res);
+            match res {
+                Ok(_) => Toast::show("撤销恢复完成！".to_string()),
+                Err(err) => {
+                    error!(
+                        "undo restore {} to {} failed: {:?}",
+                        auto_backup, game_save_dir, err
+                    );
+                    Toast::show(format!("撤销恢复失败：{}", err));
+                }
+            }
+            Loading::hide();
+            pending.store(false, Ordering::Relaxed);
+        }));
+    }
+
+    /// restore or delete item of trash
+    pub fn do_trash_action(&self, action: TrashAction, item: TrashItem) {
+        let pending = Arc::clone(&self.pending);
+        pending.store(true, Ordering::Relaxed);
+        Loading::show();
+        tokio::spawn(Operation::begin().run(async move {
+            Loading::notify_desc(item.name().to_string());
+            match action {
+                TrashAction::Restore => {
+                    Loading::notify_title("正在从回收站恢复".to_string());
+                    match restore_from_trash(&item) {
+                        Ok(_) => Toast::show(format!("已恢复到 {}", item.path)),
+                        Err(err) => {
+                            error!("restore {:?} from trash failed: {:?}", item, err);
+                            Toast::show(format!("恢复失败：{}", err));
+                        }
+                    }
+                }
+                TrashAction::Delete => {
+                    Loading::notify_title("正在永久删除".to_string());
+                    match delete_from_trash(&item) {
+                        Ok(_) => Toast::show("永久删除完成！".to_string()),
+                        Err(err) => {
+                            error!("delete {:?} from trash failed: {:?}", item, err);
+                            Toast::show(format!("删除失败：{}", err));
+                        }
+                    }
+                }
+            }
+            Loading::hide();
+            pending.store(false, Ordering::Relaxed);
+        }));
+    }
+
+    /// export diagnostics to local, and upload to cloud if `upload`
+    pub fn export_diagnostics(&self, titles: &Titles, upload: bool) {
+        let summary = device_summary(titles);
+        let name = format!("diagnostics {}.zip", get_current_format_time());
+        let path = format!("{}/{}", DIAGNOSTICS_DIR, name);
+        let pending = Arc::clone(&self.pending);
+        pending.store(true, Ordering::Relaxed);
+        Loading::show();
+        tokio::spawn(Operation::begin().run(async move {
+            Loading::notify_title("正在导出诊断信息".to_string());
+            match export_diagnostics(&path, &summary) {
+                Ok(_) if upload => {
+                    Loading::notify_title("正在上传诊断信息".to_string());
+                    match upload_to_cloud(DIAGNOSTICS_CLOUD_DIR, &name, &path, false) {
+                        Ok(_) => Toast::show(format!(
+                            "诊断信息已上传到 {}/{}",
+                            DIAGNOSTICS_CLOUD_DIR, name
+                        )),
+                        Err(err) => {
+                            error!("upload {} to cloud failed: {:?}", path, err);
+                            Toast::show(format!("上传失败，诊断信息已保存到 {}", path));
+                        }
+                    }
+                }
+                Ok(_) => Toast::show(format!("诊断信息已保存到 {}", path)),
+                Err(err) => {
+                    error!("export diagnostics to {} failed: {:?}", path, err);
+                    Toast::show("导出诊断信息失败！".to_string());
+                }
+            }
+            Loading::hide();
+            pending.store(false, Ordering::Relaxed);
+        }));
     }
 
     pub fn backup_all_game_save(&self, titles: &Titles) {
+        let passphrase = match input_encrypt_passphrase() {
+            Some(passphrase) => passphrase,
+            None => return,
+        };
         let list = titles
             .iter()
-            .map(|title| {
-                (
+            .filter_map(|title| {
+                Some((
                     title.title_id().to_string(),
-                    title.real_id().to_string(),
+                    get_game_save_dir(title)?,
                     title.name().to_string(),
-                )
+                ))
             })
-            .collect::<Vec<(String, String, String)>>();
+            .collect::<Vec<(String, SaveLocation, String)>>();
 
         let game_save_dir_on_mounted = Arc::clone(&self.game_save_dir_on_mounted);
         let game_save_dir_prepare_to_mount = Arc::clone(&self.game_save_dir_prepare_to_mount);
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             let mut backup_failed_count = 0;
-            for (idx, (title_id, real_id, name)) in list.iter().enumerate() {
+            for (idx, (title_id, game_save_dir, name)) in list.iter().enumerate() {
                 Loading::notify_title(format!(
                     "正在备份 ({}/{})： {}！",
                     idx + 1,
                     list.len(),
                     name
                 ));
-                let dirs = [
-                    format!("{}/{}", GAME_CARD_SAVE_DIR, real_id),
-                    format!("{}/{}", GAME_SAVE_DIR, real_id),
-                ];
-                let game_save_dir = dirs.iter().find(|dir| Path::new(&dir).exists());
-                if game_save_dir.is_none() {
-                    continue;
-                }
-                let game_save_dir = game_save_dir.unwrap();
                 let mut is_prepare = false;
                 loop {
                     if let Ok(game_save_dir_on_mounted) = game_save_dir_on_mounted.try_read() {
@@ -234,7 +412,22 @@ impl GameList {
                     get_game_local_backup_dir(&title_id, &name),
                     get_current_format_time()
                 );
-                match backup_game_save(game_save_dir, &backup_to_path) {
+                let res = backup_game_save_with(
+                    game_save_dir,
+                    &backup_to_path,
+                    Settings::compression_of(title_id),
+                    &Settings::backup_filter_of(title_id),
+                    passphrase.as_deref(),
+                );
+                JournalEntry::new(
+                    JournalOp::Backup,
+                    title_id,
+                    &game_save_dir.to_string(),
+                    &backup_to_path,
+                )
+                .size_of(&backup_to_path)
+                .record(&res);
+                match res {
                     Err(err) => {
                         backup_failed_count += 1;
                         error!(
@@ -256,44 +449,48 @@ impl GameList {
             }
             Loading::hide();
             pending.store(false, Ordering::Relaxed);
-        });
+        }));
     }
 
     pub fn backup_all_game_save_to_cloud(&self, titles: &Titles) {
+        if !is_online() {
+            Toast::show(OFFLINE_TEXT.to_string());
+            return;
+        }
+        let passphrase = match input_encrypt_passphrase() {
+            Some(passphrase) => passphrase,
+            None => return,
+        };
         let list = titles
             .iter()
-            .map(|title| {
-                (
+            .filter_map(|title| {
+                Some((
                     title.title_id().to_string(),
-                    title.real_id().to_string(),
+                    get_game_save_dir(title)?,
                     title.name().to_string(),
-                )
+                ))
             })
-            .collect::<Vec<(String, String, String)>>();
+            .collect::<Vec<(String, SaveLocation, String)>>();
 
         let game_save_dir_on_mounted = Arc::clone(&self.game_save_dir_on_mounted);
         let game_save_dir_prepare_to_mount = Arc::clone(&self.game_save_dir_prepare_to_mount);
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             let mut backup_failed_count = 0;
-            for (idx, (title_id, real_id, name)) in list.iter().enumerate() {
+            // backups which are in cloud already
+            let mut skipped_count = 0;
+            let mut skipped_size = 0;
+            // titles not uploaded after the wait of upload window is canceled
+            let mut canceled_count = 0;
+            for (idx, (title_id, game_save_dir, name)) in list.iter().enumerate() {
                 Loading::notify_title(format!(
                     "正在备份 ({}/{})： {}！",
                     idx + 1,
                     list.len(),
                     name
                 ));
-                let dirs = [
-                    format!("{}/{}", GAME_CARD_SAVE_DIR, real_id),
-                    format!("{}/{}", GAME_SAVE_DIR, real_id),
-                ];
-                let game_save_dir = dirs.iter().find(|dir| Path::new(&dir).exists());
-                if game_save_dir.is_none() {
-                    continue;
-                }
-                let game_save_dir = game_save_dir.unwrap();
                 let mut is_prepare = false;
                 loop {
                     if let Ok(game_save_dir_on_mounted) = game_save_dir_on_mounted.try_read() {
@@ -316,34 +513,85 @@ impl GameList {
                 let backup_name = format!("{}.zip", get_current_format_time());
                 let local_dir = get_game_local_backup_dir(&title_id, &name);
                 let backup_to_path = format!("{}/{}", local_dir, backup_name);
-                let success = match backup_game_save(game_save_dir, &backup_to_path) {
+                let res = backup_game_save_with(
+                    game_save_dir,
+                    &backup_to_path,
+                    Settings::compression_of(title_id),
+                    &Settings::backup_filter_of(title_id),
+                    passphrase.as_deref(),
+                );
+                let success = match res {
                     Err(err) => {
                         backup_failed_count += 1;
                         error!(
                             "zip {} to {} failed: {:?}",
                             game_save_dir, backup_to_path, err
                         );
+                        JournalEntry::new(
+                            JournalOp::Backup,
+                            title_id,
+                            &game_save_dir.to_string(),
+                            &backup_to_path,
+                        )
+                        .record::<(), _>(&Err(err));
                         Toast::show(format!("游戏 {} 备份失败！", name));
                         false
                     }
                     _ => true,
                 };
 
-                if success {
-                    let (cloud_dir, _) = Api::fetch_save_cloud_list(&title_id, true);
-                    let cloud_dir = if cloud_dir.is_some() {
-                        cloud_dir.unwrap()
-                    } else {
-                        format!(
-                            "{}/{} {}",
-                            GAME_SAVE_CLOUD_DIR,
-                            title_id,
-                            normalize_path(name.trim())
-                        )
-                        .trim()
-                        .to_string()
+                let duplicate = if success {
+                    let (cloud_dir, saves) = fetch_save_cloud_list(title_id, true);
+                    let cloud_dir = cloud_dir
+                        .unwrap_or_else(|| title_cloud_dir(title_id, &normalize_path(name.trim())));
+                    // encrypted backups differ by the random salt
+                    let md5 = match &passphrase {
+                        Some(_) => Ok(None),
+                        None => get_file_md5(&backup_to_path).map(Some),
+                    };
+                    let duplicate = match md5 {
+                        Ok(None) => None,
+                        Ok(Some(md5)) => find_cloud_duplicate(
+                            &cloud_dir,
+                            saves.as_deref().unwrap_or_default(),
+                            &md5,
+                        ),
+                        Err(err) => {
+                            error!("md5 of {} failed: {:?}", backup_to_path, err);
+                            None
+                        }
                     };
-                    match Api::upload_to_cloud(&cloud_dir, &backup_name, &backup_to_path, false) {
+                    if let Some(duplicate) = &duplicate {
+                        info!(
+                            "{} is the same as {}/{}, skip upload",
+                            backup_to_path, cloud_dir, duplicate
+                        );
+                        skipped_count += 1;
+                        skipped_size += fs::metadata(&backup_to_path).map_or(0, |meta| meta.len());
+                    }
+                    Some((cloud_dir, duplicate))
+                } else {
+                    None
+                };
+
+                if let Some((cloud_dir, None)) = duplicate {
+                    if !wait_upload_window(|| {
+                        Loading::notify_desc("等待充电或空闲后上传，按 × 取消...".to_string());
+                    }) {
+                        // keep the local backup, the remaining titles are not backed up
+                        canceled_count = list.len() - idx;
+                        break;
+                    }
+                    let res = upload_to_cloud(&cloud_dir, &backup_name, &backup_to_path, false);
+                    JournalEntry::new(
+                        JournalOp::Backup,
+                        title_id,
+                        &game_save_dir.to_string(),
+                        &format!("{}/{}", cloud_dir, backup_name),
+                    )
+                    .size_of(&backup_to_path)
+                    .record(&res);
+                    match res {
                         Err(err) => {
                             error!("upload {} to cloud failed: {:?}", backup_to_path, err);
                             Toast::show(format!("游戏 {} 备份上传失败！", title_id));
@@ -363,17 +611,31 @@ impl GameList {
                     let _ = delete_dir_if_empty(&local_dir);
                 }
             }
-            if backup_failed_count == 0 {
-                Toast::show("所有游戏备份完成！".to_string());
+            let skipped_text = if skipped_count > 0 {
+                format!(
+                    "，{} 个存档未变化，节省上传 {}",
+                    skipped_count,
+                    format_size(skipped_size)
+                )
             } else {
+                String::new()
+            };
+            if canceled_count > 0 {
                 Toast::show(format!(
-                    "部分游戏备份完成，{} 个备份失败！",
-                    backup_failed_count
+                    "已停止等待上传{}，{} 个游戏未上传，备份保留在本地！",
+                    skipped_text, canceled_count
+                ));
+            } else if backup_failed_count == 0 {
+                Toast::show(format!("所有游戏备份完成{}！", skipped_text));
+            } else {
+                Toast::show(format!(
+                    "部分游戏备份完成{}，{} 个备份失败！",
+                    skipped_text, backup_failed_count
                 ));
             }
             Loading::hide();
             pending.store(false, Ordering::Relaxed);
-        });
+        }));
     }
 
     pub fn mount_game_dir_if_exists(&self) {
@@ -390,13 +652,82 @@ impl GameList {
 
         // mount
         if let Some(prepare_dir) = prepare_dir {
-            mount_pfs(&prepare_dir);
+            prepare_dir.mount();
             *self.game_save_dir_on_mounted.write().unwrap() = Some(prepare_dir);
         }
     }
 
+    /// text of action with current settings
+    fn action_text(&self, action: &GameMenuAction) -> String {
+        match action {
+            GameMenuAction::Compression => {
+                format!("{}：{}", action, Settings::read().compression.name())
+            }
+            GameMenuAction::GameCompression => {
+                let settings = Settings::read();
+                match settings.game_compressions.get(&self.title_id) {
+                    Some(compression) => format!("{}：{}", action, compression.name()),
+                    None => format!("{}：跟随全局（{}）", action, settings.compression.name()),
+                }
+            }
+            GameMenuAction::LogFormat => format!(
+                "{}：{}",
+                action,
+                if Settings::read().json_log {
+                    "JSON"
+                } else {
+                    "文本"
+                }
+            ),
+            GameMenuAction::RateLimit => {
+                let settings = Settings::read();
+                let text = |limit: Option<u64>| {
+                    limit.map_or("不限".to_string(), |limit| {
+                        format!("{}/s", format_size(limit))
+                    })
+                };
+                if settings.upload_limit() == settings.download_limit() {
+                    format!("{}：{}", action, text(settings.upload_limit()))
+                } else {
+                    format!(
+                        "{}：上传 {} 下载 {}",
+                        action,
+                        text(settings.upload_limit()),
+                        text(settings.download_limit())
+                    )
+                }
+            }
+            GameMenuAction::DeferUploads => format!(
+                "{}：{}",
+                action,
+                if Settings::read().defer_uploads {
+                    "开"
+                } else {
+                    "关"
+                }
+            ),
+            GameMenuAction::Parallelism => {
+                format!("{}：{}", action, Settings::read().transfer_parallelism())
+            }
+            GameMenuAction::BackupRules => {
+                format!("{}：{}", action, rules_text(&Settings::read().backup_rules))
+            }
+            GameMenuAction::GameBackupRules => {
+                let settings = Settings::read();
+                match settings.game_backup_rules.get(&self.title_id) {
+                    Some(rules) => format!("{}：{}", action, rules_text(rules)),
+                    None => format!("{}：跟随全局", action),
+                }
+            }
+            _ => action.to_string(),
+        }
+    }
+
     pub fn update(&mut self, buttons: u32, title: &Title, titles: &Titles) {
         self.mount_game_dir_if_exists();
+        if self.title_id != title.title_id() {
+            self.title_id = title.title_id().to_string();
+        }
 
         if self.is_pending() {
             return;
@@ -406,7 +737,11 @@ impl GameList {
             let action = &self.list[selected_idx as usize];
             match action {
                 GameMenuAction::LaunchApp => {
-                    if UIDialog::present(&format!(
+                    if title.kind() == TitleKind::Profile {
+                        Toast::show("备份方案无法启动！".to_string());
+                    } else if !title.installed() {
+                        Toast::show("游戏未安装！".to_string());
+                    } else if UIDialog::present(&format!(
                         "{}: {}",
                         &GameMenuAction::LaunchApp,
                         title.name()
@@ -433,28 +768,143 @@ impl GameList {
                     }
                 }
                 GameMenuAction::ChangeAccountId => {
-                    if UIDialog::present(&GameMenuAction::ChangeAccountId) {
-                        [
-                            format!("{}/{}", GAME_CARD_SAVE_DIR, title.real_id()),
-                            format!("{}/{}", GAME_SAVE_DIR, title.real_id()),
-                        ]
-                        .iter()
-                        .any(|path| {
+                    // psp save and profile are not bound to account
+                    if title.kind() == TitleKind::Psp {
+                        Toast::show("PSP 存档无需修改账号！".to_string());
+                    } else if title.kind() == TitleKind::Profile {
+                        Toast::show("备份方案无需修改账号！".to_string());
+                    } else if UIDialog::present(&GameMenuAction::ChangeAccountId) {
+                        if let Some(SaveLocation::Vita(path)) = get_game_save_dir(title) {
                             let sfo_path = format!("{}/sce_sys/param.sfo", path);
                             if Path::new(&sfo_path).exists() {
-                                mount_pfs(path);
-                                if let Ok(()) = update_sfo_file_with_current_account_id(&sfo_path) {
+                                mount_pfs(&path);
+                                let res = update_sfo_file_with_current_account_id(&sfo_path);
+                                JournalEntry::new(
+                                    JournalOp::ChangeAccountId,
+                                    title.title_id(),
+                                    &sfo_path,
+                                    &sfo_path,
+                                )
+                                .record(&res);
+                                if let Ok(()) = res {
                                     Toast::show("修改存档为当前账号完成！".to_string());
                                 } else {
                                     Toast::show("修改存档为当前账号失败！".to_string());
                                 }
                                 unmount_pfs();
-                                return true;
                             }
-                            false
+                        }
+                    }
+                }
+                GameMenuAction::History => {
+                    if let Some(entry) = UIJournal::present(title.title_id()) {
+                        self.undo_restore(entry);
+                    }
+                }
+                GameMenuAction::BackupRules => {
+                    let rules = Settings::read().backup_rules.clone();
+                    if let Some(rules) = input_backup_rules(&rules) {
+                        Settings::update(|settings| settings.backup_rules = rules);
+                    }
+                }
+                GameMenuAction::GameBackupRules => {
+                    let title_id = title.title_id().to_string();
+                    let rules = {
+                        let settings = Settings::read();
+                        settings
+                            .game_backup_rules
+                            .get(&title_id)
+                            .unwrap_or(&settings.backup_rules)
+                            .clone()
+                    };
+                    if let Some(rules) = input_backup_rules(&rules) {
+                        // empty rules follow global rules
+                        Settings::update(|settings| {
+                            if rules.is_empty() {
+                                settings.game_backup_rules.remove(&title_id);
+                            } else {
+                                settings.game_backup_rules.insert(title_id, rules);
+                            }
                         });
                     }
                 }
+                GameMenuAction::AddProfile => add_profile(),
+                GameMenuAction::Trash => {
+                    if let Some((action, item)) = UITrash::present() {
+                        self.do_trash_action(action, item);
+                    }
+                }
+                GameMenuAction::ViewLog => {
+                    UILogViewer::present();
+                }
+                GameMenuAction::LogFormat => {
+                    Settings::update(|settings| {
+                        settings.json_log = !settings.json_log;
+                        log::set_json(settings.json_log);
+                    });
+                }
+                GameMenuAction::ExportDiagnostics => {
+                    if UIDialog::present(&GameMenuAction::ExportDiagnostics) {
+                        let upload = Api::get_read().is_login()
+                            && UIDialog::present("同时上传诊断信息到云端？");
+                        self.export_diagnostics(titles, upload);
+                    }
+                }
+                GameMenuAction::Compression => {
+                    Settings::update(|settings| {
+                        settings.compression = settings.compression.next();
+                    });
+                }
+                GameMenuAction::RateLimit => {
+                    Settings::update(|settings| {
+                        // both limits switch to the next preset of upload limit
+                        let limit = settings.upload_limit_kb.unwrap_or(0);
+                        let limit = RATE_LIMIT_PRESETS_KB
+                            .iter()
+                            .find(|preset| **preset > limit)
+                            .copied()
+                            .unwrap_or(0);
+                        settings.upload_limit_kb = Some(limit);
+                        settings.download_limit_kb = Some(limit);
+                    });
+                }
+                GameMenuAction::DeferUploads => {
+                    Settings::update(|settings| {
+                        settings.defer_uploads = !settings.defer_uploads;
+                    });
+                }
+                GameMenuAction::Parallelism => {
+                    Settings::update(|settings| {
+                        // 1 -> ... -> max -> 1
+                        let parallelism =
+                            settings.transfer_parallelism() % TRANSFER_MAX_PARALLELISM;
+                        settings.transfer_parallelism = Some(parallelism + 1);
+                    });
+                }
+                GameMenuAction::GameCompression => {
+                    let title_id = title.title_id().to_string();
+                    Settings::update(|settings| {
+                        // follow global -> Store -> ... -> DeflateBest -> follow global
+                        match settings.game_compressions.get(&title_id) {
+                            Some(&Compression::DeflateBest) => {
+                                settings.game_compressions.remove(&title_id);
+                            }
+                            Some(compression) => {
+                                let compression = compression.next();
+                                settings.game_compressions.insert(title_id, compression);
+                            }
+                            None => {
+                                settings
+                                    .game_compressions
+                                    .insert(title_id, Compression::Store);
+                            }
+                        }
+                    });
+                }
+                // data of profile may be shared with others
+                GameMenuAction::DeleteGameSave if title.kind() == TitleKind::Profile => {
+                    Toast::show("备份方案不支持删除数据！".to_string());
+                }
                 GameMenuAction::DeleteGameSave => {
                     let mut count = 3;
                     loop {
@@ -555,8 +1005,88 @@ impl GameList {
                 y + 30 * idx,
                 rgba(0xff, 0xff, 0xff, 0xff),
                 1.0,
-                &actions[i as usize],
+                &self.action_text(&actions[i as usize]),
             );
         }
     }
 }
+
+/// split input of keyboard by `;`
+fn split_input(input: &str) -> Vec<String> {
+    input
+        .split(';')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// summary of backup rules, such as `排除 2 项 包含 1 项`
+fn rules_text(rules: &BackupRules) -> String {
+    match (rules.excludes.len(), rules.includes.len()) {
+        (0, 0) => "无".to_string(),
+        (excludes, 0) => format!("排除 {} 项", excludes),
+        (0, includes) => format!("包含 {} 项", includes),
+        (excludes, includes) => format!("排除 {} 项 包含 {} 项", excludes, includes),
+    }
+}
+
+/// # input backup rules by keyboard
+///
+/// globs are separated by `;`, return `None` if canceled
+fn input_backup_rules(rules: &BackupRules) -> Option<BackupRules> {
+    if !UIDialog::present("输入排除规则，如 cache/**;**/*.tmp") {
+        return None;
+    }
+    let excludes = split_input(&show_keyboard(&rules.excludes.join(";")));
+    if !UIDialog::present("输入包含规则，为空则备份所有文件") {
+        return None;
+    }
+    let includes = split_input(&show_keyboard(&rules.includes.join(";")));
+    Some(BackupRules { excludes, includes })
+}
+
+/// # add backup profile by keyboard
+///
+/// input name, paths and excludes in turn, multiple paths are separated by `;`,
+/// the profile is listed after restart
+fn add_profile() {
+    let name = show_keyboard("").trim().to_string();
+    if name.is_empty() {
+        return;
+    }
+    if !UIDialog::present("输入备份路径，多个路径用 ; 分隔") {
+        return;
+    }
+    let paths = split_input(&show_keyboard("ux0:data/"));
+    let excludes = if UIDialog::present("是否输入排除规则，如 **/*.tmp？") {
+        split_input(&show_keyboard(""))
+    } else {
+        vec![]
+    };
+    let profile = Profile {
+        name,
+        paths,
+        excludes,
+        ..Profile::default()
+    };
+    if !profile.is_valid() {
+        Toast::show("备份路径需要以设备开头，如 ux0:data/！".to_string());
+        return;
+    }
+    let mut is_added = false;
+    Settings::update(|settings| {
+        if settings
+            .profiles
+            .iter()
+            .all(|item| item.title_id() != profile.title_id())
+        {
+            settings.profiles.push(profile.clone());
+            is_added = true;
+        }
+    });
+    Toast::show(if is_added {
+        format!("已添加备份方案 {}，重启后显示！", profile.name)
+    } else {
+        format!("备份方案 {} 已存在！", profile.name)
+    });
+}
@@ -1,13 +1,12 @@
-use std::path::Path;
-
 use crate::{
     constant::{
-        GAME_CARD_SAVE_DIR, GAME_SAVE_DIR, NEW_BACKUP, NEW_CLOUD_BACKUP,
-        SAVE_DRAWER_BOTTOM_BAR_TEXT, SAVE_DRAWER_CLOUD_BOTTOM_BAR_TEXT, SCREEN_WIDTH, TAB_CLOUD,
-        TAB_LOCAL, TEXT_L, TEXT_R,
+        NEW_BACKUP, NEW_CLOUD_BACKUP, SAVE_DRAWER_BOTTOM_BAR_TEXT,
+        SAVE_DRAWER_CLOUD_BOTTOM_BAR_TEXT, SCREEN_WIDTH, TAB_CLOUD, TAB_LOCAL, TEXT_L, TEXT_R,
     },
+    save_location::SaveLocation,
     tai::Title,
     ui::{ui_drawer::UIDrawer, ui_list::UIList},
+    utils::get_game_save_dir,
     vita2d::{
         is_button, rgba, vita2d_draw_rect, vita2d_draw_text, vita2d_line, vita2d_text_height,
         vita2d_text_width, SceCtrlButtons,
@@ -23,7 +22,7 @@ pub struct SaveMenu {
     local: Option<Box<dyn UIList>>,
     cloud: Option<Box<dyn UIList>>,
     drawer: Option<UIDrawer>,
-    game_save_dir: Option<String>,
+    game_save_dir: Option<SaveLocation>,
 }
 
 impl SaveMenu {
@@ -38,17 +37,7 @@ impl SaveMenu {
     }
 
     pub fn init_list(&mut self, title: &Title) {
-        self.game_save_dir = None;
-        for game_save_dir in [
-            format!("{}/{}", GAME_CARD_SAVE_DIR, title.real_id()),
-            format!("{}/{}", GAME_SAVE_DIR, title.real_id()),
-        ] {
-            let path = Path::new(&game_save_dir);
-            if path.exists() {
-                self.game_save_dir = Some(game_save_dir);
-                break;
-            }
-        }
+        self.game_save_dir = get_game_save_dir(title);
         // init save list
         self.local = Some(Box::new(SaveListLocal::new(NEW_BACKUP, title)));
         self.cloud = Some(Box::new(SaveListCloud::new(NEW_CLOUD_BACKUP, title)));
@@ -1,4 +1,186 @@
+use std::{fs, path::Path};
+
+use log::{error, info};
+
+use crate::{
+    constant::DECRYPTED_BACKUP_SUFFIX,
+    crypto::{decrypt_file, is_encrypted_file},
+    ime::show_keyboard,
+    save_location::SaveLocation,
+    tai::get_psv_account_id,
+    ui::{ui_check_list::UICheckList, ui_dialog::UIDialog, ui_toast::Toast},
+    utils::{get_backup_account_id, get_zip_file_entries},
+};
+
 pub mod save_list_cloud;
 pub mod save_list_local;
 
 pub const DISPLAY_ROW: i32 = 14;
+
+/// # select file entries of backup to restore
+///
+/// return `None` if canceled, `Some(None)` if all entries are selected
+pub fn select_restore_entries(backup_name: &str, backup_path: &str) -> Option<Option<Vec<String>>> {
+    let entries = match get_zip_file_entries(backup_path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            error!("read zip entries of {} failed: {:?}", backup_path, err);
+            Toast::show(format!("读取备份失败：{}", err));
+            return None;
+        }
+    };
+    let checked = match UICheckList::present(
+        &format!("使用备份还原游戏：{}", backup_name),
+        &entries,
+        true,
+    ) {
+        Some(checked) => checked,
+        None => {
+            Toast::show("还原取消！".to_string());
+            return None;
+        }
+    };
+    let selected = entries
+        .iter()
+        .zip(checked.iter())
+        .filter(|(_, &c)| c)
+        .map(|(entry, _)| entry.to_string())
+        .collect::<Vec<String>>();
+    if selected.is_empty() {
+        Toast::show("没有选择文件，还原取消！".to_string());
+        None
+    } else if selected.len() == entries.len() {
+        Some(None)
+    } else {
+        Some(Some(selected))
+    }
+}
+
+/// # confirm account id of backup to restore
+///
+/// return true if the account id of backup should be rewritten with current account id
+pub fn confirm_rewrite_account_id(backup_name: &str, backup_path: &str) -> bool {
+    let backup_account_id = match get_backup_account_id(backup_path) {
+        Ok(Some(account_id)) => account_id,
+        Ok(None) => return true,
+        Err(err) => {
+            error!("read account id of {} failed: {:?}", backup_path, err);
+            return true;
+        }
+    };
+    let account_id = get_psv_account_id();
+    if account_id == 0 || account_id == backup_account_id {
+        return true;
+    }
+    let is_rewrite = UIDialog::present(&format!(
+        "备份存档来自其他账号\n备份账号：{:016x}\n当前账号：{:016x}\n修改为当前账号？取消则保留原账号",
+        backup_account_id, account_id
+    ));
+    info!(
+        "restore {} from account {:016x} on account {:016x}: {}",
+        backup_name,
+        backup_account_id,
+        account_id,
+        if is_rewrite { "rewrite" } else { "keep" }
+    );
+    is_rewrite
+}
+
+/// # input passphrase to encrypt backup
+///
+/// return `None` if canceled, `Some(None)` if the backup is not encrypted
+pub fn input_encrypt_passphrase() -> Option<Option<String>> {
+    if !UIDialog::present("是否使用密码加密备份？") {
+        return Some(None);
+    }
+    loop {
+        let passphrase = show_keyboard("").to_string();
+        if passphrase.is_empty() {
+            Toast::show("密码为空，备份取消！".to_string());
+            return None;
+        }
+        // input again to confirm
+        if *show_keyboard("") == passphrase {
+            return Some(Some(passphrase));
+        }
+        if !UIDialog::present("两次输入的密码不一致，是否重新输入？") {
+            return None;
+        }
+    }
+}
+
+/// # prepare backup to restore
+///
+/// decrypt the backup if it is encrypted, return the path of backup to restore,
+/// `None` if canceled
+pub fn prepare_restore_backup(backup_path: &str) -> Option<String> {
+    match is_encrypted_file(backup_path) {
+        Ok(false) => return Some(backup_path.to_string()),
+        Ok(true) => {}
+        Err(err) => {
+            error!("read {} failed: {:?}", backup_path, err);
+            Toast::show(format!("读取备份失败：{}", err));
+            return None;
+        }
+    }
+    let passphrase = show_keyboard("");
+    if passphrase.is_empty() {
+        Toast::show("还原取消！".to_string());
+        return None;
+    }
+    let decrypted_path = format!("{}{}", backup_path, DECRYPTED_BACKUP_SUFFIX);
+    match decrypt_file(backup_path, &decrypted_path, &passphrase) {
+        Ok(_) => Some(decrypted_path),
+        Err(err) => {
+            error!("decrypt {} failed: {:?}", backup_path, err);
+            remove_decrypted_backup(&decrypted_path);
+            Toast::show("密码错误或备份已损坏！".to_string());
+            None
+        }
+    }
+}
+
+/// # game save dir to restore to
+///
+/// the existing dir, or `new_game_save_dir` if the game has not created save yet
+pub fn restore_dir(
+    game_save_dir: &Option<SaveLocation>,
+    new_game_save_dir: &Option<SaveLocation>,
+) -> Option<SaveLocation> {
+    game_save_dir.clone().or_else(|| new_game_save_dir.clone())
+}
+
+/// remove the temporary file created by `prepare_restore_backup`
+pub fn remove_decrypted_backup(path: &str) {
+    if path.ends_with(DECRYPTED_BACKUP_SUFFIX) && Path::new(path).exists() {
+        if let Err(err) = fs::remove_file(path) {
+            error!("remove decrypted backup {} failed: {:?}", path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        save_location::SaveLocation, title_source::fixture_titles, utils::get_new_game_save_dir,
+    };
+
+    use super::restore_dir;
+
+    #[test]
+    fn test_restore_dir() {
+        let titles = fixture_titles();
+        // the game has not created save yet
+        let title = &titles.wait()[1];
+        let new_game_save_dir = get_new_game_save_dir(title);
+        assert_eq!(
+            new_game_save_dir,
+            Some(SaveLocation::Vita(
+                "ux0:user/00/savedata/PCSG00002".to_string()
+            ))
+        );
+        assert_eq!(restore_dir(&None, &new_game_save_dir), new_game_save_dir);
+        let psp = Some(SaveLocation::psp("ULJM05800"));
+        assert_eq!(restore_dir(&psp, &new_game_save_dir), psp);
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     fs,
     path::Path,
     sync::{
@@ -8,17 +9,30 @@ use std::{
 };
 
 use log::{error, info};
-use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
 use crate::{
+    accounts::{active_account, save_login_account, title_cloud_dir},
     api::{Api, AuthData},
+    cloud::{
+        cached_save_cloud_list, fetch_cloud_file_size, fetch_save_cloud_list, start_download,
+        upload_to_cloud,
+    },
     constant::{
-        GAME_SAVE_CLOUD_DIR, HOME_PAGE_URL, LIST_NAME_WIDTH, SAVE_LIST_QR_CODE_SIZE,
-        SCAN_QR_CODE_TIPS, SCREEN_WIDTH,
+        HOME_PAGE_URL, LIST_NAME_WIDTH, OFFLINE_TEXT, SAVE_LIST_QR_CODE_SIZE, SCAN_QR_CODE_TIPS,
+        SCREEN_WIDTH,
     },
     ime::{get_current_format_time, show_keyboard},
-    tai::{mount_pfs, Title},
+    journal::{JournalEntry, JournalOp},
+    listing_cache::invalidate_listing,
+    log::Operation,
+    network::is_online,
+    save_location::SaveLocation,
+    settings::Settings,
+    space::check_space,
+    tai::Title,
+    trash::move_to_cloud_trash,
     ui::{
+        ui_accounts::UIAccounts,
         ui_cloud::list_state::ListState,
         ui_dialog::UIDialog,
         ui_list::UIList,
@@ -27,8 +41,8 @@ use crate::{
         ui_toast::Toast,
     },
     utils::{
-        backup_game_save, delete_dir_if_empty, get_game_local_backup_dir, normalize_path,
-        restore_game_save,
+        backup_game_save_with, delete_dir_if_empty, get_game_local_backup_dir,
+        get_new_game_save_dir, normalize_path, restore_game_save_with,
     },
     vita2d::{
         is_button, rgba, vita2d_draw_rect, vita2d_draw_text, vita2d_draw_texture,
@@ -38,7 +52,10 @@ use crate::{
 };
 use crate::{constant::SCREEN_HEIGHT, utils::get_active_color};
 
-use super::DISPLAY_ROW;
+use super::{
+    confirm_rewrite_account_id, input_encrypt_passphrase, prepare_restore_backup,
+    remove_decrypted_backup, restore_dir, select_restore_entries, DISPLAY_ROW,
+};
 
 pub struct SaveItem {
     pub name: String,
@@ -59,46 +76,90 @@ impl QrCodeState {
     }
 }
 
+type SaveItems = Arc<RwLock<Option<Vec<SaveItem>>>>;
+// cloud dir and items of title
+type Listing = (Arc<RwLock<String>>, SaveItems);
+
 pub struct SaveListCloud {
     pending: Arc<AtomicBool>,
     list_state: ListState,
     local_dir: String,
     cloud_dir: Arc<RwLock<String>>,
     title_id: String,
-    items: Arc<RwLock<Option<Vec<SaveItem>>>>,
+    // name in cloud dir of title
+    title_name: String,
+    items: SaveItems,
+    // (name, root dir) of active account
+    account: Option<(String, String)>,
+    // cloud dir and items of other accounts
+    listings: HashMap<(String, String), Listing>,
+    // network state at last update, fetch again when it is back
+    online: bool,
     qr_code_state: QrCodeState,
     new_backup_text: &'static str,
     scroll_progress: ScrollProgress,
+    // created by restore if the game has not created save yet
+    new_game_save_dir: Option<SaveLocation>,
+    // (backup name, downloaded backup path, game save dir)
+    restore_pending: Arc<RwLock<Option<(String, String, SaveLocation)>>>,
 }
 
 impl SaveListCloud {
     pub fn new(new_back: &'static str, title: &Title) -> SaveListCloud {
+        let title_name = normalize_path(title.name().trim());
         SaveListCloud {
             list_state: ListState::new(DISPLAY_ROW),
             pending: Arc::new(AtomicBool::new(false)),
             local_dir: get_game_local_backup_dir(&title.title_id(), &title.name()),
-            cloud_dir: Arc::new(RwLock::new(
-                format!(
-                    "{}/{} {}",
-                    GAME_SAVE_CLOUD_DIR,
-                    title.title_id(),
-                    normalize_path(title.name().trim())
-                )
-                .trim()
-                .to_string(),
-            )),
+            cloud_dir: Arc::new(RwLock::new(title_cloud_dir(title.title_id(), &title_name))),
             title_id: title.title_id().to_string(),
+            title_name,
+            new_game_save_dir: get_new_game_save_dir(title),
             items: Arc::new(RwLock::new(None)),
+            account: active_account().map(|account| (account.name, account.root_dir)),
+            listings: HashMap::new(),
+            online: is_online(),
             qr_code_state: QrCodeState::new(),
             new_backup_text: new_back,
             scroll_progress: ScrollProgress::new(40.0, 100.0),
+            restore_pending: Arc::new(RwLock::new(None)),
         }
     }
 
+    /// local backup dir of title
     fn local_dir(&self) -> String {
         self.local_dir.to_string()
     }
 
+    /// # swap listing when active account changes
+    ///
+    /// listings of accounts are kept, fetch the list if it is new
+    fn sync_account(&mut self) {
+        let account = active_account().map(|account| (account.name, account.root_dir));
+        if account == self.account {
+            return;
+        }
+        let (cloud_dir, items) = account
+            .as_ref()
+            .and_then(|account| self.listings.remove(account))
+            .unwrap_or_else(|| {
+                (
+                    Arc::new(RwLock::new(title_cloud_dir(
+                        &self.title_id,
+                        &self.title_name,
+                    ))),
+                    Arc::new(RwLock::new(None)),
+                )
+            });
+        let cloud_dir = std::mem::replace(&mut self.cloud_dir, cloud_dir);
+        let items = std::mem::replace(&mut self.items, items);
+        if let Some(old) = std::mem::replace(&mut self.account, account) {
+            self.listings.insert(old, (cloud_dir, items));
+        }
+        self.list_state = ListState::new(DISPLAY_ROW);
+        self.init();
+    }
+
     fn cloud_dir(&self) -> String {
         self.cloud_dir
             .read()
@@ -233,22 +294,49 @@ impl SaveListCloud {
         Arc::strong_count(&self.items) > 1
     }
 
+    /// # fetch backups
+    ///
+    /// cached backups are shown at once, they are fetched again in
+    /// background if stale, a failed fetch keeps the cached ones
     fn fetch_save_list(&mut self) {
         if self.is_fetch_cloud_save_list() {
             return;
         }
+        let cached = cached_save_cloud_list(&self.title_id);
+        let is_stale = match &cached {
+            Some((_, _, is_stale)) => *is_stale,
+            None => true,
+        };
+        if let Some((cloud_dir, saves, _)) = cached {
+            if !self.is_list_ready() {
+                *self.cloud_dir.write().expect("write save dir") = cloud_dir;
+                *self.items.write().expect("write game saves") = Some(saves);
+            }
+        }
+        if !is_stale || !is_online() {
+            return;
+        }
         let dir = Arc::clone(&self.cloud_dir);
         let items = Arc::clone(&self.items);
         let title_id = self.title_id.to_string();
         tokio::spawn(async move {
-            let (game_save_dir, res) = Api::fetch_save_cloud_list(&title_id, false);
+            let (game_save_dir, res) = fetch_save_cloud_list(&title_id, false);
             if let Some(game_save_dir) = game_save_dir {
                 *dir.write().expect("write save dir") = game_save_dir;
             }
-            *items.write().expect("write game saves") = res;
+            if res.is_some() {
+                *items.write().expect("write game saves") = res;
+            }
         });
     }
 
+    /// fetch backups even if the cache is fresh
+    fn refresh_save_list(&mut self) {
+        invalidate_listing(&self.cloud_dir());
+        self.fetch_save_list();
+        Toast::show("正在刷新云端备份".to_string());
+    }
+
     fn start_auth(&mut self) {
         let qr_code_buf = Arc::clone(&self.qr_code_state.qr_code_buf);
         let dir = Arc::clone(&self.cloud_dir);
@@ -285,14 +373,14 @@ impl SaveListCloud {
                             token_res.access_token.as_ref().unwrap(),
                         ) {
                             Ok(name_of_pancake) => {
+                                save_login_account(&token_res, &name_of_pancake);
                                 // 更新登录状态
                                 Api::update_auth_data(
                                     api_type,
                                     Some(AuthData::new(token_res, name_of_pancake)),
                                 );
                                 // 获取云端存档列表
-                                let (game_save_dir, res) =
-                                    Api::fetch_save_cloud_list(&title_id, false);
+                                let (game_save_dir, res) = fetch_save_cloud_list(&title_id, false);
                                 if let Some(game_save_dir) = game_save_dir {
                                     *dir.write().expect("write save dir") = game_save_dir;
                                 }
@@ -322,13 +410,13 @@ impl SaveListCloud {
         });
     }
 
-    fn download_cloud_backup(&self, game_save_dir: &Option<String>, restore: bool) {
+    fn download_cloud_backup(&self, game_save_dir: &Option<SaveLocation>, restore: bool) {
         let idx = self.list_state.selected_idx - 1;
         if idx < 0 {
             return;
         }
         if let Some(game_save_dir) = game_save_dir {
-            let game_save_dir = game_save_dir.to_string();
+            let game_save_dir = game_save_dir.clone();
             let (backup_name, fs_id) = self.get_item_by_idx(idx as usize).unwrap();
             let download_backup_name = if !restore {
                 backup_name.to_string()
@@ -343,65 +431,146 @@ impl SaveListCloud {
                 } else {
                     format!("下载云备份：{}？", backup_name)
                 }) {
+                    let cloud_path = format!("{}/{}", self.cloud_dir(), backup_name);
+                    let title_id = self.title_id.to_string();
+                    let restore_pending = Arc::clone(&self.restore_pending);
                     let pending = Arc::clone(&self.pending);
                     pending.store(true, Ordering::Relaxed);
                     Loading::show();
-                    if restore {
-                        mount_pfs(&game_save_dir);
-                    }
-                    tokio::spawn(async move {
+                    tokio::spawn(Operation::begin().run(async move {
                         Loading::notify_title("正在下载云备份".to_string());
-                        Loading::notify_desc(backup_name);
-                        let is_success = match Api::start_download(fs_id, &download_to_path) {
-                            Ok(_) => true,
+                        Loading::notify_desc(backup_name.clone());
+                        // the backup to restore is extracted to a staging copy
+                        // beside the downloaded one
+                        let copies = if restore { 2 } else { 1 };
+                        if let Err(err) = fetch_cloud_file_size(fs_id).and_then(|size| {
+                            check_space(&download_to_path, size.unwrap_or(0) * copies)
+                        }) {
+                            error!("check space of {} failed: {:?}", download_to_path, err);
+                            Toast::show(err.to_string());
+                            Loading::hide();
+                            pending.store(false, Ordering::Relaxed);
+                            return;
+                        }
+                        let res = start_download(fs_id, &download_to_path);
+                        JournalEntry::new(
+                            JournalOp::Download,
+                            &title_id,
+                            &cloud_path,
+                            &download_to_path,
+                        )
+                        .size_of(&download_to_path)
+                        .record(&res);
+                        match res {
+                            Ok(_) => {
+                                if restore {
+                                    // select entries to restore in ui thread
+                                    *restore_pending.write().expect("write restore pending") =
+                                        Some((backup_name, download_to_path, game_save_dir));
+                                } else {
+                                    Toast::show("云备份下载完成！".to_string());
+                                }
+                            }
                             Err(err) => {
                                 error!(
                                     "download {} from cloud failed: {:?}",
                                     download_to_path, err
                                 );
+                                // do not leave the partial backup
+                                let _ = fs::remove_file(&download_to_path);
                                 Toast::show(format!("云备份下载失败"));
-                                false
-                            }
-                        };
-                        if is_success {
-                            if restore {
-                                Loading::notify_title("正在恢复存档".to_string());
-                                match restore_game_save(&download_to_path, &game_save_dir) {
-                                    Ok(_) => {
-                                        Toast::show("存档恢复完成！".to_string());
-                                    }
-                                    Err(err) => {
-                                        error!(
-                                            "extract zip {} to {} failed: {:?}",
-                                            download_to_path, game_save_dir, err
-                                        );
-                                        Toast::show(format!("存档恢复失败：{}", err));
-                                    }
-                                }
-                                // remove local backup after restore
-                                if Path::new(&download_to_path).exists() {
-                                    if let Err(err) = fs::remove_file(&download_to_path) {
-                                        error!(
-                                            "remove {} failed after backup restore: {:?}",
-                                            download_to_path, err
-                                        );
-                                    }
-                                    let _ = delete_dir_if_empty(&local_dir);
-                                }
-                            } else {
-                                // update save list
-                                Toast::show("云备份下载完成！".to_string());
                             }
                         }
                         Loading::hide();
                         pending.store(false, Ordering::Relaxed);
-                    });
+                    }));
                 }
             } else {
                 Toast::show("本地已存在同名备份！".to_string());
             }
         }
     }
+
+    fn restore_downloaded_backup(&self) {
+        let restore_pending = match self.restore_pending.try_write() {
+            Ok(mut restore_pending) => restore_pending.take(),
+            _ => None,
+        };
+        let (backup_name, download_to_path, game_save_dir) = match restore_pending {
+            Some(restore_pending) => restore_pending,
+            None => return,
+        };
+        let local_dir = self.local_dir();
+        let restore_path = match prepare_restore_backup(&download_to_path) {
+            Some(restore_path) => restore_path,
+            None => {
+                remove_downloaded_backup(&download_to_path, &local_dir);
+                return;
+            }
+        };
+        let entries = select_restore_entries(&backup_name, &restore_path);
+        if let Some(entries) = entries {
+            let rewrite_account_id = confirm_rewrite_account_id(&backup_name, &restore_path);
+            let cloud_path = format!("{}/{}", self.cloud_dir(), backup_name);
+            let title_id = self.title_id.to_string();
+            let filter = Settings::backup_filter_of(&self.title_id);
+            let pending = Arc::clone(&self.pending);
+            pending.store(true, Ordering::Relaxed);
+            Loading::show();
+            game_save_dir.mount();
+            tokio::spawn(Operation::begin().run(async move {
+                let (auto_backup, res) = restore_game_save_with(
+                    &restore_path,
+                    &game_save_dir,
+                    Settings::compression_of(&title_id),
+                    &filter,
+                    entries.as_deref(),
+                    rewrite_account_id,
+                );
+                JournalEntry::new(
+                    JournalOp::Restore,
+                    &title_id,
+                    &cloud_path,
+                    &game_save_dir.to_string(),
+                )
+                .size_of(&restore_path)
+                .auto_backup(auto_backup)
+                .record(&res);
+                match res {
+                    Ok(_) => {
+                        Toast::show("存档恢复完成！".to_string());
+                    }
+                    Err(err) => {
+                        error!(
+                            "extract zip {} to {} failed: {:?}",
+                            restore_path, game_save_dir, err
+                        );
+                        Toast::show(format!("存档恢复失败：{}", err));
+                    }
+                }
+                // remove local backup after restore
+                remove_decrypted_backup(&restore_path);
+                remove_downloaded_backup(&download_to_path, &local_dir);
+                Loading::hide();
+                pending.store(false, Ordering::Relaxed);
+            }));
+        } else {
+            remove_decrypted_backup(&restore_path);
+            remove_downloaded_backup(&download_to_path, &local_dir);
+        }
+    }
+}
+
+fn remove_downloaded_backup(download_to_path: &str, local_dir: &str) {
+    if Path::new(download_to_path).exists() {
+        if let Err(err) = fs::remove_file(download_to_path) {
+            error!(
+                "remove {} failed after backup restore: {:?}",
+                download_to_path, err
+            );
+        }
+        let _ = delete_dir_if_empty(local_dir);
+    }
 }
 
 impl UIList for SaveListCloud {
@@ -409,7 +578,8 @@ impl UIList for SaveListCloud {
         if Arc::strong_count(&self.qr_code_state.qr_code_buf) > 1 {
             return;
         }
-        if Api::get_read().is_login() {
+        // browse cached backups while offline
+        if Api::get_read().is_login() || !is_online() {
             self.fetch_save_list();
         } else {
             self.start_auth();
@@ -420,10 +590,14 @@ impl UIList for SaveListCloud {
         self.pending.load(Ordering::Relaxed)
     }
 
-    fn do_backup_game_save(&self, game_save_dir: &Option<String>, input_overwrite: Option<String>) {
+    fn do_backup_game_save(
+        &self,
+        game_save_dir: &Option<SaveLocation>,
+        input_overwrite: Option<String>,
+    ) {
         match &game_save_dir {
             Some(game_save_dir) => {
-                let game_save_dir = game_save_dir.to_string();
+                let game_save_dir = game_save_dir.clone();
                 let local_dir = self.local_dir();
                 let input = match &input_overwrite {
                     Some(input) => format!("{}", input),
@@ -436,31 +610,48 @@ impl UIList for SaveListCloud {
                         }
                     }
                 };
-                if input.len() > 0 {
+                let passphrase = if input.len() > 0 {
+                    input_encrypt_passphrase()
+                } else {
+                    None
+                };
+                if let Some(passphrase) = passphrase {
                     let backup_name = format!("{}/{}", local_dir, input);
                     let is_overwrite = input_overwrite.is_some();
                     let cloud_dir = self.cloud_dir();
                     let dir = Arc::clone(&self.cloud_dir);
                     let items = Arc::clone(&self.items);
                     let title_id = self.title_id.to_string();
+                    let compression = Settings::compression_of(&self.title_id);
+                    let filter = Settings::backup_filter_of(&self.title_id);
                     let pending = Arc::clone(&self.pending);
                     pending.store(true, Ordering::Relaxed);
                     Loading::show();
-                    mount_pfs(&game_save_dir);
-                    tokio::spawn(async move {
+                    game_save_dir.mount();
+                    tokio::spawn(Operation::begin().run(async move {
                         Loading::notify_title("正在云备份".to_string());
-                        match backup_game_save(&game_save_dir, &backup_name) {
+                        let entry = JournalEntry::new(
+                            JournalOp::Backup,
+                            &title_id,
+                            &game_save_dir.to_string(),
+                            &format!("{}/{}", cloud_dir, input),
+                        );
+                        match backup_game_save_with(
+                            &game_save_dir,
+                            &backup_name,
+                            compression,
+                            &filter,
+                            passphrase.as_deref(),
+                        ) {
                             Ok(_) => {
-                                match Api::upload_to_cloud(
-                                    &cloud_dir,
-                                    &input,
-                                    &backup_name,
-                                    is_overwrite,
-                                ) {
+                                let res =
+                                    upload_to_cloud(&cloud_dir, &input, &backup_name, is_overwrite);
+                                entry.size_of(&backup_name).record(&res);
+                                match res {
                                     Ok(_) => {
                                         // 获取云端存档列表
                                         let (game_save_dir, res) =
-                                            Api::fetch_save_cloud_list(&title_id, false);
+                                            fetch_save_cloud_list(&title_id, false);
                                         if let Some(game_save_dir) = game_save_dir {
                                             *dir.write().expect("write save dir") = game_save_dir;
                                         }
@@ -483,6 +674,7 @@ impl UIList for SaveListCloud {
                                     "zip {} to {} failed: {:?}",
                                     game_save_dir, backup_name, err
                                 );
+                                entry.record::<(), _>(&Err(err));
                                 Toast::show(format!("云备份失败"));
                             }
                         }
@@ -498,7 +690,7 @@ impl UIList for SaveListCloud {
                         }
                         Loading::hide();
                         pending.store(false, Ordering::Relaxed);
-                    });
+                    }));
                 } else {
                     Toast::show("备份取消！".to_string());
                 }
@@ -517,17 +709,14 @@ impl UIList for SaveListCloud {
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
+        tokio::spawn(Operation::begin().run(async move {
             Loading::notify_title("正在删除云备份".to_string());
             Loading::notify_desc(backup_name.split("/").last().unwrap_or("").to_string());
-            match Api::start_file_manager(
-                &utf8_percent_encode(&backup_name, NON_ALPHANUMERIC).to_string(),
-                None,
-                None,
-                crate::api::ApiOperates::Delete,
-            ) {
+            let res = move_to_cloud_trash(&backup_name);
+            JournalEntry::new(JournalOp::Delete, &title_id, &backup_name, "").record(&res);
+            match res {
                 Ok(_) => {
-                    let (game_save_dir, res) = Api::fetch_save_cloud_list(&title_id, false);
+                    let (game_save_dir, res) = fetch_save_cloud_list(&title_id, false);
                     if let Some(game_save_dir) = game_save_dir {
                         *dir.write().expect("write save dir") = game_save_dir;
                     }
@@ -541,15 +730,41 @@ impl UIList for SaveListCloud {
             }
             Loading::hide();
             pending.store(false, Ordering::Relaxed);
-        });
+        }));
     }
 
-    fn update(&mut self, game_save_dir: &Option<String>, buttons: u32) {
+    fn update(&mut self, game_save_dir: &Option<SaveLocation>, buttons: u32) {
         self.scroll_progress.update(buttons);
+        self.sync_account();
+        let online = is_online();
+        if online != std::mem::replace(&mut self.online, online) && online {
+            self.init();
+        }
+        // the backup is downloaded already, restore it even if offline
+        self.restore_downloaded_backup();
+        if !online {
+            // browse the list only
+            if [
+                SceCtrlButtons::SceCtrlCircle,
+                SceCtrlButtons::SceCtrlTriangle,
+                SceCtrlButtons::SceCtrlSelect,
+                SceCtrlButtons::SceCtrlSquare,
+            ]
+            .into_iter()
+            .any(|button| is_button(buttons, button))
+            {
+                Toast::show(OFFLINE_TEXT.to_string());
+            }
+            self.list_state
+                .update((self.get_size() + 1) as i32, buttons);
+            return;
+        }
         // do backup
         let selected_idx = self.list_state.selected_idx;
         let idx = selected_idx - 1;
-        if is_button(buttons, SceCtrlButtons::SceCtrlCircle) {
+        if is_button(buttons, SceCtrlButtons::SceCtrlStart) {
+            UIAccounts::present_and_do(&self.pending);
+        } else if is_button(buttons, SceCtrlButtons::SceCtrlCircle) {
             if selected_idx == 0 {
                 if self.is_list_ready() {
                     if Api::is_eat_pancake_valid() {
@@ -573,6 +788,10 @@ impl UIList for SaveListCloud {
                     UIDialog::present_qrcode(HOME_PAGE_URL);
                 }
             }
+        } else if selected_idx == 0 && is_button(buttons, SceCtrlButtons::SceCtrlTriangle) {
+            if self.is_list_ready() {
+                self.refresh_save_list();
+            }
         } else if idx >= 0 {
             if is_button(buttons, SceCtrlButtons::SceCtrlTriangle) {
                 if Api::is_eat_pancake_valid() {
@@ -585,13 +804,19 @@ impl UIList for SaveListCloud {
                 }
             } else if is_button(buttons, SceCtrlButtons::SceCtrlSelect) {
                 if Api::is_eat_pancake_valid() {
-                    self.download_cloud_backup(game_save_dir, false);
+                    self.download_cloud_backup(
+                        &restore_dir(game_save_dir, &self.new_game_save_dir),
+                        false,
+                    );
                 } else {
                     UIDialog::present_qrcode(HOME_PAGE_URL);
                 }
             } else if is_button(buttons, SceCtrlButtons::SceCtrlSquare) {
                 if Api::is_eat_pancake_valid() {
-                    self.download_cloud_backup(game_save_dir, true);
+                    self.download_cloud_backup(
+                        &restore_dir(game_save_dir, &self.new_game_save_dir),
+                        true,
+                    );
                 } else {
                     UIDialog::present_qrcode(HOME_PAGE_URL);
                 }
@@ -626,6 +851,14 @@ impl UIList for SaveListCloud {
                 1.0,
                 SCAN_QR_CODE_TIPS,
             )
+        } else if !self.online && !self.is_list_ready() {
+            vita2d_draw_text(
+                left + (SCREEN_WIDTH / 2 - vita2d_text_width(1.0, OFFLINE_TEXT)) / 2,
+                SCREEN_HEIGHT / 2,
+                rgba(0xff, 0xff, 0xff, 0xff),
+                1.0,
+                OFFLINE_TEXT,
+            );
         } else if !self.is_list_ready() {
             draw_loading((left + 12) as f32, (SCREEN_HEIGHT - 104) as f32, 15.0);
         }
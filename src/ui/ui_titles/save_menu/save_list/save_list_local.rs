@@ -1,5 +1,4 @@
 use std::{
-    fs,
     path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -10,17 +9,24 @@ use std::{
 use log::error;
 
 use crate::{
+    accounts::title_cloud_dir,
     api::Api,
-    constant::{GAME_SAVE_CLOUD_DIR, HOME_PAGE_URL, LIST_NAME_WIDTH, SCREEN_WIDTH},
+    cloud::{fetch_save_cloud_list, upload_to_cloud},
+    constant::{HOME_PAGE_URL, LIST_NAME_WIDTH, SCREEN_WIDTH},
     ime::{get_current_format_time, show_keyboard},
-    tai::{mount_pfs, Title},
+    journal::{JournalEntry, JournalOp},
+    log::Operation,
+    save_location::SaveLocation,
+    settings::Settings,
+    tai::Title,
+    trash::move_to_trash,
     ui::{
         ui_cloud::list_state::ListState, ui_dialog::UIDialog, ui_list::UIList, ui_loading::Loading,
         ui_scroll_progress::ScrollProgress, ui_toast::Toast,
     },
     utils::{
-        backup_game_save, get_active_color, get_game_local_backup_dir, get_local_game_saves,
-        normalize_path, restore_game_save,
+        backup_game_save_with, get_active_color, get_game_local_backup_dir, get_local_game_saves,
+        get_new_game_save_dir, normalize_path, restore_game_save_with,
     },
     vita2d::{
         is_button, rgba, vita2d_draw_rect, vita2d_draw_text, vita2d_set_clip, vita2d_text_width,
@@ -28,17 +34,23 @@ use crate::{
     },
 };
 
-use super::DISPLAY_ROW;
+use super::{
+    confirm_rewrite_account_id, input_encrypt_passphrase, prepare_restore_backup,
+    remove_decrypted_backup, restore_dir, select_restore_entries, DISPLAY_ROW,
+};
 
 pub struct SaveListLocal {
     pending: Arc<AtomicBool>,
     list_state: ListState,
     local_dir: String,
-    cloud_dir: Arc<RwLock<String>>,
     title_id: String,
+    // name in cloud dir of title
+    title_name: String,
     items: Arc<RwLock<Vec<String>>>,
     new_backup_text: &'static str,
     scroll_progress: ScrollProgress,
+    // created by restore if the game has not created save yet
+    new_game_save_dir: Option<SaveLocation>,
 }
 
 impl SaveListLocal {
@@ -47,17 +59,9 @@ impl SaveListLocal {
             list_state: ListState::new(DISPLAY_ROW),
             pending: Arc::new(AtomicBool::new(false)),
             local_dir: get_game_local_backup_dir(&title.title_id(), &title.name()),
-            cloud_dir: Arc::new(RwLock::new(
-                format!(
-                    "{}/{} {}",
-                    GAME_SAVE_CLOUD_DIR,
-                    title.title_id(),
-                    normalize_path(title.name().trim())
-                )
-                .trim()
-                .to_string(),
-            )),
             title_id: title.title_id().to_string(),
+            title_name: normalize_path(title.name().trim()),
+            new_game_save_dir: get_new_game_save_dir(title),
             items: Arc::new(RwLock::new(vec![])),
             new_backup_text: new_back,
             scroll_progress: ScrollProgress::new(40.0, 100.0),
@@ -68,15 +72,14 @@ impl SaveListLocal {
         self.items.read().expect("read game saves")
     }
 
+    /// local backup dir of title
     fn local_dir(&self) -> String {
         self.local_dir.to_string()
     }
 
+    /// cloud dir of title in active account
     fn cloud_dir(&self) -> String {
-        self.cloud_dir
-            .read()
-            .expect("read cloud save dir")
-            .to_string()
+        title_cloud_dir(&self.title_id, &self.title_name)
     }
 
     fn upload_backup(&self) {
@@ -91,10 +94,10 @@ impl SaveListLocal {
                 let pending = Arc::clone(&self.pending);
                 pending.store(true, Ordering::Relaxed);
                 Loading::show();
-                tokio::spawn(async move {
+                tokio::spawn(Operation::begin().run(async move {
                     Loading::notify_title("正在上传存档".to_string());
                     Loading::notify_desc(backup_name.clone());
-                    let (game_save_dir, list) = Api::fetch_save_cloud_list(&title_id, false);
+                    let (game_save_dir, list) = fetch_save_cloud_list(&title_id, false);
                     if !(list.is_some()
                         && list
                             .unwrap()
@@ -107,12 +110,21 @@ impl SaveListLocal {
                         } else {
                             cloud_dir
                         };
-                        match Api::upload_to_cloud(
+                        let res = upload_to_cloud(
                             &game_save_dir,
                             &backup_name,
                             &local_backup_path,
                             false,
-                        ) {
+                        );
+                        JournalEntry::new(
+                            JournalOp::Upload,
+                            &title_id,
+                            &local_backup_path,
+                            &format!("{}/{}", game_save_dir, backup_name),
+                        )
+                        .size_of(&local_backup_path)
+                        .record(&res);
+                        match res {
                             Ok(_) => {
                                 Toast::show("备份上传完成！".to_string());
                             }
@@ -126,7 +138,7 @@ impl SaveListLocal {
                     }
                     Loading::hide();
                     pending.store(false, Ordering::Relaxed);
-                });
+                }));
             }
         }
     }
@@ -148,20 +160,44 @@ impl UIList for SaveListLocal {
         self.pending.load(Ordering::Relaxed)
     }
 
-    fn do_restore_game_save(&self, game_save_dir: &Option<String>, backup_name: &str) {
+    fn do_restore_game_save(
+        &self,
+        game_save_dir: &Option<SaveLocation>,
+        backup_name: &str,
+        entries: Option<Vec<String>>,
+        rewrite_account_id: bool,
+    ) {
         match &game_save_dir {
             Some(game_save_dir) => {
-                let game_save_dir = game_save_dir.to_string();
+                let game_save_dir = game_save_dir.clone();
                 let backup_name = format!("{}/{}", self.local_dir, backup_name);
                 let local_dir = self.local_dir();
                 let items = Arc::clone(&self.items);
+                let title_id = self.title_id.to_string();
+                let filter = Settings::backup_filter_of(&self.title_id);
                 let pending = Arc::clone(&self.pending);
                 pending.store(true, Ordering::Relaxed);
                 Loading::show();
-                mount_pfs(&game_save_dir);
-                tokio::spawn(async move {
-                    Loading::notify_title("正在恢复存档".to_string());
-                    match restore_game_save(&backup_name, &game_save_dir) {
+                game_save_dir.mount();
+                tokio::spawn(Operation::begin().run(async move {
+                    let (auto_backup, res) = restore_game_save_with(
+                        &backup_name,
+                        &game_save_dir,
+                        Settings::compression_of(&title_id),
+                        &filter,
+                        entries.as_deref(),
+                        rewrite_account_id,
+                    );
+                    JournalEntry::new(
+                        JournalOp::Restore,
+                        &title_id,
+                        &backup_name,
+                        &game_save_dir.to_string(),
+                    )
+                    .size_of(&backup_name)
+                    .auto_backup(auto_backup)
+                    .record(&res);
+                    match res {
                         Ok(_) => {
                             get_local_game_saves(local_dir, items);
                             Toast::show("存档恢复完成！".to_string());
@@ -174,9 +210,10 @@ impl UIList for SaveListLocal {
                             Toast::show(format!("存档恢复失败：{}", err));
                         }
                     }
+                    remove_decrypted_backup(&backup_name);
                     Loading::hide();
                     pending.store(false, Ordering::Relaxed);
-                });
+                }));
             }
             None => {
                 Toast::show("没有找到游戏存档，请先运行游戏！".to_string());
@@ -184,10 +221,10 @@ impl UIList for SaveListLocal {
         }
     }
 
-    fn do_backup_game_save(&self, game_save_dir: &Option<String>, input: Option<String>) {
+    fn do_backup_game_save(&self, game_save_dir: &Option<SaveLocation>, input: Option<String>) {
         match &game_save_dir {
             Some(game_save_dir) => {
-                let game_save_dir = game_save_dir.to_string();
+                let game_save_dir = game_save_dir.clone();
                 let backup_name = match &input {
                     Some(input) => format!("{}/{}", self.local_dir, input),
                     None => {
@@ -199,17 +236,40 @@ impl UIList for SaveListLocal {
                         }
                     }
                 };
-                if backup_name.len() > 0 {
+                let passphrase = if backup_name.len() > 0 {
+                    input_encrypt_passphrase()
+                } else {
+                    None
+                };
+                if let Some(passphrase) = passphrase {
                     let is_overwrite = input.is_some();
                     let local_dir = self.local_dir();
                     let items = Arc::clone(&self.items);
+                    let title_id = self.title_id.to_string();
+                    let compression = Settings::compression_of(&self.title_id);
+                    let filter = Settings::backup_filter_of(&self.title_id);
                     let pending = Arc::clone(&self.pending);
                     pending.store(true, Ordering::Relaxed);
                     Loading::show();
-                    mount_pfs(&game_save_dir);
-                    tokio::spawn(async move {
+                    game_save_dir.mount();
+                    tokio::spawn(Operation::begin().run(async move {
                         Loading::notify_title("正在备份".to_string());
-                        match backup_game_save(&game_save_dir, &backup_name) {
+                        let res = backup_game_save_with(
+                            &game_save_dir,
+                            &backup_name,
+                            compression,
+                            &filter,
+                            passphrase.as_deref(),
+                        );
+                        JournalEntry::new(
+                            JournalOp::Backup,
+                            &title_id,
+                            &game_save_dir.to_string(),
+                            &backup_name,
+                        )
+                        .size_of(&backup_name)
+                        .record(&res);
+                        match res {
                             Ok(_) => {
                                 // update save list
                                 get_local_game_saves(local_dir, items);
@@ -229,7 +289,7 @@ impl UIList for SaveListLocal {
                         }
                         Loading::hide();
                         pending.store(false, Ordering::Relaxed);
-                    });
+                    }));
                 } else {
                     Toast::show("备份取消！".to_string());
                 }
@@ -244,11 +304,16 @@ impl UIList for SaveListLocal {
         let backup_name = format!("{}/{}", self.local_dir, backup_name);
         let local_dir = self.local_dir();
         let items = Arc::clone(&self.items);
+        let title_id = self.title_id.to_string();
         let pending = Arc::clone(&self.pending);
         pending.store(true, Ordering::Relaxed);
         Loading::show();
-        tokio::spawn(async move {
-            match fs::remove_file(&backup_name) {
+        tokio::spawn(Operation::begin().run(async move {
+            let entry = JournalEntry::new(JournalOp::Delete, &title_id, &backup_name, "")
+                .size_of(&backup_name);
+            let res = move_to_trash(&backup_name);
+            entry.record(&res);
+            match res {
                 Ok(_) => {
                     get_local_game_saves(local_dir, items);
                     Toast::show("删除完成！".to_string());
@@ -260,10 +325,10 @@ impl UIList for SaveListLocal {
             }
             Loading::hide();
             pending.store(false, Ordering::Relaxed);
-        });
+        }));
     }
 
-    fn update(&mut self, game_save_dir: &Option<String>, buttons: u32) {
+    fn update(&mut self, game_save_dir: &Option<SaveLocation>, buttons: u32) {
         self.scroll_progress.update(buttons);
         // do backup
         let selected_idx = self.list_state.selected_idx;
@@ -286,8 +351,29 @@ impl UIList for SaveListLocal {
         } else if idx >= 0 {
             if is_button(buttons, SceCtrlButtons::SceCtrlSquare) {
                 let backup_name = &self.get_items().get(idx as usize).unwrap().to_owned();
-                if UIDialog::present(&format!("使用备份还原游戏：{}？", backup_name)) {
-                    self.do_restore_game_save(game_save_dir, backup_name);
+                let restore_dir = restore_dir(game_save_dir, &self.new_game_save_dir);
+                if restore_dir.is_none() {
+                    Toast::show("没有找到游戏存档，请先运行游戏！".to_string());
+                } else if let Some(backup_path) =
+                    prepare_restore_backup(&format!("{}/{}", self.local_dir, backup_name))
+                {
+                    if let Some(entries) = select_restore_entries(backup_name, &backup_path) {
+                        let rewrite_account_id =
+                            confirm_rewrite_account_id(backup_name, &backup_path);
+                        // restore from the decrypted backup if it is encrypted
+                        let restore_name = Path::new(&backup_path)
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .unwrap_or(backup_name);
+                        self.do_restore_game_save(
+                            &restore_dir,
+                            restore_name,
+                            entries,
+                            rewrite_account_id,
+                        );
+                    } else {
+                        remove_decrypted_backup(&backup_path);
+                    }
                 }
             } else if is_button(buttons, SceCtrlButtons::SceCtrlTriangle) {
                 let backup_name = &self.get_items().get(idx as usize).unwrap().to_owned();
@@ -0,0 +1,90 @@
+use log::error;
+
+use crate::{
+    constant::TRASH_BOTTOM_BAR_TEXT,
+    trash::{list_trash, purge_local_trash, TrashItem},
+    utils::{current_time, format_size},
+    vita2d::{is_button, rgba, SceCtrlButtons},
+};
+
+use super::{
+    ui_cloud::list_state::ListState,
+    ui_dialog::UIDialog,
+    ui_modal::{UIModal, DISPLAY_ROW},
+};
+
+pub enum TrashAction {
+    Restore,
+    Delete,
+}
+
+fn item_text(item: &TrashItem, now: u64) -> String {
+    let days = now.saturating_sub(item.deleted_at) / (24 * 60 * 60);
+    let mut text = format!(
+        "{}  {}{}",
+        if days == 0 {
+            "今天".to_string()
+        } else {
+            format!("{} 天前", days)
+        },
+        match (&item.account, item.cloud) {
+            (Some(account), true) => format!("[云端 {}] ", account),
+            (None, true) => "[云端] ".to_string(),
+            _ => String::new(),
+        },
+        item.path
+    );
+    if item.size > 0 {
+        text.push_str(&format!("  ({})", format_size(item.size)));
+    }
+    text
+}
+
+pub struct UITrash;
+
+impl UITrash {
+    fn draw(modal: &UIModal, items: &[TrashItem], list_state: &ListState) {
+        let now = (current_time() / 1000) as u64;
+        let title = format!("回收站  ({})", items.len());
+        modal.draw_list(&title, list_state, items.len(), |i| {
+            (item_text(&items[i], now), rgba(0xff, 0xff, 0xff, 0xff))
+        });
+    }
+
+    /// # present trash
+    ///
+    /// return the confirmed action and item, the latest item first
+    pub fn present() -> Option<(TrashAction, TrashItem)> {
+        if let Err(err) = purge_local_trash() {
+            error!("purge trash failed: {:?}", err);
+        }
+        let items = list_trash();
+        let mut list_state = ListState::new(DISPLAY_ROW);
+        let mut modal = UIModal::new(TRASH_BOTTOM_BAR_TEXT);
+        let mut action = None;
+        loop {
+            let buttons = modal.buttons();
+            let selected = items.get(list_state.selected_idx as usize);
+            if is_button(buttons, SceCtrlButtons::SceCtrlCross) {
+                break;
+            } else if let Some(item) = selected {
+                if is_button(buttons, SceCtrlButtons::SceCtrlCircle)
+                    && UIDialog::present(&format!("恢复 {}？", item.path))
+                {
+                    action = Some((TrashAction::Restore, item.clone()));
+                    break;
+                } else if is_button(buttons, SceCtrlButtons::SceCtrlTriangle)
+                    && UIDialog::present(&format!("永久删除 {}？", item.path))
+                {
+                    action = Some((TrashAction::Delete, item.clone()));
+                    break;
+                }
+            }
+            list_state.update(items.len() as i32, buttons);
+
+            UITrash::draw(&modal, &items, &list_state);
+        }
+        modal.close(|modal| UITrash::draw(modal, &items, &list_state));
+        action
+    }
+}
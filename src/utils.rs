@@ -3,20 +3,31 @@ use std::{
     ffi::OsStr,
     fs,
     io::{self, Read, Write},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use base64::{engine::general_purpose, Engine as _};
-use log::error;
+use log::{error, info};
 
-use zip::ZipWriter;
+use zip::{write::FileOptions, ZipWriter};
 
 use crate::{
-    constant::{BACKUP_BLACK_LIST, GAME_SAVE_LOCAL_DIR, SAVE_CLOUD_DIR},
+    constant::{
+        DOWNLOAD_BUF_SIZE, GAME_CARD_SAVE_DIR, GAME_SAVE_DIR, GAME_SAVE_LOCAL_DIR, SAVE_CLOUD_DIR,
+        SAVE_SFO_PATHS,
+    },
+    crypto::is_encrypted_file,
+    glob::PathFilter,
     ime::get_current_format_time,
-    tai::{change_psv_account_id, get_psv_account_id},
+    profile::{from_entry_name, get_profile, to_entry_name, Profile},
+    save_location::SaveLocation,
+    settings::{Compression, Settings},
+    sfo::Sfo,
+    space::{check_space, check_staged_space, zip_uncompressed_size},
+    staging::{StagedBackup, StagedExtract},
+    tai::{get_psv_account_id, Title, TitleKind},
     ui::ui_loading::Loading,
     vita2d::rgba,
 };
@@ -29,6 +40,34 @@ pub fn current_time() -> u128 {
         .as_millis()
 }
 
+/// human readable size, such as `1.5 MB`
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// human readable duration, such as `3 天 4 小时`
+pub fn format_duration(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    if days > 0 {
+        format!("{} 天 {} 小时", days, hours)
+    } else if hours > 0 {
+        format!("{} 小时 {} 分钟", hours, minutes)
+    } else {
+        format!("{} 分钟", minutes)
+    }
+}
+
 pub fn normalize_path(path: &str) -> String {
     let invalid_chars = ['\\', '/', ':', '*', '?', '"', '\'', '<', '>', '|'];
     let mut path = path.to_string();
@@ -97,25 +136,45 @@ pub fn get_local_game_saves(local_dir: String, items: Arc<RwLock<Vec<String>>>)
     *items.write().expect("write game saves") = list;
 }
 
+/// # options of zip entry
+///
+/// the entry keeps modified time of file instead of now,
+/// so backups of unchanged files are the same zip
+#[allow(deprecated)]
+fn entry_options(options: FileOptions, path: &Path) -> FileOptions {
+    let time = fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| zip::DateTime::from_time(modified.into()).ok())
+        .unwrap_or_default();
+    options.last_modified_time(time)
+}
+
 pub fn zip_dir_with(
     zip: &mut ZipWriter<fs::File>,
     input_path: &Path,
     prefix: &str,
-    back_list: &[&str],
+    filter: &PathFilter,
+    options: FileOptions,
 ) -> Result<(), Box<dyn Error>> {
-    let options =
-        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
     let mut buffer = vec![0; 1024 * 512];
-    for entry in input_path.read_dir()? {
+    // same order for the same files
+    let mut entries = input_path.read_dir()?.collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.as_ref().ok().map(|entry| entry.file_name()));
+    for entry in entries {
         if let Ok(entry) = entry {
             let path = entry.path();
             let name = path.strip_prefix(Path::new(prefix)).unwrap();
-            if let Some(_) = back_list.iter().find(|&&x| x == name.to_str().unwrap()) {
+            let name_str = name.to_str().unwrap();
+            if (path.is_file() && filter.is_excluded(name_str))
+                || (path.is_dir() && filter.is_dir_excluded(name_str))
+            {
                 continue;
             }
             Loading::notify_desc(entry.file_name().to_string_lossy().to_string());
             // Write file or directory explicitly
             // Some unzip tools unzip files with directory paths correctly, some do not!
+            let options = entry_options(options, &path);
             if path.is_file() {
                 #[allow(deprecated)]
                 zip.start_file_from_path(name, options)?;
@@ -128,11 +187,15 @@ pub fn zip_dir_with(
                     zip.write_all(&buffer[0..size])?;
                 }
             } else if !name.as_os_str().is_empty() {
+                // dirs without included files are left out in include-only mode
+                if filter.is_include_only() && !has_included_file(&path, prefix, filter)? {
+                    continue;
+                }
                 // Only if not root! Avoids path spec / warning
                 // and mapname conversion failed error on unzip
                 #[allow(deprecated)]
                 zip.add_directory_from_path(name, options)?;
-                zip_dir_with(zip, path.as_path(), prefix, back_list)?;
+                zip_dir_with(zip, path.as_path(), prefix, filter, options)?;
             }
         }
     }
@@ -140,7 +203,35 @@ pub fn zip_dir_with(
     Ok(())
 }
 
-pub fn zip_dir(from: &str, to: &str, back_list: &[&str]) -> Result<(), Box<dyn Error>> {
+/// is any file in `dir` not excluded by `filter`
+fn has_included_file(dir: &Path, prefix: &str, filter: &PathFilter) -> io::Result<bool> {
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        let name = match path
+            .strip_prefix(prefix)
+            .ok()
+            .and_then(|name| name.to_str())
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        if path.is_dir() {
+            if !filter.is_dir_excluded(name) && has_included_file(&path, prefix, filter)? {
+                return Ok(true);
+            }
+        } else if !filter.is_excluded(name) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+pub fn zip_dir(
+    from: &str,
+    to: &str,
+    filter: &PathFilter,
+    compression: Compression,
+) -> Result<(), Box<dyn Error>> {
     let from = if from.ends_with("/") {
         from.to_string()
     } else {
@@ -151,16 +242,99 @@ pub fn zip_dir(from: &str, to: &str, back_list: &[&str]) -> Result<(), Box<dyn E
         fs::create_dir_all(output_path.parent().unwrap())?;
     }
     let mut zip = zip::ZipWriter::new(fs::File::create(output_path)?);
-    zip_dir_with(&mut zip, Path::new(&from), &from, back_list)?;
+    zip_dir_with(
+        &mut zip,
+        Path::new(&from),
+        &from,
+        filter,
+        compression.zip_options(),
+    )?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// # zip dirs
+///
+/// zip dirs of `from` into one zip, the entries start with the dir name
+pub fn zip_dirs(
+    from: &str,
+    names: &[String],
+    to: &str,
+    filter: &PathFilter,
+    compression: Compression,
+) -> Result<(), Box<dyn Error>> {
+    if names.is_empty() {
+        return Err("no dir to zip".into());
+    }
+    let from = if from.ends_with("/") {
+        from.to_string()
+    } else {
+        format!("{}/", from)
+    };
+    create_parent_if_not_exists(to)?;
+    let options = compression.zip_options();
+    let mut zip = zip::ZipWriter::new(fs::File::create(to)?);
+    for name in names {
+        let dir_options = entry_options(options, &Path::new(&from).join(name));
+        #[allow(deprecated)]
+        zip.add_directory_from_path(Path::new(name), dir_options)?;
+        zip_dir_with(
+            &mut zip,
+            Path::new(&from).join(name).as_path(),
+            &from,
+            filter,
+            options,
+        )?;
+    }
     zip.finish()?;
     Ok(())
 }
 
-pub fn zip_file(from: &str, name: &str, to: &str) -> Result<(), Box<dyn Error>> {
+/// # zip files of backup profile
+///
+/// entries are named by `to_entry_name`
+pub fn zip_profile(
+    profile: &Profile,
+    to: &str,
+    compression: Compression,
+) -> Result<(), Box<dyn Error>> {
+    let files = profile.files()?;
+    if files.is_empty() {
+        return Err(format!("no file of profile {}", profile.title_id()).into());
+    }
+    create_parent_if_not_exists(to)?;
+    let options = compression.zip_options();
+    let mut zip = zip::ZipWriter::new(fs::File::create(to)?);
+    let mut buffer = vec![0; 1024 * 512];
+    for file in files {
+        let name = match to_entry_name(&file) {
+            Some(name) => name,
+            None => continue,
+        };
+        Loading::notify_desc(file.clone());
+        zip.start_file(name, entry_options(options, Path::new(&file)))?;
+        let mut input_file = fs::File::open(&file)?;
+        loop {
+            let size = input_file.read(&mut buffer)?;
+            if size == 0 {
+                break;
+            }
+            zip.write_all(&buffer[0..size])?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+pub fn zip_file(
+    from: &str,
+    name: &str,
+    to: &str,
+    compression: Compression,
+) -> Result<(), Box<dyn Error>> {
     let from_path = Path::new(from).join(name);
     let mut zip = zip::ZipWriter::new(fs::File::create(to)?);
-    let options =
-        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let options = compression.zip_options();
     let mut buffer = vec![0; 1024 * 512];
     #[allow(deprecated)]
     zip.start_file_from_path(Path::new(name), options)?;
@@ -179,36 +353,90 @@ pub fn zip_file(from: &str, name: &str, to: &str) -> Result<(), Box<dyn Error>>
 pub fn zip_extract(
     from: impl AsRef<Path>,
     to: impl AsRef<Path>,
-    back_list: Option<&[&str]>,
+    filter: Option<&PathFilter>,
+) -> Result<(), Box<dyn Error>> {
+    zip_extract_with(from, to, filter, None)
+}
+
+/// # extract zip entries
+///
+/// only extract file entries in `entries` if it is some,
+/// files excluded by `filter` are not overwritten
+pub fn zip_extract_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    filter: Option<&PathFilter>,
+    entries: Option<&[String]>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(to) = to.as_ref().to_str() {
+        check_space(to, zip_uncompressed_size(&from)?)?;
+    }
+    zip_extract_by(from, |name| Some(to.as_ref().join(name)), filter, entries)
+}
+
+/// # extract zip entries
+///
+/// output path of entry is returned by `to`, skip the entry if it is none
+fn zip_extract_by(
+    from: impl AsRef<Path>,
+    to: impl Fn(&Path) -> Option<PathBuf>,
+    filter: Option<&PathFilter>,
+    entries: Option<&[String]>,
 ) -> Result<(), Box<dyn Error>> {
     let mut zip = zip::ZipArchive::new(fs::File::open(from)?)?;
+    // extract to staging dir, move files into place after all extracted
+    // staged on the device of the first target
+    let first_target = zip.file_names().find_map(|name| to(Path::new(name)));
+    let mut staged = StagedExtract::begin(
+        first_target
+            .as_ref()
+            .and_then(|path| path.to_str())
+            .unwrap_or(""),
+    )?;
+    let res = zip_extract_entries(&mut zip, &to, filter, entries, &mut staged);
+    staged.finish(res)
+}
+
+fn zip_extract_entries(
+    zip: &mut zip::ZipArchive<fs::File>,
+    to: impl Fn(&Path) -> Option<PathBuf>,
+    filter: Option<&PathFilter>,
+    entries: Option<&[String]>,
+    staged: &mut StagedExtract,
+) -> Result<(), Box<dyn Error>> {
     for i in 0..zip.len() {
         Loading::notify_title(format!("正在解压 {}/{}", i + 1, zip.len()));
         let mut file_name = zip.by_index(i)?;
-        let output_path = match file_name.enclosed_name() {
-            Some(file_name) => {
-                Loading::notify_desc(file_name.to_string_lossy().to_string());
-                to.as_ref().join(file_name).to_owned()
+        let output_path = match file_name.enclosed_name().and_then(&to) {
+            Some(output_path) => {
+                Loading::notify_desc(file_name.name().to_string());
+                output_path
             }
             None => continue,
         };
 
         if (*file_name.name()).ends_with('/') {
+            if entries.is_some() {
+                continue;
+            }
             if !output_path.exists() {
                 fs::create_dir_all(&output_path)?;
             }
         } else {
+            if entries.is_some_and(|list| list.iter().find(|&x| x == file_name.name()).is_none()) {
+                continue;
+            }
             if let Some(p) = output_path.parent() {
                 if !p.exists() {
                     fs::create_dir_all(p)?;
                 }
             }
-            if back_list.is_some_and(|list| list.iter().find(|&&x| x == file_name.name()).is_some())
+            if filter.is_some_and(|filter| filter.is_excluded(file_name.name()))
                 && output_path.exists()
             {
                 continue;
             }
-            let mut output_file = fs::File::create(&output_path)?;
+            let mut output_file = fs::File::create(staged.stage(&output_path))?;
             io::copy(&mut file_name, &mut output_file)?;
         }
     }
@@ -216,6 +444,48 @@ pub fn zip_extract(
     Ok(())
 }
 
+/// # get file entries of zip
+pub fn get_zip_file_entries(from: impl AsRef<Path>) -> Result<Vec<String>, Box<dyn Error>> {
+    let zip = zip::ZipArchive::new(fs::File::open(from)?)?;
+    let mut list = zip
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .map(|name| name.to_string())
+        .collect::<Vec<String>>();
+    list.sort();
+    Ok(list)
+}
+
+/// # read file of zip
+///
+/// return `None` if the file not exists in zip
+pub fn get_zip_file_buf(
+    from: impl AsRef<Path>,
+    name: &str,
+) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(from)?)?;
+    let mut file = match zip.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut buf = vec![];
+    file.read_to_end(&mut buf)?;
+    Ok(Some(buf))
+}
+
+/// # get account id of backup
+///
+/// read from the `param.sfo` in backup, `None` if it has no account id
+pub fn get_backup_account_id(from: impl AsRef<Path>) -> Result<Option<u64>, Box<dyn Error>> {
+    for sfo_path in SAVE_SFO_PATHS {
+        if let Some(buf) = get_zip_file_buf(&from, sfo_path)? {
+            return Ok(Sfo::from_bytes(&buf)?.account_id());
+        }
+    }
+    Ok(None)
+}
+
 pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<u64> {
     fs::create_dir_all(&dst)?;
     for entry in fs::read_dir(src)? {
@@ -230,6 +500,70 @@ pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<
     Ok(0)
 }
 
+/// size of file, or total size of files in dir
+pub fn dir_size(path: impl AsRef<Path>) -> io::Result<u64> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        return Ok(fs::metadata(path)?.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += dir_size(entry?.path())?;
+    }
+    Ok(size)
+}
+
+/// rename, or copy and remove if they are on different devices
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if from.is_dir() {
+        copy_dir_all(from, to)?;
+    } else {
+        fs::copy(from, to)?;
+    }
+    remove_path(from)
+}
+
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// # write file atomically
+///
+/// write to `{path}.tmp` and rename it to `path`
+pub fn write_file_atomic(path: impl AsRef<Path>, data: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let temp = temp_path(path);
+    fs::write(&temp, data)?;
+    replace_file(&temp, path)
+}
+
+pub fn temp_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut temp = path.as_ref().as_os_str().to_owned();
+    temp.push(".tmp");
+    PathBuf::from(temp)
+}
+
+/// rename `from` to `to`, remove `to` first if it can not be overwritten
+pub fn replace_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    if fs::rename(&from, &to).is_ok() {
+        return Ok(());
+    }
+    if to.as_ref().exists() {
+        fs::remove_file(&to)?;
+    }
+    fs::rename(from, to)
+}
+
 pub fn join_path(base: &str, path: &str) -> String {
     if base == "" || base.ends_with("/") {
         format!("{}{}", base, path)
@@ -242,11 +576,23 @@ pub fn update_sfo_file_with_current_account_id(sfo_path: &str) -> Result<(), Box
     if Path::new(&sfo_path).exists() {
         let account_id = get_psv_account_id();
         if account_id > 0 {
-            if change_psv_account_id(&sfo_path, account_id) < 0 {
+            let mut sfo = Sfo::open(sfo_path)?;
+            let old_account_id = sfo.account_id();
+            if old_account_id == Some(account_id) {
+                return Ok(());
+            }
+            if !sfo.set_account_id(account_id) {
                 let msg = format!("change psv account id failed: {}", account_id);
                 error!("{}", msg);
                 return Err(msg.into());
             }
+            sfo.save(sfo_path)?;
+            info!(
+                "change account id of {}: {:016x} -> {:016x}",
+                sfo_path,
+                old_account_id.unwrap_or_default(),
+                account_id
+            );
         } else {
             error!("get psv account id failed");
             return Err("get psv account id failed".into());
@@ -256,29 +602,282 @@ pub fn update_sfo_file_with_current_account_id(sfo_path: &str) -> Result<(), Box
     Ok(())
 }
 
-pub fn backup_game_save(from: &str, to: &str) -> Result<(), Box<dyn Error>> {
-    zip_dir(from, to, &BACKUP_BLACK_LIST)
+/// # update account id of game save
+///
+/// apply to all `param.sfo` which has account id in game save dir
+pub fn update_game_save_account_id(game_save_dir: &str) -> Result<(), Box<dyn Error>> {
+    for sfo_path in SAVE_SFO_PATHS {
+        let sfo_path = join_path(game_save_dir, sfo_path);
+        if !Path::new(&sfo_path).exists() {
+            continue;
+        }
+        if Sfo::open(&sfo_path)?.account_id().is_some() {
+            update_sfo_file_with_current_account_id(&sfo_path)?;
+        }
+    }
+    Ok(())
 }
 
-pub fn restore_game_save(from: &str, to: &str) -> Result<(), Box<dyn Error>> {
-    if let Some(from_parent) = Path::new(from).parent() {
-        if let Some(auto_backup_path) = from_parent
-            .join(&format!("{} auto.zip", get_current_format_time()))
-            .to_str()
-        {
-            Loading::notify_title("正在自动备份".to_string());
-            let _ = backup_game_save(to, auto_backup_path);
+/// # game save location of title
+///
+/// `None` if not exists
+pub fn get_game_save_dir(title: &Title) -> Option<SaveLocation> {
+    match title.kind() {
+        TitleKind::Vita => [
+            format!("{}/{}", GAME_CARD_SAVE_DIR, title.real_id()),
+            format!("{}/{}", GAME_SAVE_DIR, title.real_id()),
+        ]
+        .into_iter()
+        .find(|dir| Path::new(dir).exists())
+        .map(SaveLocation::Vita),
+        TitleKind::Profile => get_profile(title.real_id())
+            .filter(|profile| profile.exists())
+            .map(|profile| SaveLocation::Profile(profile.title_id().to_string())),
+        TitleKind::Psp => {
+            let location = SaveLocation::psp(title.real_id());
+            match location.psp_dirs() {
+                Ok(dirs) if !dirs.is_empty() => Some(location),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// # game save dir to create on restore
+///
+/// for installed vita game which has not created save yet
+pub fn get_new_game_save_dir(title: &Title) -> Option<SaveLocation> {
+    if title.kind() != TitleKind::Vita || !title.installed() || get_game_save_dir(title).is_some() {
+        return None;
+    }
+    Some(SaveLocation::Vita(format!(
+        "{}/{}",
+        GAME_SAVE_DIR,
+        title.real_id()
+    )))
+}
+
+pub fn remove_game_save(location: &SaveLocation) -> io::Result<()> {
+    match location {
+        SaveLocation::Vita(dir) => fs::remove_dir_all(dir),
+        SaveLocation::Psp { parent, .. } => {
+            for dir in location.psp_dirs()? {
+                fs::remove_dir_all(Path::new(parent).join(dir))?;
+            }
+            Ok(())
         }
+        // data of profile may be shared with others, such as config of homebrew
+        SaveLocation::Profile(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "remove data of backup profile",
+        )),
     }
+}
+
+pub fn backup_game_save(
+    from: &SaveLocation,
+    to: &str,
+    compression: Compression,
+    filter: &PathFilter,
+) -> Result<(), Box<dyn Error>> {
+    backup_game_save_with(from, to, compression, filter, None)
+}
+
+/// # back up game save
+///
+/// encrypt the backup with `passphrase` if it is some
+pub fn backup_game_save_with(
+    from: &SaveLocation,
+    to: &str,
+    compression: Compression,
+    filter: &PathFilter,
+    passphrase: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    // the temp zip and its encrypted copy are written before `to` is replaced
+    let size = game_save_size(from)?;
+    check_staged_space(to, if passphrase.is_some() { size * 2 } else { size })?;
+    // write to temp zip, rename it after verified
+    let mut staged = StagedBackup::begin(to)?;
+    let temp = staged.temp().to_string();
+    let res = match from {
+        SaveLocation::Vita(dir) => zip_dir(dir, &temp, filter, compression),
+        SaveLocation::Psp { parent, .. } => from
+            .psp_dirs()
+            .map_err(|err| err.into())
+            .and_then(|dirs| zip_dirs(parent, &dirs, &temp, filter, compression)),
+        SaveLocation::Profile(_) => match from.profile() {
+            Some(profile) => zip_profile(&profile, &temp, compression),
+            None => Err(format!("profile of {} not found", from).into()),
+        },
+    };
+    let res = match passphrase {
+        Some(passphrase) => res.and_then(|_| {
+            Loading::notify_title("正在加密备份".to_string());
+            staged.encrypt(passphrase)
+        }),
+        None => res,
+    };
+    staged.finish(res)
+}
+
+/// # size of game save
+///
+/// uncompressed size of files to back up, filter is not applied
+fn game_save_size(from: &SaveLocation) -> Result<u64, Box<dyn Error>> {
+    let mut size = 0;
+    match from {
+        SaveLocation::Vita(dir) => size = dir_size(dir)?,
+        SaveLocation::Psp { parent, .. } => {
+            for dir in from.psp_dirs()? {
+                size += dir_size(Path::new(parent).join(dir))?;
+            }
+        }
+        SaveLocation::Profile(_) => {
+            for file in from
+                .profile()
+                .map(|profile| profile.files())
+                .transpose()?
+                .unwrap_or_default()
+            {
+                size += fs::metadata(file).map(|meta| meta.len()).unwrap_or(0);
+            }
+        }
+    }
+    Ok(size)
+}
+
+/// restore with global compression and filter, rewriting the account id
+pub fn restore_game_save(from: &str, to: &SaveLocation) -> Result<(), Box<dyn Error>> {
+    let compression = Settings::read().compression;
+    let filter = Settings::global_backup_filter();
+    restore_game_save_with(from, to, compression, &filter, None, true).1
+}
+
+/// # auto backup before restore
+///
+/// back up game save `to` beside the backup `from` with the compression of
+/// the title, return the path of auto backup if succeeded
+fn auto_backup_game_save(
+    from: &str,
+    to: &SaveLocation,
+    compression: Compression,
+    filter: &PathFilter,
+) -> Option<String> {
+    let auto_backup_path = Path::new(from)
+        .parent()?
+        .join(format!("{} auto.zip", get_current_format_time()))
+        .to_str()?
+        .to_string();
+    Loading::notify_title("正在自动备份".to_string());
+    match backup_game_save(to, &auto_backup_path, compression, filter) {
+        Ok(_) => Some(auto_backup_path),
+        Err(err) => {
+            error!("auto backup {} failed: {:?}", to, err);
+            None
+        }
+    }
+}
+
+/// # restore game save
+///
+/// the current game save is backed up beside `from` with `compression` first,
+/// only restore file entries in `entries` if it is some,
+/// files excluded by `filter` are kept,
+/// keep the account id of backup if `rewrite_account_id` is false,
+/// return the path of auto backup and the result
+pub fn restore_game_save_with(
+    from: &str,
+    to: &SaveLocation,
+    compression: Compression,
+    filter: &PathFilter,
+    entries: Option<&[String]>,
+    rewrite_account_id: bool,
+) -> (Option<String>, Result<(), Box<dyn Error>>) {
+    // encrypted backup should be decrypted before restore
+    match is_encrypted_file(from) {
+        Ok(false) => {}
+        Ok(true) => return (None, Err("backup is encrypted".into())),
+        Err(err) => return (None, Err(err.into())),
+    }
+    let auto_backup = auto_backup_game_save(from, to, compression, filter);
+    let res = extract_game_save(from, to, filter, entries, rewrite_account_id);
+    (auto_backup, res)
+}
+
+fn extract_game_save(
+    from: &str,
+    to: &SaveLocation,
+    filter: &PathFilter,
+    entries: Option<&[String]>,
+    rewrite_account_id: bool,
+) -> Result<(), Box<dyn Error>> {
     Loading::notify_title("正在恢复存档".to_string());
-    let mut res = zip_extract(from, to, Some(&BACKUP_BLACK_LIST));
-    if res.is_ok() {
-        let sfo_path = format!("{}/sce_sys/param.sfo", to);
-        res = update_sfo_file_with_current_account_id(&sfo_path);
+    let dir = match to {
+        SaveLocation::Vita(dir) => dir,
+        // backup of psp game save contains the save dirs
+        SaveLocation::Psp { parent, .. } => parent,
+        SaveLocation::Profile(_) => return restore_profile(from, to, entries),
+    };
+    // the game has not created save yet
+    if !Path::new(dir).exists() {
+        info!("create game save dir {}", dir);
+        fs::create_dir_all(dir)?;
+        // write files of vita game save through pfs, never write plain files
+        let ret = to.mount();
+        if ret < 0 {
+            if let Err(err) = fs::remove_dir_all(dir) {
+                error!("remove game save dir {} failed: {:?}", dir, err);
+            }
+            return Err(
+                format!("新建的存档目录无法挂载（{:#x}），请先运行游戏创建存档", ret).into(),
+            );
+        }
+    }
+    let mut res = zip_extract_with(from, dir, Some(filter), entries);
+    // psp save is not bound to account
+    if res.is_ok() && to.vita_dir().is_some() {
+        if rewrite_account_id {
+            res = update_game_save_account_id(dir);
+        } else {
+            info!("restore {} to {}: keep account id of backup", from, to);
+        }
     }
     res
 }
 
+/// restore files of profile to their paths
+fn restore_profile(
+    from: &str,
+    to: &SaveLocation,
+    entries: Option<&[String]>,
+) -> Result<(), Box<dyn Error>> {
+    let profile = match to.profile() {
+        Some(profile) => profile,
+        None => return Err(format!("profile of {} not found", to).into()),
+    };
+    // backup may be edited or of another profile, never write outside of paths
+    for name in get_zip_file_entries(from)? {
+        if !from_entry_name(&name).is_some_and(|file| profile.contains(&file)) {
+            return Err(format!("{} is not a file of profile {}", name, profile.title_id()).into());
+        }
+    }
+    if let Some(path) = profile.paths.first() {
+        check_space(path, zip_uncompressed_size(from)?)?;
+    }
+    zip_extract_by(
+        from,
+        |name| {
+            let file = from_entry_name(name.to_str()?)?;
+            if !profile.contains(&file) || profile.is_excluded(&file) {
+                return None;
+            }
+            Some(PathBuf::from(file))
+        },
+        None,
+        entries,
+    )
+}
+
 pub fn base64_encode(data: &[u8]) -> String {
     general_purpose::STANDARD.encode(data)
 }
@@ -291,6 +890,21 @@ pub fn get_str_md5(data: &[u8]) -> String {
     format!("{:x}", md5::compute(data))
 }
 
+/// md5 of file, read by buffers
+pub fn get_file_md5(path: &str) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0; DOWNLOAD_BUF_SIZE];
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        context.consume(&buf[..len]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
+
 pub fn delete_dir_if_empty(path: &str) -> Result<(), Box<dyn Error>> {
     let path = Path::new(path);
     if path.exists() && path.is_dir() && path.read_dir()?.next().is_none() {
@@ -347,11 +961,66 @@ pub fn create_parent_if_not_exists(path: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// # temp dir of test
+///
+/// unique for each call, removed with its files on drop
+#[cfg(test)]
+pub struct TestDir(PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "save-cloud-{}-{}-{}",
+            name,
+            current_time(),
+            NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).expect("create test dir");
+        TestDir(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{
+        fs,
+        time::{Duration, UNIX_EPOCH},
+    };
 
-    use crate::utils::{base64_decode, base64_encode};
+    use crate::{
+        glob::PathFilter,
+        save_location::SaveLocation,
+        settings::{BackupRules, Compression},
+        utils::{
+            backup_game_save, base64_decode, base64_encode, remove_game_save, zip_dir, zip_extract,
+            zip_extract_with, zip_file, TestDir,
+        },
+    };
 
     use super::ease_out_expo;
 
@@ -421,6 +1090,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_format_size() {
+        assert_eq!(super::format_size(0), "0 B");
+        assert_eq!(super::format_size(1023), "1023 B");
+        assert_eq!(super::format_size(1536), "1.5 KB");
+        assert_eq!(super::format_size(3 * 1024 * 1024), "3.0 MB");
+        assert_eq!(
+            super::format_size(5 * 1024 * 1024 * 1024 * 1024),
+            "5120.0 GB"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(super::format_duration(59), "0 分钟");
+        assert_eq!(super::format_duration(3 * 3600 + 120), "3 小时 2 分钟");
+        assert_eq!(super::format_duration(30 * 86400 + 7200), "30 天 2 小时");
+    }
+
     #[test]
     fn test_normalize_path() {
         let path = "你好\\你好/你好:你好*你好?你好\"你好\'你好<你好>你好|你好";
@@ -430,4 +1118,180 @@ mod tests {
             path
         );
     }
+
+    #[test]
+    pub fn test_zip_compression() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("zip");
+        let from = dir.join("save");
+        let data = "save data ".repeat(1024).into_bytes();
+        fs::create_dir_all(from.join("sce_sys"))?;
+        fs::write(from.join("sce_sys/param.sfo"), b"\0PSF")?;
+        fs::write(from.join("data.bin"), &data)?;
+
+        let mut sizes = vec![];
+        for compression in Compression::ALL {
+            // dir
+            let zip_path = dir.join(format!("{:?}.zip", compression));
+            let zip_path = zip_path.to_str().unwrap();
+            zip_dir(
+                from.to_str().unwrap(),
+                zip_path,
+                &PathFilter::default(),
+                compression,
+            )?;
+            let mut zip = zip::ZipArchive::new(fs::File::open(zip_path)?)?;
+            assert_eq!(
+                zip.by_name("data.bin")?.compression(),
+                if compression == Compression::Store {
+                    zip::CompressionMethod::Stored
+                } else {
+                    zip::CompressionMethod::Deflated
+                }
+            );
+            sizes.push(fs::metadata(zip_path)?.len());
+            let to = dir.join(format!("{:?}", compression));
+            zip_extract(zip_path, &to, None)?;
+            assert_eq!(fs::read(to.join("data.bin"))?, data);
+            assert_eq!(fs::read(to.join("sce_sys/param.sfo"))?, b"\0PSF");
+
+            // file
+            let zip_path = dir.join(format!("{:?}-file.zip", compression));
+            let zip_path = zip_path.to_str().unwrap();
+            zip_file(from.to_str().unwrap(), "data.bin", zip_path, compression)?;
+            let to = dir.join(format!("{:?}-file", compression));
+            zip_extract(zip_path, &to, None)?;
+            assert_eq!(fs::read(to.join("data.bin"))?, data);
+        }
+        assert!(sizes[0] > sizes[1] && sizes[1] >= sizes[3]);
+
+        // unchanged files make the same zip
+        let modified = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+        fs::File::options()
+            .write(true)
+            .open(from.join("data.bin"))?
+            .set_modified(modified)?;
+        let zips = ["same-1.zip", "same-2.zip"].map(|name| dir.join(name));
+        for zip_path in &zips {
+            zip_dir(
+                from.to_str().unwrap(),
+                zip_path.to_str().unwrap(),
+                &PathFilter::default(),
+                Compression::Deflate,
+            )?;
+        }
+        assert_eq!(fs::read(&zips[0])?, fs::read(&zips[1])?);
+        let mut zip = zip::ZipArchive::new(fs::File::open(&zips[0])?)?;
+        assert_eq!(zip.by_name("data.bin")?.last_modified().year(), 2020);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_zip_include_only() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("include");
+        let from = dir.join("save");
+        for name in ["sce_sys/param.sfo", "slots/slot1.bin", "cache/shader/a.bin"] {
+            fs::create_dir_all(from.join(name).parent().unwrap())?;
+            fs::write(from.join(name), name)?;
+        }
+        let rules = BackupRules {
+            includes: vec!["slots/**".to_string()],
+            ..BackupRules::default()
+        };
+        let zip_path = dir.join("backup.zip");
+        let zip_path = zip_path.to_str().unwrap();
+        zip_dir(
+            from.to_str().unwrap(),
+            zip_path,
+            &rules.filter(),
+            Compression::Store,
+        )?;
+        let zip = zip::ZipArchive::new(fs::File::open(zip_path)?)?;
+        let mut names = zip.file_names().collect::<Vec<&str>>();
+        names.sort();
+        assert_eq!(
+            names,
+            ["sce_sys/", "sce_sys/param.sfo", "slots/", "slots/slot1.bin"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_zip_extract_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("entries");
+        let from = dir.join("save");
+        fs::create_dir_all(from.join("sce_sys"))?;
+        fs::write(from.join("sce_sys/param.sfo"), b"\0PSF")?;
+        fs::write(from.join("data.bin"), b"data")?;
+        fs::write(from.join("slot1.bin"), b"slot1")?;
+        let zip_path = dir.join("save.zip");
+        zip_dir(
+            from.to_str().unwrap(),
+            zip_path.to_str().unwrap(),
+            &PathFilter::default(),
+            Compression::Store,
+        )?;
+
+        // included entries only
+        let to = dir.join("included");
+        let entries = ["data.bin".to_string(), "sce_sys/param.sfo".to_string()];
+        zip_extract_with(&zip_path, &to, None, Some(&entries))?;
+        assert_eq!(fs::read(to.join("data.bin"))?, b"data");
+        assert_eq!(fs::read(to.join("sce_sys/param.sfo"))?, b"\0PSF");
+        assert!(!to.join("slot1.bin").exists());
+
+        // excluded entries keep the existing files
+        let to = dir.join("excluded");
+        fs::create_dir_all(&to)?;
+        fs::write(to.join("slot1.bin"), b"old")?;
+        let filter = PathFilter::new(vec!["slot*.bin".to_string()], vec![]);
+        zip_extract_with(&zip_path, &to, Some(&filter), None)?;
+        assert_eq!(fs::read(to.join("slot1.bin"))?, b"old");
+        assert_eq!(fs::read(to.join("data.bin"))?, b"data");
+
+        // empty entries extract nothing
+        let to = dir.join("empty");
+        zip_extract_with(&zip_path, &to, None, Some(&[]))?;
+        assert!(!to.join("data.bin").exists());
+        assert!(!to.join("sce_sys").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_psp_game_save() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TestDir::new("psp");
+        for name in ["ULJM05800DATA00", "ULJM05800SYSTEM", "ULJM05801DATA00"] {
+            fs::create_dir_all(dir.join(name))?;
+            fs::write(dir.join(name).join("PARAM.SFO"), name)?;
+        }
+        let location = SaveLocation::Psp {
+            parent: dir.to_str().unwrap().to_string(),
+            game_id: "ULJM05800".to_string(),
+        };
+        assert_eq!(location.psp_dirs()?, ["ULJM05800DATA00", "ULJM05800SYSTEM"]);
+
+        let zip_path = dir.join("backup.zip");
+        let zip_path = zip_path.to_str().unwrap();
+        backup_game_save(
+            &location,
+            zip_path,
+            Compression::Deflate,
+            &PathFilter::default(),
+        )?;
+        let to = dir.join("restore");
+        zip_extract(zip_path, &to, None)?;
+        assert_eq!(
+            fs::read(to.join("ULJM05800SYSTEM/PARAM.SFO"))?,
+            b"ULJM05800SYSTEM"
+        );
+        assert!(!to.join("ULJM05801DATA00").exists());
+
+        remove_game_save(&location)?;
+        assert!(location.psp_dirs()?.is_empty());
+        assert!(dir.join("ULJM05801DATA00").exists());
+
+        Ok(())
+    }
 }